@@ -2,53 +2,192 @@
 #![no_main]
 
 use arduino_hal::prelude::*;
-use gain_lib::Slider;
+use gain_lib::{
+    decode_frame, encode_frame, Button, FaderPosition, Hello, HostMessage, LedState, Message,
+    PinConfig, PinSettings, Potentiometer, RawReading, Slider, MAX_FRAME_LEN, PIN_SETTINGS_LEN,
+    PROTOCOL_VERSION,
+};
 use panic_halt as _;
-use postcard::to_slice_cobs;
+#[cfg(feature = "level-strip")]
+use {
+    gain_lib::LevelState,
+    smart_leds::{brightness, SmartLedsWrite, RGB8},
+    ws2812_spi::prerendered::Ws2812,
+};
 
 // Config
-const PINS_TO_READ: [usize; 6] = [0, 1, 2, 3, 4, 5];
-const HYSTERESIS_THRESHOLD: i16 = 4;
+// Number of potentiometers. The pins they are wired to are listed in `main`, pin `i` being
+// the `i`th pin in that list.
+const N: usize = 6;
+// Pins that are read, and their hysteresis below, are only defaults: `gain set-pin` changes
+// them at runtime and they are kept in EEPROM from then on, which takes precedence on boot.
+const PINS_TO_READ: &[usize] = &[0, 1, 2, 3, 4, 5];
+// Slider ID sent for each pin, so the IDs in the host config don't have to follow the wiring.
+// Pin `i` is reported as `PIN_TO_ID[i]`, and the button and LED paired with it use that ID
+// too. IDs must be unique.
+const PIN_TO_ID: [u8; N] = [0, 1, 2, 3, 4, 5];
+// Per-pin hysteresis, in raw ADC steps (0-1023). A slider only reports a new value once its
+// smoothed reading moves more than this away from the last one it reported. Raise it for a
+// noisy pot to stop it flickering; the cost is that small movements of that slider are
+// swallowed until they add up past the threshold.
+const HYSTERESIS_THRESHOLDS: [i16; N] = [4, 4, 4, 4, 4, 4];
+// Time between reads of every pin. Each read feeds the EMA filter, which halves the weight of
+// older samples on every read, so a longer interval makes sliders both slower to report and
+// slower to settle. A shorter one reacts faster but sends more frames to the host.
+const SAMPLE_INTERVAL_MS: u32 = 25;
+// Time a slider must rest, i.e. read within its hysteresis, before its next movement seeds the
+// EMA filter again rather than being averaged with the resting position, so slow adjustments
+// after a rest aren't dragged back by it at first. A noise spike past the hysteresis right
+// after a rest is reported unfiltered too. Set to 0 to always average.
+const IDLE_RESET_MS: u32 = 2000;
+// Also send every raw reading with the filter's smoothed value, for `gain monitor` to show
+// which pot is noisy. Off by default since it sends a frame per pin on every sample.
+const DEBUG_RAW: bool = false;
+// Time between heartbeat frames, which tell the host the device is alive while no slider moves.
+const HEARTBEAT_INTERVAL_MS: u32 = 1000;
+// Buttons are read from digital pins d2-d7, wired to ground. Index `i` pairs with pin `i`.
+const BUTTONS_TO_READ: [usize; 6] = [0, 1, 2, 3, 4, 5];
+// How long a button must read its new state before the change is reported, so contact bounce
+// doesn't send a press (and toggle a mute) more than once
+const BUTTON_DEBOUNCE_MS: u32 = 50;
+// LEDs are driven on digital pins d8-d13, lit by the host while pin `i`'s target is muted.
+#[cfg(not(feature = "level-strip"))]
+const LED_COUNT: usize = 6;
+// The level strip's SPI bus takes d10-d13, which leaves the mute LEDs on d8 and d9 only.
+#[cfg(feature = "level-strip")]
+const LED_COUNT: usize = 2;
+// With the `level-strip` feature, a WS2812 strip wired to d11 (MOSI) shows the volume the host
+// sends for each pin's target (with `general.level_leds` set) as a bar of this many LEDs, from
+// green when quiet to red at full volume. The strip runs along the pins in order: pin 0's LEDs
+// come first. d13 (SCK) and d10 (the SPI chip select) can't be used for anything else.
+//
+// The SPI bus runs at 2 MHz (the CPU clock over 8) as ws2812-spi needs, and the frame is
+// prerendered into a buffer of 12 bytes per LED, since an AVR is too slow to encode it on the
+// fly. Writing the strip blocks for about 0.6 ms plus 50 us per LED, during which bytes from
+// the host overrun the serial port's two byte buffer: the strip is only written between
+// frames, and a frame that was cut anyway fails its checksum and is dropped. The RAM the
+// buffer takes and the time the write blocks for limit the strip to `MAX_STRIP_LEN` LEDs.
+#[cfg(feature = "level-strip")]
+const LEDS_PER_SLIDER: usize = 4;
+// Scales every strip color, out of 255. A WS2812 draws up to 60 mA at full white, more than
+// USB can power for a whole strip.
+#[cfg(feature = "level-strip")]
+const LEVEL_BRIGHTNESS: u8 = 32;
+#[cfg(feature = "level-strip")]
+const STRIP_LEN: usize = N * LEDS_PER_SLIDER;
+#[cfg(feature = "level-strip")]
+const MAX_STRIP_LEN: usize = 48;
+#[cfg(feature = "level-strip")]
+const _: () = assert!(STRIP_LEN <= MAX_STRIP_LEN, "the level strip is too long");
+// Motorized faders are driven towards the position the host sends until their reading is
+// within this many raw steps of it. See `drive_fader`.
+const FADER_TOLERANCE: u16 = 8;
 
-#[derive(Clone, Copy)]
-struct Potentiometer {
-    accumulator: u32,
-    last_stable_val: u16,
+/// Direction to run a fader's motor in.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Drive {
+    Stop,
+    Up,
+    Down,
 }
 
-impl Potentiometer {
+/// Runs the motor of pin `i`'s fader, called on every sample while the host has asked for
+/// the fader to move and with `Drive::Stop` once it got there. The stock wiring uses every
+/// digital pin for buttons and LEDs, so this does nothing until a motor driver (such as an
+/// H-bridge per fader) is wired up and its inputs are set here.
+fn drive_fader(_i: usize, _drive: Drive) {}
+
+/// Returns pin `i`'s part of the level strip for `level` (0 to 255): a bar of lit LEDs as
+/// long as the level, colored from green to red as it rises. Any level above 0 lights one.
+#[cfg(feature = "level-strip")]
+fn level_bar(level: u8) -> impl Iterator<Item = RGB8> {
+    let lit = (level as usize * LEDS_PER_SLIDER).div_ceil(u8::MAX as usize);
+    let color = RGB8 {
+        r: level,
+        g: u8::MAX - level,
+        b: 0,
+    };
+    (0..LEDS_PER_SLIDER).map(move |led| if led < lit { color } else { RGB8::default() })
+}
+
+/// Returns the slider ID reported for pin (and button and LED) `i`.
+fn slider_id(i: usize) -> u8 {
+    PIN_TO_ID.get(i).copied().unwrap_or(i as u8)
+}
+
+/// Returns the settings pin `i` is built with.
+fn default_settings(i: usize) -> PinSettings {
+    PinSettings {
+        enabled: PINS_TO_READ.contains(&i),
+        threshold: HYSTERESIS_THRESHOLDS[i],
+    }
+}
+
+/// Returns the EEPROM offset of pin `i`'s stored settings.
+fn settings_offset(i: usize) -> u16 {
+    (i * PIN_SETTINGS_LEN) as u16
+}
+
+/// Returns the settings stored for pin `i`, if valid ones were stored.
+fn stored_settings(eeprom: &arduino_hal::Eeprom, i: usize) -> Option<PinSettings> {
+    let mut bytes = [0; PIN_SETTINGS_LEN];
+    eeprom.read(settings_offset(i), &mut bytes).ok()?;
+    PinSettings::from_bytes(bytes)
+}
+
+/// Turns each pin into an ADC channel, so pins of different types can share one array.
+macro_rules! analog_channels {
+    ($adc:expr, $($pin:expr),+ $(,)?) => {
+        [$($pin.into_analog_input($adc).into_channel()),+]
+    };
+}
+
+/// Collects bytes from the host until a whole frame has arrived.
+struct FrameReceiver {
+    buf: [u8; MAX_FRAME_LEN],
+    len: usize,
+    overflowed: bool,
+}
+
+impl FrameReceiver {
     fn new() -> Self {
         Self {
-            accumulator: 0,
-            last_stable_val: 0,
+            buf: [0; MAX_FRAME_LEN],
+            len: 0,
+            overflowed: false,
         }
     }
 
-    fn update(&mut self, raw_input: u16) -> u16 {
-        // EMA Filter
-        if self.accumulator == 0 {
-            self.accumulator = (raw_input as u32) << 1;
-        } else {
-            self.accumulator = self.accumulator - (self.accumulator >> 1) + raw_input as u32;
-        }
-
-        let smoothed_raw = (self.accumulator >> 1) as u16;
+    /// Returns whether no frame is partly received, so blocking won't cut one.
+    #[cfg(feature = "level-strip")]
+    fn is_idle(&self) -> bool {
+        self.len == 0 && !self.overflowed
+    }
 
-        // Hysteresis
-        let diff = (smoothed_raw as i16 - self.last_stable_val as i16).abs();
+    /// Adds a received byte, returning the message once its frame is complete. Corrupted
+    /// and oversized frames are dropped.
+    fn push(&mut self, byte: u8) -> Option<HostMessage> {
+        if byte == 0x00 {
+            let len = self.len;
+            let overflowed = self.overflowed;
+            self.len = 0;
+            self.overflowed = false;
 
-        if diff > HYSTERESIS_THRESHOLD {
-            self.last_stable_val = smoothed_raw;
+            if overflowed {
+                return None;
+            }
+            return decode_frame(&mut self.buf[..len]).ok();
         }
 
-        // Edge Clamping
-        if self.last_stable_val > 1018 {
-            1023
-        } else if self.last_stable_val < 5 {
-            0
-        } else {
-            self.last_stable_val
+        match self.buf.get_mut(self.len) {
+            Some(slot) => {
+                *slot = byte;
+                self.len += 1;
+            }
+            // Too long to be a valid frame, skip everything until the next delimiter
+            None => self.overflowed = true,
         }
+        None
     }
 }
 
@@ -56,65 +195,236 @@ impl Potentiometer {
 fn main() -> ! {
     let dp = arduino_hal::Peripherals::take().unwrap();
     let pins = arduino_hal::pins!(dp);
-    let mut serial = arduino_hal::default_serial!(dp, pins, 57600);
+    let serial = arduino_hal::default_serial!(dp, pins, 57600);
+    let (mut rx, mut tx) = serial.split();
 
     let mut adc = arduino_hal::Adc::new(dp.ADC, Default::default());
-    let a0 = pins.a0.into_analog_input(&mut adc);
-    let a1 = pins.a1.into_analog_input(&mut adc);
-    let a2 = pins.a2.into_analog_input(&mut adc);
-    let a3 = pins.a3.into_analog_input(&mut adc);
-    let a4 = pins.a4.into_analog_input(&mut adc);
-    let a5 = pins.a5.into_analog_input(&mut adc);
+    // One pin per potentiometer, `N` in total. Pins without a digital mode (such as `ADC6` and
+    // `ADC7` on a Nano) can be added as `arduino_hal::adc::channel::ADC6.into_channel()`.
+    let channels: [arduino_hal::adc::Channel; N] =
+        analog_channels!(&mut adc, pins.a0, pins.a1, pins.a2, pins.a3, pins.a4, pins.a5);
+
+    let buttons = [
+        pins.d2.into_pull_up_input().downgrade(),
+        pins.d3.into_pull_up_input().downgrade(),
+        pins.d4.into_pull_up_input().downgrade(),
+        pins.d5.into_pull_up_input().downgrade(),
+        pins.d6.into_pull_up_input().downgrade(),
+        pins.d7.into_pull_up_input().downgrade(),
+    ];
 
-    let mut pots = [Potentiometer::new(); 6];
-    let mut last_output_values = [0u16; 6];
+    #[cfg(not(feature = "level-strip"))]
+    let mut leds: [_; LED_COUNT] = [
+        pins.d8.into_output().downgrade(),
+        pins.d9.into_output().downgrade(),
+        pins.d10.into_output().downgrade(),
+        pins.d11.into_output().downgrade(),
+        pins.d12.into_output().downgrade(),
+        pins.d13.into_output().downgrade(),
+    ];
+    #[cfg(feature = "level-strip")]
+    let mut leds: [_; LED_COUNT] = [
+        pins.d8.into_output().downgrade(),
+        pins.d9.into_output().downgrade(),
+    ];
 
-    let mut buf = [0; core::mem::size_of::<Slider>() * 2];
+    // The chip select has to stay an output for the SPI peripheral to remain the bus master
+    #[cfg(feature = "level-strip")]
+    let (spi, _cs) = arduino_hal::Spi::new(
+        dp.SPI,
+        pins.d13.into_output(),
+        pins.d11.into_output(),
+        pins.d12.into_pull_up_input(),
+        pins.d10.into_output(),
+        arduino_hal::spi::Settings {
+            clock: arduino_hal::spi::SerialClockRate::OscfOver8,
+            mode: ws2812_spi::prerendered::MODE,
+            ..Default::default()
+        },
+    );
+    #[cfg(feature = "level-strip")]
+    let mut strip_data = [0; STRIP_LEN * 12];
+    #[cfg(feature = "level-strip")]
+    let mut strip = Ws2812::new(spi, &mut strip_data);
+    // Last level the host sent for each pin's target, and whether the strip still shows older
+    // ones. It starts dark, which needs a write too.
+    #[cfg(feature = "level-strip")]
+    let mut levels = [0u8; N];
+    #[cfg(feature = "level-strip")]
+    let mut strip_outdated = true;
+    let mut receiver = FrameReceiver::new();
+
+    let mut eeprom = arduino_hal::Eeprom::new(dp.EEPROM);
+    let mut settings: [PinSettings; N] =
+        core::array::from_fn(|i| stored_settings(&eeprom, i).unwrap_or(default_settings(i)));
+
+    let mut pots = settings.map(|pin| {
+        let mut pot = Potentiometer::new(pin.threshold);
+        pot.set_idle_reset((IDLE_RESET_MS / SAMPLE_INTERVAL_MS) as u16);
+        pot
+    });
+    let mut last_output_values = [0u16; N];
+    let mut last_button_states = [false; 6];
+    // How long each button has read differently from its last reported state
+    let mut button_changed_ms = [0u32; 6];
+    // Position each fader's motor is moving it to, if any
+    let mut fader_targets: [Option<u16>; N] = [None; N];
+    let mut since_heartbeat_ms = 0;
+
+    let mut buf = [0; MAX_FRAME_LEN];
+    let mut send = |message: &Message| {
+        match encode_frame(message, &mut buf) {
+            Ok(encoded_data) => {
+                for &mut byte in encoded_data {
+                    nb::block!(tx.write(byte)).unwrap();
+                }
+            }
+            Err(_) => {
+                // Buffer error
+            }
+        }
+    };
+
+    let hello = Message::Hello(Hello {
+        version: PROTOCOL_VERSION,
+    });
+    send(&hello);
 
     loop {
-        arduino_hal::delay_ms(25);
-
-        let raw_reads = [
-            a0.analog_read(&mut adc),
-            a1.analog_read(&mut adc),
-            a2.analog_read(&mut adc),
-            a3.analog_read(&mut adc),
-            a4.analog_read(&mut adc),
-            a5.analog_read(&mut adc),
-        ];
-
-        let current_output_values: [u16; 6] = [
-            pots[0].update(raw_reads[0]),
-            pots[1].update(raw_reads[1]),
-            pots[2].update(raw_reads[2]),
-            pots[3].update(raw_reads[3]),
-            pots[4].update(raw_reads[4]),
-            pots[5].update(raw_reads[5]),
-        ];
-
-        for (i, &new_val) in current_output_values.iter().enumerate() {
-            if !PINS_TO_READ.contains(&i) {
+        // Wait out the sample interval in short steps, draining the receiver as we go since
+        // the hardware only buffers two bytes
+        for _ in 0..SAMPLE_INTERVAL_MS * 10 {
+            arduino_hal::delay_us(100);
+
+            while let Ok(byte) = rx.read() {
+                match receiver.push(byte) {
+                    Some(HostMessage::Led(LedState { id, on })) => {
+                        let index = (0..LED_COUNT).find(|&i| slider_id(i) == id);
+                        if let Some(led) = index.and_then(|i| leds.get_mut(i)) {
+                            if on {
+                                led.set_high();
+                            } else {
+                                led.set_low();
+                            }
+                        }
+                    }
+                    Some(HostMessage::Hello(_)) => send(&hello),
+                    Some(HostMessage::Ping(ping)) => send(&Message::Pong(ping)),
+                    Some(HostMessage::Fader(FaderPosition { id, value })) => {
+                        if let Some(i) = (0..N).find(|&i| slider_id(i) == id) {
+                            fader_targets[i] = Some(value.min(1023));
+                        }
+                    }
+                    Some(HostMessage::PinConfig(PinConfig { id, settings: new })) => {
+                        let Some(i) = (0..N).find(|&i| slider_id(i) == id) else {
+                            continue;
+                        };
+                        // Invalid settings are never written, the reply tells the host
+                        // what the pin kept instead. Unchanged ones aren't rewritten
+                        // either, to spare the EEPROM's limited write cycles.
+                        if new.is_valid() && new != settings[i] {
+                            settings[i] = new;
+                            pots[i].set_threshold(new.threshold);
+                            let _ = eeprom.write(settings_offset(i), &new.to_bytes());
+                        }
+                        send(&Message::PinConfig(PinConfig {
+                            id,
+                            settings: settings[i],
+                        }));
+                    }
+                    #[cfg(feature = "level-strip")]
+                    Some(HostMessage::Level(LevelState { id, level })) => {
+                        if let Some(i) = (0..N).find(|&i| slider_id(i) == id) {
+                            strip_outdated |= levels[i] != level;
+                            levels[i] = level;
+                        }
+                    }
+                    // No strip to show it on
+                    #[cfg(not(feature = "level-strip"))]
+                    Some(HostMessage::Level(_)) => {}
+                    None => {}
+                }
+            }
+        }
+
+        // Written once per sample at most, after every frame that arrived has been handled
+        #[cfg(feature = "level-strip")]
+        if strip_outdated && receiver.is_idle() {
+            strip_outdated = false;
+            let colors = levels.iter().flat_map(|&level| level_bar(level));
+            let _ = strip.write(brightness(colors, LEVEL_BRIGHTNESS));
+        }
+
+        since_heartbeat_ms += SAMPLE_INTERVAL_MS;
+        if since_heartbeat_ms >= HEARTBEAT_INTERVAL_MS {
+            since_heartbeat_ms = 0;
+            send(&Message::Heartbeat);
+        }
+
+        for (i, (pot, channel)) in pots.iter_mut().zip(&channels).enumerate() {
+            if !settings[i].enabled {
                 continue;
             }
 
+            let raw = adc.read_blocking(channel);
+            let new_val = pot.update(raw);
+
+            if DEBUG_RAW {
+                send(&Message::Raw(RawReading {
+                    id: slider_id(i),
+                    raw,
+                    smoothed: pot.smoothed(),
+                }));
+            }
+
+            if let Some(target) = fader_targets[i] {
+                let drive = if raw.saturating_add(FADER_TOLERANCE) < target {
+                    Drive::Up
+                } else if raw > target.saturating_add(FADER_TOLERANCE) {
+                    Drive::Down
+                } else {
+                    fader_targets[i] = None;
+                    Drive::Stop
+                };
+                drive_fader(i, drive);
+                // Positions on the way would drag the target's volume along, so only the
+                // one the fader ends up at is reported
+                if drive != Drive::Stop {
+                    continue;
+                }
+            }
+
             if new_val != last_output_values[i] {
                 last_output_values[i] = new_val;
 
-                let slider = Slider {
-                    id: i as u8,
+                send(&Message::Slider(Slider {
+                    id: slider_id(i),
                     value: new_val,
-                };
+                }));
+            }
+        }
 
-                match to_slice_cobs(&slider, &mut buf) {
-                    Ok(encoded_data) => {
-                        for &mut byte in encoded_data {
-                            nb::block!(serial.write(byte)).unwrap();
-                        }
-                    }
-                    Err(_) => {
-                        // Buffer error
-                    }
-                }
+        for (i, button) in buttons.iter().enumerate() {
+            if !BUTTONS_TO_READ.contains(&i) {
+                continue;
+            }
+
+            // Pull-up input, so a pressed button reads low
+            let pressed = button.is_low();
+
+            if pressed == last_button_states[i] {
+                button_changed_ms[i] = 0;
+                continue;
+            }
+            button_changed_ms[i] += SAMPLE_INTERVAL_MS;
+            if button_changed_ms[i] >= BUTTON_DEBOUNCE_MS {
+                button_changed_ms[i] = 0;
+                last_button_states[i] = pressed;
+
+                send(&Message::Button(Button {
+                    id: slider_id(i),
+                    pressed,
+                }));
             }
         }
     }
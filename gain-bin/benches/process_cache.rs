@@ -0,0 +1,64 @@
+//! Compares looking up the owner of every audio session on every slider tick with going
+//! through the backend's process cache, for a few session counts. Looking a process up is
+//! simulated with a busy wait of about what `OpenProcess` and `K32GetModuleBaseNameW` take.
+
+// Only the name lookups are benchmarked
+#[allow(dead_code)]
+#[path = "../src/volume/processes.rs"]
+mod processes;
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use processes::{PROCESS_CACHE_TTL, ProcessCache};
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+/// Simulated cost of opening a process and reading its name.
+const LOOKUP_COST: Duration = Duration::from_micros(5);
+/// Slider ticks per second, as sent by a slider that is being moved.
+const TICKS_PER_SECOND: u32 = 60;
+const SESSION_COUNTS: [u32; 3] = [5, 20, 80];
+
+fn lookup(pid: u32) -> Option<String> {
+    let start = Instant::now();
+    while start.elapsed() < LOOKUP_COST {}
+    Some(format!("app{}.exe", pid))
+}
+
+/// Simulates one second of slider ticks, each looking up the owner of `sessions` sessions.
+fn second_of_ticks(sessions: u32, mut name: impl FnMut(u32, Instant) -> Option<String>) {
+    let start = Instant::now();
+    let tick = PROCESS_CACHE_TTL / TICKS_PER_SECOND;
+    for i in 0..TICKS_PER_SECOND {
+        let now = start + tick * i;
+        for pid in 0..sessions {
+            black_box(name(black_box(pid), now));
+        }
+    }
+}
+
+fn session_lookups(c: &mut Criterion) {
+    let mut group = c.benchmark_group("one second of ticks");
+    for sessions in SESSION_COUNTS {
+        group.bench_with_input(
+            BenchmarkId::new("uncached", sessions),
+            &sessions,
+            |b, &sessions| b.iter(|| second_of_ticks(sessions, |pid, _| lookup(pid))),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("cached", sessions),
+            &sessions,
+            |b, &sessions| {
+                b.iter(|| {
+                    let mut cache = ProcessCache::default();
+                    second_of_ticks(sessions, |pid, now| cache.name(pid, now, lookup))
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, session_lookups);
+criterion_main!(benches);
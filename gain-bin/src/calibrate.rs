@@ -0,0 +1,79 @@
+use crate::{
+    config::{self, LoadedConfig},
+    open_port, read_message,
+};
+use anyhow::{Result, anyhow};
+use gain_lib::Message;
+use std::{
+    collections::BTreeMap,
+    io::{BufReader, Write},
+    sync::mpsc::{self, TryRecvError},
+    thread,
+};
+
+/// Runs `gain calibrate`: records the lowest and highest raw reading of every slider while
+/// the user sweeps them, until Enter is pressed, then writes them to the configuration file
+/// as each slider's `min_raw` and `max_raw`.
+pub fn run(config: &LoadedConfig, config_path: &str) -> Result<()> {
+    let mut reader = BufReader::new(open_port(&config.connection)?);
+
+    let (enter_tx, enter) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = std::io::stdin().read_line(&mut String::new());
+        let _ = enter_tx.send(());
+    });
+
+    println!("Move every slider all the way down and all the way up, then press Enter.");
+
+    let mut ranges: BTreeMap<u8, (u16, u16)> = BTreeMap::new();
+    let mut buffer = Vec::new();
+    loop {
+        match enter.try_recv() {
+            Ok(()) => break,
+            Err(TryRecvError::Disconnected) => return Err(anyhow!("Failed to read from stdin")),
+            Err(TryRecvError::Empty) => {}
+        }
+
+        if let Some(Message::Slider(slider)) = read_message(&mut reader, &mut buffer)? {
+            let range = ranges
+                .entry(slider.id)
+                .or_insert((slider.value, slider.value));
+            range.0 = range.0.min(slider.value);
+            range.1 = range.1.max(slider.value);
+            print_ranges(&ranges)?;
+        }
+    }
+
+    println!();
+    if ranges.is_empty() {
+        println!("No slider readings received");
+        return Ok(());
+    }
+    for (id, _) in ranges.extract_if(.., |_, (min, max)| min >= max) {
+        println!("Slider {} was not moved, skipping it", id);
+    }
+    if ranges.is_empty() {
+        return Ok(());
+    }
+
+    for id in config::save_calibration(config_path, &ranges)? {
+        let (min, max) = ranges[&id];
+        println!(
+            "Slider {} has no [[slider]] entry to save to (min_raw = {}, max_raw = {})",
+            id, min, max
+        );
+    }
+    println!("Saved calibration to {}", config_path);
+    Ok(())
+}
+
+/// Rewrites the progress line with the travel recorded so far for every slider.
+fn print_ranges(ranges: &BTreeMap<u8, (u16, u16)>) -> Result<()> {
+    let line: Vec<String> = ranges
+        .iter()
+        .map(|(id, (min, max))| format!("{}: {}-{}", id, min, max))
+        .collect();
+    print!("\r{}", line.join("  "));
+    std::io::stdout().flush()?;
+    Ok(())
+}
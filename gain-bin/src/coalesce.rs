@@ -0,0 +1,110 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// Limits how often each slider is applied: a value arriving within `window` of the last
+/// applied one is held back, and only the latest held value is applied once the window is
+/// over. The first value after a quiet period is applied right away, so a single movement
+/// gets no extra latency.
+#[derive(Default)]
+pub struct Coalescer {
+    last_applied: HashMap<u8, Instant>,
+    pending: HashMap<u8, u16>,
+}
+
+impl Coalescer {
+    /// Offers a new value of slider `id`, returning it if it should be applied now.
+    pub fn offer(&mut self, id: u8, value: u16, now: Instant, window: Duration) -> Option<u16> {
+        if let Some(&last) = self.last_applied.get(&id)
+            && now.duration_since(last) < window
+        {
+            self.pending.insert(id, value);
+            return None;
+        }
+
+        self.last_applied.insert(id, now);
+        self.pending.remove(&id);
+        Some(value)
+    }
+
+    /// Takes the held-back values whose window is over, to be applied now.
+    pub fn due(&mut self, now: Instant, window: Duration) -> Vec<(u8, u16)> {
+        let due: Vec<(u8, u16)> = self
+            .pending
+            .iter()
+            .filter(|(id, _)| {
+                self.last_applied
+                    .get(id)
+                    .is_none_or(|&last| now.duration_since(last) >= window)
+            })
+            .map(|(&id, &value)| (id, value))
+            .collect();
+
+        for (id, _) in &due {
+            self.pending.remove(id);
+            self.last_applied.insert(*id, now);
+        }
+        due
+    }
+
+    /// Returns whether any value is held back.
+    pub fn is_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: Duration = Duration::from_millis(10);
+
+    #[test]
+    fn first_value_is_applied_immediately() {
+        let mut coalescer = Coalescer::default();
+        assert_eq!(coalescer.offer(0, 100, Instant::now(), WINDOW), Some(100));
+        assert!(!coalescer.is_pending());
+    }
+
+    #[test]
+    fn only_the_latest_value_in_a_window_is_applied() {
+        let mut coalescer = Coalescer::default();
+        let start = Instant::now();
+        coalescer.offer(0, 100, start, WINDOW);
+        for (ms, value) in [(2, 110), (4, 120), (6, 130)] {
+            let now = start + Duration::from_millis(ms);
+            assert_eq!(coalescer.offer(0, value, now, WINDOW), None);
+        }
+
+        assert!(
+            coalescer
+                .due(start + Duration::from_millis(8), WINDOW)
+                .is_empty()
+        );
+        assert_eq!(
+            coalescer.due(start + Duration::from_millis(10), WINDOW),
+            [(0, 130)]
+        );
+        assert!(!coalescer.is_pending());
+    }
+
+    #[test]
+    fn sliders_are_coalesced_independently() {
+        let mut coalescer = Coalescer::default();
+        let now = Instant::now();
+        assert_eq!(coalescer.offer(0, 100, now, WINDOW), Some(100));
+        assert_eq!(coalescer.offer(1, 200, now, WINDOW), Some(200));
+        assert_eq!(coalescer.offer(0, 110, now, WINDOW), None);
+        assert_eq!(coalescer.due(now + WINDOW, WINDOW), [(0, 110)]);
+    }
+
+    #[test]
+    fn zero_window_applies_every_value() {
+        let mut coalescer = Coalescer::default();
+        let now = Instant::now();
+        for value in [100, 110, 120] {
+            assert_eq!(coalescer.offer(0, value, now, Duration::ZERO), Some(value));
+        }
+    }
+}
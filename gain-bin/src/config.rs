@@ -1,6 +1,17 @@
-use anyhow::Result;
-use log::info;
-use std::{collections::HashMap, fs, time::Instant};
+use crate::{
+    error::GainError,
+    hotkey::Hotkey,
+    matcher::{AppMatcher, UnmappedApps},
+    slider::MAX_RAW_VALUE,
+};
+use anyhow::{Result, anyhow};
+use log::{info, warn};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
 
 /// Configuration structure for the application, deserialized from a TOML file.
 #[derive(serde::Deserialize, Debug, Clone)]
@@ -9,31 +20,410 @@ pub struct Config {
     /// Connection configuration.
     pub connection: Connection,
     #[serde(default)]
+    /// Further serial devices read alongside the one in `connection`.
+    pub device: Vec<Connection>,
+    #[serde(default)]
     /// General settings for volume control.
     pub general: General,
     #[serde(default)]
     /// Slider mappings to volume targets.
     pub slider: Vec<SliderMappings>,
+    #[serde(default)]
+    /// Named sets of slider mappings that can be switched between at runtime.
+    pub profile: BTreeMap<String, Profile>,
+    #[serde(default)]
+    /// Named sets of applications that `group` targets refer to.
+    pub group: BTreeMap<String, Group>,
+    #[serde(default)]
+    /// Friendly names for applications, replaced by what they stand for wherever an app is
+    /// listed.
+    pub alias: BTreeMap<String, Alias>,
+}
+
+/// A named set of applications, so the same list can be targeted by several sliders and
+/// profiles without repeating it.
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct Group {
+    pub apps: Vec<AppMatcher>,
+}
+
+/// The application names an alias stands for. Names that are aliases themselves are
+/// replaced in turn.
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum Alias {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Alias {
+    fn names(&self) -> &[String] {
+        match self {
+            Alias::One(name) => std::slice::from_ref(name),
+            Alias::Many(names) => names,
+        }
+    }
+}
+
+/// Checks that every alias stands for at least one app and that none ends up standing for
+/// itself, whether or not it is used.
+fn check_aliases(aliases: &BTreeMap<String, Alias>) -> Result<()> {
+    for (name, alias) in aliases {
+        if alias.names().is_empty() || alias.names().iter().any(String::is_empty) {
+            return Err(anyhow!("Alias {:?} must name at least one app", name));
+        }
+        resolve_aliases(vec![AppMatcher::from(name.clone())], aliases)?;
+    }
+    Ok(())
+}
+
+/// Replaces the apps in `apps` that are aliases with the apps they stand for, which keep
+/// the alias' weight. Other apps are kept as they are.
+fn resolve_aliases(
+    apps: Vec<AppMatcher>,
+    aliases: &BTreeMap<String, Alias>,
+) -> Result<Vec<AppMatcher>> {
+    fn expand(
+        app: AppMatcher,
+        aliases: &BTreeMap<String, Alias>,
+        seen: &mut Vec<String>,
+        resolved: &mut Vec<AppMatcher>,
+    ) -> Result<()> {
+        let Some(alias) = aliases.get(app.pattern()) else {
+            resolved.push(app);
+            return Ok(());
+        };
+        let cycles = seen.iter().any(|name| name == app.pattern());
+        seen.push(app.pattern().to_string());
+        if cycles {
+            return Err(anyhow!("Alias cycle: {}", seen.join(" -> ")));
+        }
+        for name in alias.names() {
+            expand(app.renamed(name.clone()), aliases, seen, resolved)?;
+        }
+        seen.pop();
+        Ok(())
+    }
+
+    let mut resolved = Vec::new();
+    for app in apps {
+        expand(app, aliases, &mut Vec::new(), &mut resolved)?;
+    }
+    Ok(resolved)
+}
+
+/// Replaces the aliases among the apps of slider `id`'s `target`, see [`resolve_aliases`].
+fn resolve_target_aliases(
+    id: u8,
+    target: &mut VolumeTarget,
+    aliases: &BTreeMap<String, Alias>,
+) -> Result<()> {
+    let resolve = |apps: &mut Vec<AppMatcher>| -> Result<()> {
+        *apps = resolve_aliases(std::mem::take(apps), aliases)?;
+        Ok(())
+    };
+    match target {
+        VolumeTarget::Apps(apps) => resolve(apps)?,
+        VolumeTarget::Unmapped { exclude, include } => {
+            resolve(exclude)?;
+            if let Some(include) = include {
+                resolve(include)?;
+            }
+        }
+        VolumeTarget::Solo { app, .. } => {
+            let mut apps = resolve_aliases(vec![app.clone()], aliases)?;
+            if apps.len() != 1 {
+                return Err(anyhow!(
+                    "Slider {}: the solo app {} must stand for a single app",
+                    id,
+                    app
+                ));
+            }
+            *app = apps.remove(0);
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// A named set of slider mappings, layered over the top-level ones: sliders it maps use
+/// its mapping, the others keep the top-level one.
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct Profile {
+    #[serde(default)]
+    pub slider: Vec<SliderMappings>,
 }
 
 #[derive(serde::Deserialize, Debug, Clone)]
 pub struct General {
-    /// Volume adjustment step size (0.0 to 1.0) for each slider movement.
+    /// Volume adjustment step size, above 0.0 and up to 1.0. Slider volumes are rounded to
+    /// a multiple of it, so smaller steps give finer control. Defaults to 0.01.
+    #[serde(default = "default_volume_step")]
     pub volume_step: f64,
     /// Invert the direction of volume adjustment for sliders.
+    #[serde(default)]
     pub invert_direction: bool,
+    /// Response curve of the sliders that don't set their own.
+    #[serde(default)]
+    pub curve: Curve,
+    /// Mute the target when its slider reaches 0, keeping the previous volume level so it
+    /// is restored on unmute.
+    #[serde(default)]
+    pub mute_on_zero: bool,
+    /// Volume (0.0 to 1.0) under which a slider mutes its target instead of setting the
+    /// volume, for apps that are still faintly audible at low levels. 0 disables it.
+    #[serde(default)]
+    pub mute_below: f64,
+    /// Time in milliseconds over which volume changes are faded in. 0 applies them instantly.
+    #[serde(default)]
+    pub ramp_ms: u64,
+    /// Time in milliseconds over which the focused application fades to the level of a
+    /// `foreground` slider when the focus moves to it. 0 sets it instantly.
+    #[serde(default)]
+    pub focus_ramp_ms: u64,
+    /// Shortest time in milliseconds between two volume changes from the same slider. Values
+    /// arriving faster are coalesced so only the latest one is applied. 0 applies every
+    /// reading.
+    #[serde(default)]
+    pub coalesce_ms: u64,
+    /// How app names in the configuration are matched against process names.
+    #[serde(default)]
+    pub match_mode: MatchMode,
+    /// Processes that no slider, button or other action ever changes, matched like app
+    /// names (following `match_mode`).
+    #[serde(default)]
+    pub ignore: Vec<AppMatcher>,
+    /// Audio system to control on Linux. Read at startup.
+    #[serde(default)]
+    pub backend: AudioBackend,
+    /// Smallest change in a slider's raw value (0 to 1023) that is applied. Smaller
+    /// movements are treated as jitter and ignored. 0 applies every reading.
+    #[serde(default)]
+    pub deadzone: u16,
+    /// Apply the last volume of app and `unmapped` sliders to apps that start playing audio
+    /// after the slider moved, instead of leaving them at the level the system remembers.
+    #[serde(default)]
+    pub reapply_on_launch: bool,
+    /// Volumes to set when gain exits.
+    #[serde(default)]
+    pub restore_on_exit: Option<RestoreOnExit>,
+    /// Local TCP port on which slider updates are broadcast as JSON lines. Read at startup.
+    #[serde(default)]
+    pub status_port: Option<u16>,
+    /// Local port of the HTTP API for reading and setting volumes. Read at startup, and
+    /// requires the `http` feature.
+    #[serde(default)]
+    pub http_port: Option<u16>,
+    /// MQTT broker that slider updates are published to. Read at startup, and requires the
+    /// `mqtt` feature.
+    #[serde(default)]
+    pub mqtt: Option<Mqtt>,
+    /// Part of the name of the MIDI output port to send to. Defaults to the first port.
+    #[serde(default)]
+    pub midi_port: Option<String>,
+    /// Smoothing applied to raw slider values on top of the firmware's own filtering.
+    #[serde(default)]
+    pub host_filter: HostFilter,
+    /// Profile that is active at startup. Defaults to the top-level slider mappings.
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// ID of a button that switches to the next profile when pressed, instead of muting.
+    #[serde(default)]
+    pub profile_button: Option<u8>,
+    /// File that logs are also written to, rotated by size. Read at startup.
+    #[serde(default)]
+    pub log_file: Option<String>,
+    /// Raw steps (0 to 1023) a motorized fader has to be away from its target's volume
+    /// before it is moved there, so it doesn't fight a hand nudging it.
+    #[serde(default = "default_fader_threshold")]
+    pub fader_threshold: u16,
+    /// Global hotkey, such as `Ctrl+Alt+M`, that drops master (and with `panic_apps` every
+    /// app) to `panic_volume`. Read at startup, and only supported on Windows.
+    #[serde(default)]
+    pub panic_hotkey: Option<String>,
+    /// Volume (0.0 to 1.0) the panic hotkey drops louder targets to.
+    #[serde(default = "default_panic_volume")]
+    pub panic_volume: f64,
+    /// Whether the panic hotkey also drops every app's audio session, not just master.
+    #[serde(default)]
+    pub panic_apps: bool,
+    /// Whether a slider whose target changed, on a reload or a profile switch, only takes
+    /// over the target once it crosses the target's current volume.
+    #[serde(default)]
+    pub pickup_mode: bool,
+    /// Whether every slider's volume is sent to the devices, for firmware that shows it on
+    /// an LED strip.
+    #[serde(default)]
+    pub level_leds: bool,
+}
+
+fn default_volume_step() -> f64 {
+    0.01
+}
+
+fn default_fader_threshold() -> u16 {
+    20
+}
+
+fn default_panic_volume() -> f64 {
+    0.1
+}
+
+/// Connection to an MQTT broker, which receives a JSON message for every slider update.
+#[derive(serde::Deserialize, Debug, Clone)]
+#[cfg_attr(not(feature = "mqtt"), allow(dead_code))]
+pub struct Mqtt {
+    /// Host name or IP address of the broker.
+    pub host: String,
+    #[serde(default = "default_mqtt_port")]
+    pub port: u16,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    /// Topic each update is published to, with `{id}` replaced by the slider ID.
+    #[serde(default = "default_mqtt_topic")]
+    pub topic: String,
+}
+
+fn default_mqtt_port() -> u16 {
+    1883
+}
+
+fn default_mqtt_topic() -> String {
+    "gain/slider/{id}".to_string()
+}
+
+/// Smoothing filter for raw slider values. Both filters run in continuous time, so the
+/// result doesn't depend on how often the firmware reports.
+#[derive(serde::Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum HostFilter {
+    /// Values are used as received.
+    #[default]
+    None,
+    /// Exponential moving average: the value covers about 63% of a change within
+    /// `time_constant_ms`.
+    Ema {
+        #[serde(default = "default_time_constant_ms")]
+        time_constant_ms: f64,
+    },
+    /// One Euro filter: smooths heavily while a slider is still and lightly while it moves
+    /// fast. `min_cutoff` (Hz) sets the smoothing at rest, `beta` how quickly the cutoff
+    /// rises with speed (in raw units per second), and `d_cutoff` (Hz) the smoothing of that
+    /// speed estimate.
+    OneEuro {
+        #[serde(default = "default_min_cutoff")]
+        min_cutoff: f64,
+        #[serde(default = "default_beta")]
+        beta: f64,
+        #[serde(default = "default_d_cutoff")]
+        d_cutoff: f64,
+    },
+}
+
+fn default_time_constant_ms() -> f64 {
+    50.0
+}
+
+fn default_min_cutoff() -> f64 {
+    1.0
+}
+
+fn default_beta() -> f64 {
+    0.005
+}
+
+fn default_d_cutoff() -> f64 {
+    1.0
+}
+
+/// Volumes to set when gain exits.
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum RestoreOnExit {
+    /// If true, every slider target is set back to the volume it had when gain started.
+    Startup(bool),
+    /// Explicit volumes (0.0 to 1.0) keyed by target: `master`, `current`, `unmapped`,
+    /// `microphone`, `system_sounds`, or an app name.
+    Volumes(HashMap<String, f64>),
+}
+
+/// How app names in the configuration are matched against process names.
+#[derive(serde::Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchMode {
+    /// The whole process name must match, ignoring case and an optional `.exe` extension.
+    #[default]
+    Exact,
+    /// The process name must contain the app name, ignoring case.
+    Contains,
+    /// The app name is a regular expression searched for in the process name, ignoring case.
+    Regex,
+}
+
+/// Which audio system the Linux volume backend talks to.
+#[derive(serde::Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioBackend {
+    /// PulseAudio (or PipeWire-Pulse) if a server is running, otherwise bare ALSA.
+    #[default]
+    Auto,
+    PulseAudio,
+    Alsa,
+}
+
+impl General {
+    /// Compiles `app` from the configuration: switches it to `match_mode` and makes it skip
+    /// the `ignore` list.
+    pub fn matcher(&self, app: AppMatcher) -> Result<AppMatcher> {
+        Ok(app.with_mode(self.match_mode)?.ignoring(&self.ignore))
+    }
+
+    /// Returns whether a slider at `volume` mutes its target rather than setting its volume,
+    /// because of `mute_on_zero` or `mute_below`.
+    pub fn mutes_at(&self, volume: f64) -> bool {
+        (self.mute_on_zero && volume <= 0.0) || volume < self.mute_below
+    }
 }
 
 impl Default for General {
     fn default() -> Self {
         General {
-            volume_step: 0.01,
+            volume_step: default_volume_step(),
             invert_direction: false,
+            curve: Curve::default(),
+            mute_on_zero: false,
+            mute_below: 0.0,
+            ramp_ms: 0,
+            focus_ramp_ms: 0,
+            coalesce_ms: 0,
+            match_mode: MatchMode::default(),
+            ignore: Vec::new(),
+            backend: AudioBackend::default(),
+            deadzone: 0,
+            reapply_on_launch: false,
+            restore_on_exit: None,
+            status_port: None,
+            http_port: None,
+            mqtt: None,
+            midi_port: None,
+            host_filter: HostFilter::default(),
+            profile: None,
+            profile_button: None,
+            log_file: None,
+            fader_threshold: default_fader_threshold(),
+            panic_hotkey: None,
+            panic_volume: default_panic_volume(),
+            panic_apps: false,
+            pickup_mode: false,
+            level_leds: false,
         }
     }
 }
 
-#[derive(serde::Deserialize, Debug, Clone)]
+#[derive(serde::Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
 pub struct Connection {
     pub com_port: Option<String>,
     pub baud_rate: u32,
@@ -42,6 +432,34 @@ pub struct Connection {
     pub serial_number_filter: Option<String>,
     pub manufacturer_filter: Option<String>,
     pub product_filter: Option<String>,
+    /// Seconds without any data after which the port is closed and reopened, for adapters
+    /// that wedge while staying connected. Disabled if unset.
+    pub watchdog_secs: Option<u64>,
+    /// Seconds the modes that only talk to the device, such as `gain monitor`, keep trying
+    /// to open the port before giving up, so they can be started before it is plugged in.
+    pub startup_retry_secs: u64,
+    /// Added to the slider, button and encoder IDs this device reports, so several devices
+    /// running the same firmware can control different sliders.
+    pub id_offset: u8,
+    /// Milliseconds a serial read waits for data before giving up. Shorter timeouts notice
+    /// stalls and shutdown sooner. Must not be 0.
+    pub read_timeout_ms: u64,
+}
+
+impl Connection {
+    /// Returns whether any USB device filter is set.
+    pub fn has_filters(&self) -> bool {
+        self.vid_filter.is_some()
+            || self.pid_filter.is_some()
+            || self.serial_number_filter.is_some()
+            || self.manufacturer_filter.is_some()
+            || self.product_filter.is_some()
+    }
+
+    /// Returns the serial read timeout.
+    pub fn read_timeout(&self) -> Duration {
+        Duration::from_millis(self.read_timeout_ms)
+    }
 }
 
 impl Default for Connection {
@@ -54,6 +472,10 @@ impl Default for Connection {
             serial_number_filter: None,
             manufacturer_filter: None,
             product_filter: None,
+            watchdog_secs: None,
+            startup_retry_secs: 10,
+            id_offset: 0,
+            read_timeout_ms: 1000,
         }
     }
 }
@@ -64,12 +486,197 @@ pub struct SliderMappings {
     /// Slider ID (e.g., 0 for the first slider).
     pub id: u8,
     /// Target volume control for the slider.
-    #[serde(default)]
+    #[serde(default, deserialize_with = "deserialize_target")]
     pub target: VolumeTarget,
+    /// Whether the slider reports absolute positions or a rotary encoder's relative ticks.
+    #[serde(default)]
+    pub mode: SliderMode,
+    /// What the button sharing the slider's ID does to the slider's target.
+    #[serde(default)]
+    pub button: ButtonMode,
+    /// Response curve applied to the slider position, overriding `general.curve`.
+    #[serde(default)]
+    pub curve: Option<Curve>,
+    /// MIDI control change sent alongside the target, so one slider can drive both.
+    #[serde(default)]
+    pub midi: Option<MidiCc>,
+    /// OSC message sent alongside the target, so one slider can drive both.
+    #[serde(default)]
+    pub osc: Option<OscControl>,
+    /// Lowest raw reading the slider actually reaches, as learned by `gain calibrate`.
+    #[serde(default)]
+    pub min_raw: Option<u16>,
+    /// Highest raw reading the slider actually reaches, as learned by `gain calibrate`.
+    #[serde(default)]
+    pub max_raw: Option<u16>,
+    /// Added to every raw reading before anything else, to line up sliders that report
+    /// different readings at the same physical position.
+    #[serde(default)]
+    pub offset_raw: i16,
+    /// Volume set with the slider all the way down, so the target never fully mutes.
+    #[serde(default)]
+    pub min_scalar: Option<f64>,
+    /// Volume set with the slider all the way up, to keep the target below full volume.
+    #[serde(default)]
+    pub max_scalar: Option<f64>,
+    /// Whether to invert this slider's direction, overriding `general.invert_direction`.
+    #[serde(default)]
+    pub invert: Option<bool>,
+    /// Whether the slider is a motorized fader, moved by the host to follow its target's
+    /// volume when it is changed elsewhere.
+    #[serde(default)]
+    pub motorized: bool,
+}
+
+/// A MIDI control-change controller that sliders can send their level to, scaled to 0-127.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MidiCc {
+    /// MIDI channel, from 1 to 16.
+    pub channel: u8,
+    /// Controller number, from 0 to 127.
+    pub cc: u8,
+}
+
+/// An OSC address that sliders can send their level to, as a float from 0.0 to 1.0.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct OscControl {
+    /// UDP endpoint the message is sent to, e.g. `127.0.0.1:53000`.
+    pub address: String,
+    /// OSC address pattern of the message, e.g. `/cue/1/level`.
+    pub path: String,
+}
+
+impl SliderMappings {
+    /// Returns every MIDI controller this slider sends to, from its target and its `midi`
+    /// option.
+    pub fn midi_ccs(&self) -> impl Iterator<Item = MidiCc> {
+        let target = match self.target {
+            VolumeTarget::Midi(cc) => Some(cc),
+            _ => None,
+        };
+        target.into_iter().chain(self.midi)
+    }
+
+    /// Returns every OSC address this slider sends to, from its target and its `osc`
+    /// option.
+    pub fn osc_controls(&self) -> impl Iterator<Item = &OscControl> {
+        let target = match &self.target {
+            VolumeTarget::Osc(osc) => Some(osc),
+            _ => None,
+        };
+        target.into_iter().chain(self.osc.as_ref())
+    }
+
+    /// Returns the raw travel of the slider, from `min_raw` to `max_raw`, defaulting to the
+    /// full range of the firmware's ADC.
+    pub fn raw_range(&self) -> (u16, u16) {
+        (
+            self.min_raw.unwrap_or(0),
+            self.max_raw.unwrap_or(MAX_RAW_VALUE),
+        )
+    }
+
+    /// Returns whether the slider's direction is inverted, by its own `invert` or else
+    /// `general.invert_direction`.
+    pub fn inverted(&self, general: &General) -> bool {
+        self.invert.unwrap_or(general.invert_direction)
+    }
+
+    /// Returns the slider's response curve, its own `curve` or else `general.curve`. It
+    /// shapes the slider's volume before that is applied to each of its apps.
+    pub fn curve(&self, general: &General) -> Curve {
+        self.curve.unwrap_or(general.curve)
+    }
+
+    /// Returns the volumes the slider's travel is rescaled to, from `min_scalar` to
+    /// `max_scalar`, defaulting to 0.0 to 1.0.
+    pub fn volume_range(&self) -> (f64, f64) {
+        (
+            self.min_scalar.unwrap_or(0.0),
+            self.max_scalar.unwrap_or(1.0),
+        )
+    }
+}
+
+/// How the readings of a slider ID are interpreted.
+#[derive(serde::Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SliderMode {
+    /// A potentiometer reporting its position, from 0 to 1023.
+    #[default]
+    Absolute,
+    /// A rotary encoder reporting ticks, each moving the target's current volume by one
+    /// `volume_step`.
+    Relative,
+}
+
+/// What the button sharing a slider's ID does to the slider's target.
+#[derive(serde::Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ButtonMode {
+    /// Mutes the target for as long as the button is held down.
+    #[default]
+    Momentary,
+    /// Mutes or unmutes the target on every press.
+    Toggle,
+}
+
+/// Response curve mapping a normalized slider position (0.0 to 1.0) to a volume level.
+#[derive(serde::Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Curve {
+    /// Volume follows the slider position directly.
+    #[default]
+    Linear,
+    /// Logarithmic (audio) taper: about 10% volume at half travel, giving finer control
+    /// at low volumes.
+    Log,
+    /// Inverse of the logarithmic taper: about 85% volume at half travel, giving finer
+    /// control at high volumes.
+    Exp,
+    /// Raises the slider position to the given exponent.
+    Gamma(f64),
+}
+
+/// Base of the log taper, sets the ratio between full and half travel levels.
+const CURVE_BASE: f64 = 100.0;
+
+impl Curve {
+    /// Returns whether the curve maps positions to volumes from 0.0 to 1.0, which a gamma
+    /// exponent only does when it is above 0.0 and finite.
+    pub fn is_valid(self) -> bool {
+        match self {
+            Curve::Gamma(gamma) => gamma.is_finite() && gamma > 0.0,
+            _ => true,
+        }
+    }
+
+    /// Applies the curve to a normalized slider position.
+    pub fn apply(self, position: f64) -> f64 {
+        let position = position.clamp(0.0, 1.0);
+        match self {
+            Curve::Linear => position,
+            Curve::Log => (CURVE_BASE.powf(position) - 1.0) / (CURVE_BASE - 1.0),
+            Curve::Exp => (1.0 + position * (CURVE_BASE - 1.0)).log(CURVE_BASE),
+            Curve::Gamma(gamma) => position.powf(gamma),
+        }
+    }
+
+    /// Returns the normalized slider position the curve maps to `level`, undoing
+    /// [`Curve::apply`].
+    pub fn invert(self, level: f64) -> f64 {
+        let level = level.clamp(0.0, 1.0);
+        match self {
+            Curve::Linear => level,
+            Curve::Log => (1.0 + level * (CURVE_BASE - 1.0)).log(CURVE_BASE),
+            Curve::Exp => (CURVE_BASE.powf(level) - 1.0) / (CURVE_BASE - 1.0),
+            Curve::Gamma(gamma) => level.powf(1.0 / gamma),
+        }
+    }
 }
 
 /// Enumeration of possible volume targets for a slider.
-#[derive(serde::Deserialize, Debug, Clone)]
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum VolumeTarget {
     /// Master volume control.
@@ -77,10 +684,53 @@ pub enum VolumeTarget {
     /// Volume control for the currently active application.
     #[serde(rename = "current")]
     CurrentApp,
-    /// Volume control for applications not explicitly mapped.
-    Unmapped,
+    /// Like [`VolumeTarget::CurrentApp`], but the slider's last volume is also applied to
+    /// every application that comes to the front afterwards.
+    Foreground,
+    /// Like [`VolumeTarget::CurrentApp`], but only while the title of the focused window
+    /// contains this text, ignoring case. Tells apart what one process shows in different
+    /// windows, such as browser tabs, although every session of the process is changed.
+    #[serde(rename = "window_title")]
+    WindowTitle(String),
+    /// Volume control for applications not explicitly mapped. Written as `"unmapped"`, or
+    /// as a table to narrow down the applications further; see [`UnmappedApps`] for how
+    /// the lists combine.
+    Unmapped {
+        /// Applications left alone even though no other slider maps them.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        exclude: Vec<AppMatcher>,
+        /// If set, only these applications are controlled.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        include: Option<Vec<AppMatcher>>,
+    },
+    /// Input volume of the default recording device (microphone).
+    #[serde(alias = "mic")]
+    Microphone,
+    /// The Windows system sounds session, which plays notification and alert sounds and
+    /// isn't owned by any one process.
+    #[serde(rename = "system_sounds")]
+    SystemSounds,
     /// Volume control for specific applications.
-    Apps(Vec<String>),
+    Apps(Vec<AppMatcher>),
+    /// One application that is set to the slider's volume while every other one is
+    /// turned down to `duck_to`. Lowering the slider to 0 puts the others back.
+    Solo {
+        app: AppMatcher,
+        /// Volume (0.0 to 1.0) the other applications are ducked to.
+        duck_to: f64,
+    },
+    /// The applications of a group from the configuration. Replaced by the equivalent
+    /// [`VolumeTarget::Apps`] when the configuration is loaded.
+    Group(String),
+    /// Master volume of a specific output device, which doesn't have to be the default one.
+    Device {
+        /// Name of the device as listed by `gain list-devices`, matched ignoring case.
+        name: String,
+    },
+    /// MIDI control change instead of an audio volume.
+    Midi(MidiCc),
+    /// OSC message instead of an audio volume.
+    Osc(OscControl),
 }
 
 impl Default for VolumeTarget {
@@ -89,73 +739,440 @@ impl Default for VolumeTarget {
     }
 }
 
+impl VolumeTarget {
+    /// An `unmapped` target without exclude or include lists.
+    pub fn unmapped() -> Self {
+        VolumeTarget::Unmapped {
+            exclude: Vec::new(),
+            include: None,
+        }
+    }
+
+    /// Parses the name of a built-in target, such as `master`, in any case.
+    pub fn builtin(name: &str) -> Option<Self> {
+        Some(match name.to_lowercase().as_str() {
+            "master" => VolumeTarget::Master,
+            "current" => VolumeTarget::CurrentApp,
+            "unmapped" => VolumeTarget::unmapped(),
+            "microphone" | "mic" => VolumeTarget::Microphone,
+            "system_sounds" => VolumeTarget::SystemSounds,
+            _ => return None,
+        })
+    }
+
+    /// Parses a target written as a single name, as used for the keys of
+    /// [`RestoreOnExit::Volumes`]. Names that aren't a built-in target are app names, or
+    /// aliases replaced by the apps they stand for.
+    pub fn from_name(
+        name: &str,
+        general: &General,
+        aliases: &BTreeMap<String, Alias>,
+    ) -> Result<Self> {
+        if let Some(target) = VolumeTarget::builtin(name) {
+            return Ok(target);
+        }
+        let apps = resolve_aliases(vec![AppMatcher::from(name.to_string())], aliases)?;
+        Ok(VolumeTarget::Apps(
+            apps.into_iter()
+                .map(|app| general.matcher(app))
+                .collect::<Result<_>>()?,
+        ))
+    }
+}
+
+/// Name of the configuration file looked for when no path is given.
+pub const CONFIG_FILE_NAME: &str = "gain.toml";
+
+/// Picks the configuration file: the path given on the command line, then the `GAIN_CONFIG`
+/// environment variable, then `gain.toml` in the working directory, then `gain.toml` in
+/// the platform's configuration directory. Falls back to `gain.toml` in the working
+/// directory if none of the files exist, so the error names the usual location.
+pub fn resolve_path(cli_path: Option<String>) -> String {
+    if let Some(path) = cli_path.or_else(|| std::env::var("GAIN_CONFIG").ok()) {
+        return path;
+    }
+    if Path::new(CONFIG_FILE_NAME).exists() {
+        return CONFIG_FILE_NAME.to_string();
+    }
+    match platform_config_dir().map(|dir| dir.join("gain").join(CONFIG_FILE_NAME)) {
+        Some(path) if path.exists() => path.to_string_lossy().into_owned(),
+        _ => CONFIG_FILE_NAME.to_string(),
+    }
+}
+
+/// Per-user configuration directory: `%APPDATA%` on Windows,
+/// `~/Library/Application Support` on macOS, and `$XDG_CONFIG_HOME` or `~/.config`
+/// elsewhere.
+fn platform_config_dir() -> Option<PathBuf> {
+    let env_dir = |name| std::env::var_os(name).map(PathBuf::from);
+    if cfg!(windows) {
+        env_dir("APPDATA")
+    } else if cfg!(target_os = "macos") {
+        env_dir("HOME").map(|home| home.join("Library/Application Support"))
+    } else {
+        env_dir("XDG_CONFIG_HOME").or_else(|| env_dir("HOME").map(|home| home.join(".config")))
+    }
+}
+
+/// Deserializes a slider target. The derived implementation only reads `unmapped` in its
+/// table form, because it has fields, so its plain name is handled here.
+fn deserialize_target<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<VolumeTarget, D::Error> {
+    use serde::de::{self, IntoDeserializer, value::MapAccessDeserializer};
+
+    struct TargetVisitor;
+
+    impl<'de> de::Visitor<'de> for TargetVisitor {
+        type Value = VolumeTarget;
+
+        fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.write_str("a target name or table")
+        }
+
+        fn visit_str<E: de::Error>(self, name: &str) -> std::result::Result<VolumeTarget, E> {
+            match name {
+                "unmapped" => Ok(VolumeTarget::unmapped()),
+                _ => serde::Deserialize::deserialize(name.into_deserializer()),
+            }
+        }
+
+        fn visit_map<A: de::MapAccess<'de>>(
+            self,
+            map: A,
+        ) -> std::result::Result<VolumeTarget, A::Error> {
+            serde::Deserialize::deserialize(MapAccessDeserializer::new(map))
+        }
+    }
+
+    deserializer.deserialize_any(TargetVisitor)
+}
+
+/// Reads only `general.log_file` from the configuration file, so logging can be set up
+/// before the full configuration is loaded and validated.
+pub fn log_file(filename: &str) -> Result<Option<String>, GainError> {
+    let load = || -> Result<Config> { Ok(toml::from_str(&fs::read_to_string(filename)?)?) };
+    load()
+        .map(|config| config.general.log_file)
+        .map_err(|source| GainError::Config {
+            path: filename.to_string(),
+            source,
+        })
+}
+
+/// Writes calibrated `(min_raw, max_raw)` travels, keyed by slider ID, into every matching
+/// `[[slider]]` entry of the configuration file, profiles included. The rest of the file,
+/// comments and formatting included, is left as it was. Returns the IDs that have no entry
+/// in the file.
+pub fn save_calibration(filename: &str, ranges: &BTreeMap<u8, (u16, u16)>) -> Result<Vec<u8>> {
+    let (contents, missing) = apply_calibration(&fs::read_to_string(filename)?, ranges)?;
+    fs::write(filename, contents)?;
+    Ok(missing)
+}
+
+fn apply_calibration(
+    contents: &str,
+    ranges: &BTreeMap<u8, (u16, u16)>,
+) -> Result<(String, Vec<u8>)> {
+    let mut document: toml_edit::Document = contents.parse()?;
+    let mut found = BTreeSet::new();
+
+    calibrate_slider_tables(document.get_mut("slider"), ranges, &mut found);
+    if let Some(profiles) = document
+        .get_mut("profile")
+        .and_then(toml_edit::Item::as_table_like_mut)
+    {
+        for (_, profile) in profiles.iter_mut() {
+            calibrate_slider_tables(profile.get_mut("slider"), ranges, &mut found);
+        }
+    }
+
+    let missing = ranges.keys().filter(|id| !found.contains(id)).copied();
+    Ok((document.to_string(), missing.collect()))
+}
+
+/// Sets `min_raw` and `max_raw` on every table of a `slider` array whose ID is in `ranges`.
+fn calibrate_slider_tables(
+    sliders: Option<&mut toml_edit::Item>,
+    ranges: &BTreeMap<u8, (u16, u16)>,
+    found: &mut BTreeSet<u8>,
+) {
+    let Some(sliders) = sliders.and_then(toml_edit::Item::as_array_of_tables_mut) else {
+        return;
+    };
+    for slider in sliders.iter_mut() {
+        let id = slider.get("id").and_then(toml_edit::Item::as_integer);
+        if let Some(id) = id.and_then(|id| u8::try_from(id).ok())
+            && let Some(&(min, max)) = ranges.get(&id)
+        {
+            slider["min_raw"] = toml_edit::value(min as i64);
+            slider["max_raw"] = toml_edit::value(max as i64);
+            found.insert(id);
+        }
+    }
+}
+
+/// Name of the profile made of the top-level slider mappings.
+pub const DEFAULT_PROFILE: &str = "default";
+
 /// Loaded configuration with additional runtime data.
 pub struct LoadedConfig {
     /// The general configuration data.
     pub general: General,
     /// The connection configuration data.
     pub connection: Connection,
-    /// Mappings of slider IDs to their respective configurations.
-    pub mappings: HashMap<u8, SliderMappings>,
-    /// List of applications that have specific volume mappings.
-    pub mapped_apps: Vec<String>,
+    /// Connections of the further devices.
+    pub devices: Vec<Connection>,
+    /// Explicit volumes to set on exit, parsed from `general.restore_on_exit`.
+    pub restore_volumes: Vec<(VolumeTarget, f64)>,
+    /// Every profile, the default one first and then the named ones in alphabetical order.
+    profiles: Vec<LoadedProfile>,
+    /// Index of the active profile in `profiles`.
+    active: usize,
     last_modified: std::time::SystemTime,
     last_checked: std::time::Instant,
+    reload_requested: bool,
+}
+
+/// The slider mappings of one profile, with the runtime data derived from them.
+struct LoadedProfile {
+    name: String,
+    /// Mappings of slider IDs to their respective configurations.
+    mappings: HashMap<u8, SliderMappings>,
+    /// List of applications that have specific volume mappings.
+    mapped_apps: Vec<AppMatcher>,
 }
 
 impl LoadedConfig {
     /// Loads the configuration from a specified TOML file.
-    pub fn new_from_file(filename: &str) -> Result<Self> {
-        let config_data = std::fs::read_to_string(filename)?;
-        let config: Config = toml::from_str(&config_data)?;
-        let last_modified = fs::metadata(filename)
-            .and_then(|m| m.modified())
-            .unwrap_or(std::time::SystemTime::now());
-        Ok(LoadedConfig::new(config, last_modified))
+    pub fn new_from_file(filename: &str) -> Result<Self, GainError> {
+        let load = || -> Result<Self> {
+            let config_data = std::fs::read_to_string(filename)?;
+            let config: Config = toml::from_str(&config_data)?;
+            let last_modified = fs::metadata(filename)
+                .and_then(|m| m.modified())
+                .unwrap_or(std::time::SystemTime::now());
+            LoadedConfig::new(config, last_modified)
+        };
+        load().map_err(|source| GainError::Config {
+            path: filename.to_string(),
+            source,
+        })
     }
 
     /// Reloads the configuration from the file if it has been modified since the last load.
-    pub fn reload_if_needed(&mut self, filename: &str) -> Result<()> {
-        if self.should_reload(filename) {
-            let config_data = fs::read_to_string(filename)?;
-            let config: Config = toml::from_str(&config_data)?;
-            *self = LoadedConfig::new(config, self.last_modified);
-            info!("Configuration reloaded from {}", filename);
+    /// Returns whether the connection settings changed, in which case the serial port has
+    /// to be reopened for them to apply. The active profile is kept if it still exists.
+    pub fn reload_if_needed(&mut self, filename: &str) -> Result<bool> {
+        if !self.should_reload(filename) {
+            return Ok(false);
         }
-        Ok(())
+
+        let config_data = fs::read_to_string(filename)?;
+        let config: Config = toml::from_str(&config_data)?;
+        let mut reloaded = LoadedConfig::new(config, self.last_modified)?;
+        if let Some(active) = reloaded.position(self.profile_name()) {
+            reloaded.active = active;
+        }
+        let connection_changed =
+            reloaded.connection != self.connection || reloaded.devices != self.devices;
+        *self = reloaded;
+        info!("Configuration reloaded from {}", filename);
+        Ok(connection_changed)
     }
 
-    fn new(config: Config, last_modified: std::time::SystemTime) -> Self {
-        let mappings: HashMap<u8, SliderMappings> = config
-            .slider
-            .clone()
-            .into_iter()
-            .map(|s| (s.id, s))
-            .collect();
+    /// Makes the next [`LoadedConfig::reload_if_needed`] reload the file even if it looks
+    /// unchanged.
+    pub fn request_reload(&mut self) {
+        self.reload_requested = true;
+    }
 
-        let mapped_apps: Vec<String> = mappings
-            .values()
-            .filter_map(|mapping| {
-                if let VolumeTarget::Apps(apps) = &mapping.target {
-                    Some(apps.clone())
-                } else {
-                    None
-                }
-            })
-            .flatten()
-            .collect();
+    /// Connections of every device to read from, the one in `connection` first.
+    pub fn connections(&self) -> impl Iterator<Item = &Connection> {
+        std::iter::once(&self.connection).chain(&self.devices)
+    }
+
+    /// Mappings of slider IDs to their configuration in the active profile.
+    pub fn mappings(&self) -> &HashMap<u8, SliderMappings> {
+        &self.profiles[self.active].mappings
+    }
+
+    /// Mappings of every profile, including the inactive ones. A slider mapped in several
+    /// profiles comes up once for each.
+    pub fn all_mappings(&self) -> impl Iterator<Item = &SliderMappings> {
+        self.profiles
+            .iter()
+            .flat_map(|profile| profile.mappings.values())
+    }
+
+    /// Applications that have specific volume mappings in the active profile.
+    pub fn mapped_apps(&self) -> &[AppMatcher] {
+        &self.profiles[self.active].mapped_apps
+    }
 
-        LoadedConfig {
+    /// Name of the active profile.
+    pub fn profile_name(&self) -> &str {
+        &self.profiles[self.active].name
+    }
+
+    /// Returns the applications an `unmapped` target with the given lists controls in the
+    /// active profile.
+    pub fn unmapped_apps<'a>(
+        &'a self,
+        exclude: &'a [AppMatcher],
+        include: Option<&'a [AppMatcher]>,
+    ) -> UnmappedApps<'a> {
+        UnmappedApps {
+            mapped: self.mapped_apps(),
+            exclude,
+            include,
+            ignored: &self.general.ignore,
+        }
+    }
+
+    /// Switches to the next profile, wrapping around after the last one.
+    pub fn next_profile(&mut self) {
+        self.active = (self.active + 1) % self.profiles.len();
+        info!("Switched to profile {}", self.profile_name());
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.profiles
+            .iter()
+            .position(|profile| profile.name == name)
+    }
+
+    /// Validates a parsed configuration, as if it had been read from a file last modified at
+    /// `last_modified`.
+    pub fn new(mut config: Config, last_modified: std::time::SystemTime) -> Result<Self> {
+        let step = config.general.volume_step;
+        // Also rejects NaN, which fails every comparison
+        if !(step > 0.0 && step <= 1.0) {
+            return Err(anyhow!(
+                "volume_step must be above 0.0 and at most 1.0, got {}",
+                step
+            ));
+        }
+        let mute_below = config.general.mute_below;
+        if !(0.0..=1.0).contains(&mute_below) {
+            return Err(anyhow!(
+                "mute_below must be from 0.0 to 1.0, got {}",
+                mute_below
+            ));
+        }
+
+        let panic_volume = config.general.panic_volume;
+        if !(0.0..=1.0).contains(&panic_volume) {
+            return Err(anyhow!(
+                "panic_volume must be from 0.0 to 1.0, got {}",
+                panic_volume
+            ));
+        }
+        if !config.general.curve.is_valid() {
+            return Err(anyhow!(
+                "curve gamma must be above 0.0 and finite, got {:?}",
+                config.general.curve
+            ));
+        }
+        if let Some(hotkey) = &config.general.panic_hotkey {
+            Hotkey::parse(hotkey)?;
+        }
+        if let Some(connection) = std::iter::once(&config.connection)
+            .chain(&config.device)
+            .find(|connection| connection.read_timeout_ms == 0)
+        {
+            return Err(anyhow!(
+                "read_timeout_ms must be above 0 for {}",
+                connection.com_port.as_deref().unwrap_or("the scanned port")
+            ));
+        }
+
+        check_unique_ids(DEFAULT_PROFILE, &config.slider)?;
+        for (name, profile) in &config.profile {
+            check_unique_ids(name, &profile.slider)?;
+        }
+
+        check_aliases(&config.alias)?;
+        let sliders = config.slider.iter_mut().chain(
+            config
+                .profile
+                .values_mut()
+                .flat_map(|profile| &mut profile.slider),
+        );
+        for mapping in sliders {
+            resolve_target_aliases(mapping.id, &mut mapping.target, &config.alias)?;
+        }
+        for group in config.group.values_mut() {
+            group.apps = resolve_aliases(std::mem::take(&mut group.apps), &config.alias)?;
+        }
+        config.general.ignore =
+            resolve_aliases(std::mem::take(&mut config.general.ignore), &config.alias)?;
+
+        let match_mode = config.general.match_mode;
+        config.general.ignore = std::mem::take(&mut config.general.ignore)
+            .into_iter()
+            .map(|app| app.with_mode(match_mode))
+            .collect::<Result<_>>()?;
+        let mut profiles = vec![LoadedProfile::new(
+            DEFAULT_PROFILE,
+            config.slider.clone(),
+            &config.group,
+            &config.general,
+        )?];
+        for (name, profile) in &config.profile {
+            if name == DEFAULT_PROFILE {
+                return Err(anyhow!(
+                    "Profile name {:?} is reserved for the top-level sliders",
+                    DEFAULT_PROFILE
+                ));
+            }
+            // Later entries win when collected, so the profile overrides the top level
+            let sliders = config.slider.iter().chain(&profile.slider).cloned();
+            profiles.push(LoadedProfile::new(
+                name,
+                sliders,
+                &config.group,
+                &config.general,
+            )?);
+        }
+
+        let mut loaded = LoadedConfig {
             general: config.general,
             connection: config.connection,
-            mappings,
-            mapped_apps,
+            devices: config.device,
+            restore_volumes: Vec::new(),
+            profiles,
+            active: 0,
             last_modified,
             last_checked: Instant::now(),
+            reload_requested: false,
+        };
+
+        if let Some(name) = &loaded.general.profile {
+            loaded.active = loaded
+                .position(name)
+                .ok_or_else(|| anyhow!("Unknown profile {:?}", name))?;
         }
+
+        loaded.restore_volumes = match &loaded.general.restore_on_exit {
+            Some(RestoreOnExit::Volumes(volumes)) => volumes
+                .iter()
+                .map(|(name, &volume)| {
+                    let target = VolumeTarget::from_name(name, &loaded.general, &config.alias)?;
+                    Ok((target, volume))
+                })
+                .collect::<Result<_>>()?,
+            _ => Vec::new(),
+        };
+
+        Ok(loaded)
     }
 
     fn should_reload(&mut self, filename: &str) -> bool {
+        if std::mem::take(&mut self.reload_requested) {
+            return true;
+        }
+
         let now = Instant::now();
         // Throttle checks to once every 2 seconds
         if now.duration_since(self.last_checked).as_secs() < 2 {
@@ -176,3 +1193,739 @@ impl LoadedConfig {
         }
     }
 }
+
+impl LoadedProfile {
+    /// Validates and compiles `sliders`, resolving `group` targets from `groups`. When a
+    /// slider ID appears more than once, the last mapping wins, which is how a profile
+    /// overrides the top-level sliders.
+    fn new(
+        name: &str,
+        sliders: impl IntoIterator<Item = SliderMappings>,
+        groups: &BTreeMap<String, Group>,
+        general: &General,
+    ) -> Result<Self> {
+        let mut mappings: HashMap<u8, SliderMappings> =
+            sliders.into_iter().map(|s| (s.id, s)).collect();
+
+        for mapping in mappings.values_mut() {
+            if let VolumeTarget::Group(group) = &mapping.target {
+                let apps = &groups
+                    .get(group)
+                    .ok_or_else(|| anyhow!("Slider {}: unknown group {:?}", mapping.id, group))?
+                    .apps;
+                mapping.target = VolumeTarget::Apps(apps.clone());
+            }
+            let (min_raw, max_raw) = mapping.raw_range();
+            if min_raw >= max_raw || max_raw > MAX_RAW_VALUE {
+                return Err(anyhow!(
+                    "Slider {}: min_raw must be below max_raw, and max_raw at most {}",
+                    mapping.id,
+                    MAX_RAW_VALUE
+                ));
+            }
+            let (min_scalar, max_scalar) = mapping.volume_range();
+            if !(0.0 <= min_scalar && min_scalar < max_scalar && max_scalar <= 1.0) {
+                return Err(anyhow!(
+                    "Slider {}: min_scalar must be below max_scalar, both from 0.0 to 1.0",
+                    mapping.id
+                ));
+            }
+            if let Some(curve) = mapping.curve
+                && !curve.is_valid()
+            {
+                return Err(anyhow!(
+                    "Slider {}: curve gamma must be above 0.0 and finite, got {:?}",
+                    mapping.id,
+                    curve
+                ));
+            }
+            for midi in mapping.midi_ccs() {
+                if !(1..=16).contains(&midi.channel) || midi.cc > 127 {
+                    return Err(anyhow!(
+                        "Slider {}: MIDI channel must be 1-16 and cc 0-127",
+                        mapping.id
+                    ));
+                }
+            }
+            for osc in mapping.osc_controls() {
+                if !osc.path.starts_with('/') {
+                    return Err(anyhow!(
+                        "Slider {}: OSC path `{}` must start with `/`",
+                        mapping.id,
+                        osc.path
+                    ));
+                }
+            }
+            if let VolumeTarget::WindowTitle(title) = &mapping.target
+                && title.is_empty()
+            {
+                return Err(anyhow!(
+                    "Slider {}: window_title must not be empty",
+                    mapping.id
+                ));
+            }
+            if let VolumeTarget::Solo { duck_to, .. } = mapping.target
+                && !(0.0..=1.0).contains(&duck_to)
+            {
+                return Err(anyhow!(
+                    "Slider {}: duck_to must be from 0.0 to 1.0",
+                    mapping.id
+                ));
+            }
+            let with_mode = |apps: &mut Vec<AppMatcher>| -> Result<()> {
+                *apps = std::mem::take(apps)
+                    .into_iter()
+                    .map(|app| general.matcher(app))
+                    .collect::<Result<_>>()?;
+                Ok(())
+            };
+            match &mut mapping.target {
+                VolumeTarget::Apps(apps) => with_mode(apps)?,
+                VolumeTarget::Solo { app, .. } => *app = general.matcher(app.clone())?,
+                VolumeTarget::Unmapped { exclude, include } => {
+                    with_mode(exclude)?;
+                    if let Some(include) = include {
+                        with_mode(include)?;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mapped_apps: Vec<AppMatcher> = mappings
+            .values()
+            .filter_map(|mapping| match &mapping.target {
+                VolumeTarget::Apps(apps) => Some(apps.clone()),
+                VolumeTarget::Solo { app, .. } => Some(vec![app.clone()]),
+                _ => None,
+            })
+            .flatten()
+            .collect();
+
+        warn_overlapping_targets(name, &mappings);
+
+        Ok(LoadedProfile {
+            name: name.to_string(),
+            mappings,
+            mapped_apps,
+        })
+    }
+}
+
+/// Rejects a list of sliders, the top-level one or a profile's, that maps an ID more than
+/// once, since only the last mapping would be used.
+fn check_unique_ids(profile: &str, sliders: &[SliderMappings]) -> Result<()> {
+    let mut seen = BTreeSet::new();
+    match sliders.iter().find(|mapping| !seen.insert(mapping.id)) {
+        Some(mapping) => Err(anyhow!(
+            "Slider {} is mapped more than once in profile {}",
+            mapping.id,
+            profile
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Warns about sliders that control the same target. They don't break anything, but each
+/// one overrides the others whenever it moves, which is rarely intended.
+fn warn_overlapping_targets(profile: &str, mappings: &HashMap<u8, SliderMappings>) {
+    let mut sliders: Vec<&SliderMappings> = mappings.values().collect();
+    sliders.sort_by_key(|mapping| mapping.id);
+
+    for (i, first) in sliders.iter().enumerate() {
+        for second in &sliders[i + 1..] {
+            if let Some(shared) = shared_target(&first.target, &second.target) {
+                warn!(
+                    "Sliders {} and {} both control {} in profile {}; whichever moved last wins",
+                    first.id, second.id, shared, profile
+                );
+            }
+        }
+    }
+}
+
+/// Describes the part of the two targets that overlaps, if any.
+fn shared_target(first: &VolumeTarget, second: &VolumeTarget) -> Option<String> {
+    match (first, second) {
+        (VolumeTarget::Master, VolumeTarget::Master) => Some("master".into()),
+        (VolumeTarget::CurrentApp, VolumeTarget::CurrentApp) => Some("current".into()),
+        (VolumeTarget::Foreground, VolumeTarget::Foreground) => Some("foreground".into()),
+        (VolumeTarget::WindowTitle(first), VolumeTarget::WindowTitle(second))
+            if first.eq_ignore_ascii_case(second) =>
+        {
+            Some(format!("windows titled {:?}", first))
+        }
+        (VolumeTarget::Unmapped { .. }, VolumeTarget::Unmapped { .. }) => Some("unmapped".into()),
+        (VolumeTarget::Microphone, VolumeTarget::Microphone) => Some("microphone".into()),
+        (VolumeTarget::SystemSounds, VolumeTarget::SystemSounds) => Some("system sounds".into()),
+        (VolumeTarget::Device { name: first }, VolumeTarget::Device { name: second })
+            if first.eq_ignore_ascii_case(second) =>
+        {
+            Some(format!("device {}", first))
+        }
+        (VolumeTarget::Midi(first), VolumeTarget::Midi(second)) if first == second => {
+            Some(format!("MIDI channel {} cc {}", first.channel, first.cc))
+        }
+        (VolumeTarget::Osc(first), VolumeTarget::Osc(second)) if first == second => {
+            Some(format!("OSC {} at {}", first.path, first.address))
+        }
+        (VolumeTarget::Apps(first), VolumeTarget::Apps(second)) => {
+            let shared: Vec<String> = first
+                .iter()
+                .filter(|app| {
+                    second
+                        .iter()
+                        .any(|other| app.pattern().eq_ignore_ascii_case(other.pattern()))
+                })
+                .map(|app| app.to_string())
+                .collect();
+            (!shared.is_empty()).then(|| shared.join(", "))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(general: &str) -> Result<LoadedConfig> {
+        let config: Config = toml::from_str(&format!("[general]\n{}", general)).unwrap();
+        LoadedConfig::new(config, std::time::SystemTime::now())
+    }
+
+    fn load_toml(toml: &str) -> Result<LoadedConfig> {
+        let config: Config = toml::from_str(toml).unwrap();
+        LoadedConfig::new(config, std::time::SystemTime::now())
+    }
+
+    #[test]
+    fn minimal_configs_get_the_defaults() {
+        let defaults = General::default();
+        for toml in [
+            "[[slider]]\nid = 0\ntarget = \"master\"",
+            "[general]\nramp_ms = 50",
+        ] {
+            let config = load_toml(toml).unwrap();
+            assert_eq!(config.general.volume_step, defaults.volume_step);
+            assert_eq!(config.general.volume_step, 0.01);
+            assert!(!config.general.invert_direction);
+            assert_eq!(config.general.fader_threshold, defaults.fader_threshold);
+            assert_eq!(config.general.backend, AudioBackend::Auto);
+            assert_eq!(config.connection, Connection::default());
+            assert_eq!(config.connection.baud_rate, 57600);
+        }
+    }
+
+    #[test]
+    fn backends_are_named_in_lowercase() {
+        let config = load("backend = \"pulseaudio\"").unwrap();
+        assert_eq!(config.general.backend, AudioBackend::PulseAudio);
+        let config = load("backend = \"alsa\"").unwrap();
+        assert_eq!(config.general.backend, AudioBackend::Alsa);
+        assert!(toml::from_str::<Config>("[general]\nbackend = \"jack\"").is_err());
+    }
+
+    #[test]
+    fn calibration_is_written_to_matching_sliders() {
+        let contents = r#"
+            # Master on the first slider
+            [[slider]]
+            id = 0
+            target = "master"
+            min_raw = 5
+
+            [[slider]]
+            id = 1 # mic
+            target = "mic"
+
+            [[profile.gaming.slider]]
+            id = 0
+            target = "current"
+        "#;
+        let ranges = BTreeMap::from([(0, (30, 1000)), (2, (10, 990))]);
+        let (written, missing) = apply_calibration(contents, &ranges).unwrap();
+
+        assert_eq!(missing, vec![2]);
+        assert!(written.contains("# Master on the first slider"));
+        assert!(written.contains("id = 1 # mic"));
+        let config = load_toml(&written).unwrap();
+        assert_eq!(config.mappings()[&0].raw_range(), (30, 1000));
+        assert_eq!(config.mappings()[&1].raw_range(), (0, 1023));
+        let parsed: Config = toml::from_str(&written).unwrap();
+        assert_eq!(parsed.profile["gaming"].slider[0].raw_range(), (30, 1000));
+    }
+
+    #[test]
+    fn device_targets_name_their_device() {
+        let config = load_toml(
+            r#"
+            [[slider]]
+            id = 5
+            target = { device = { name = "Headphones (USB DAC)" } }
+            "#,
+        )
+        .unwrap();
+        assert!(matches!(
+            &config.mappings()[&5].target,
+            VolumeTarget::Device { name } if name == "Headphones (USB DAC)"
+        ));
+    }
+
+    #[test]
+    fn system_sounds_are_a_target_of_their_own() {
+        let config = load_toml(
+            r#"
+            [[slider]]
+            id = 2
+            target = "system_sounds"
+            "#,
+        )
+        .unwrap();
+        assert!(matches!(
+            config.mappings()[&2].target,
+            VolumeTarget::SystemSounds
+        ));
+        assert!(matches!(
+            VolumeTarget::builtin("System_Sounds"),
+            Some(VolumeTarget::SystemSounds)
+        ));
+    }
+
+    #[test]
+    fn osc_controls_come_from_the_target_and_the_option() {
+        let config = load_toml(
+            r#"
+            [[slider]]
+            id = 0
+            target = { osc = { address = "127.0.0.1:53000", path = "/cue/1/level" } }
+
+            [[slider]]
+            id = 1
+            target = "master"
+            osc = { address = "127.0.0.1:8000", path = "/fader/1" }
+            "#,
+        )
+        .unwrap();
+        let paths = |id| -> Vec<String> {
+            config.mappings()[&id]
+                .osc_controls()
+                .map(|osc| osc.path.clone())
+                .collect()
+        };
+        assert_eq!(paths(0), ["/cue/1/level"]);
+        assert_eq!(paths(1), ["/fader/1"]);
+
+        assert!(
+            load_toml(
+                r#"
+                [[slider]]
+                id = 0
+                target = { osc = { address = "127.0.0.1:53000", path = "cue/1" } }
+                "#,
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn unmapped_targets_combine_their_lists_with_mapped_apps() {
+        let config = load_toml(
+            r#"
+            [[slider]]
+            id = 0
+            target = { apps = ["game.exe"] }
+
+            [[slider]]
+            id = 1
+            target = { unmapped = { exclude = ["Discord"], include = ["game", "discord", "spotify"] } }
+            "#,
+        )
+        .unwrap();
+        let VolumeTarget::Unmapped { exclude, include } = &config.mappings()[&1].target else {
+            panic!("slider 1 should be unmapped");
+        };
+        let unmapped = config.unmapped_apps(exclude, include.as_deref());
+
+        assert!(unmapped.matches("spotify.exe"));
+        // Mapped by another slider, excluded, and not included, respectively
+        assert!(!unmapped.matches("game.exe"));
+        assert!(!unmapped.matches("discord.exe"));
+        assert!(!unmapped.matches("firefox.exe"));
+
+        let everything = config.unmapped_apps(&[], None);
+        assert!(everything.matches("firefox.exe"));
+        assert!(!everything.matches("game.exe"));
+    }
+
+    #[test]
+    fn example_config_loads() {
+        let mut config = load_toml(include_str!("../gain.example.toml")).unwrap();
+        let mut ids: Vec<u8> = config.mappings().keys().copied().collect();
+        ids.sort();
+        assert_eq!(ids, [0, 1, 2, 3, 4, 6]);
+        assert_eq!(config.general.profile_button, Some(5));
+
+        config.next_profile();
+        assert_eq!(config.profile_name(), "gaming");
+        let VolumeTarget::Apps(apps) = &config.mappings()[&2].target else {
+            panic!("slider 2 should target apps");
+        };
+        assert_eq!(apps[1].weighted(1.0), 0.5);
+    }
+
+    #[test]
+    fn apps_take_optional_weights() {
+        let config = load_toml(
+            r#"
+            [[slider]]
+            id = 0
+            target = { apps = ["game", { name = "discord", weight = 0.5 }, { name = "spotify" }] }
+            "#,
+        )
+        .unwrap();
+        let VolumeTarget::Apps(apps) = &config.mappings()[&0].target else {
+            panic!("slider 0 should target apps");
+        };
+        let weighted: Vec<f64> = apps.iter().map(|app| app.weighted(0.8)).collect();
+        assert_eq!(weighted, [0.8, 0.4, 0.8]);
+
+        assert!(
+            toml::from_str::<Config>(
+                r#"
+                [[slider]]
+                id = 0
+                target = { apps = [{ name = "discord", weight = 1.5 }] }
+                "#,
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn ignored_processes_are_never_targeted() {
+        let config = load_toml(
+            r#"
+            [general]
+            match_mode = "contains"
+            ignore = ["anticheat"]
+
+            [[slider]]
+            id = 0
+            target = { apps = ["game"] }
+
+            [[slider]]
+            id = 1
+            target = "unmapped"
+            "#,
+        )
+        .unwrap();
+        let VolumeTarget::Apps(apps) = &config.mappings()[&0].target else {
+            panic!("slider 0 should target apps");
+        };
+        assert!(apps[0].matches("game.exe"));
+        assert!(!apps[0].matches("game-anticheat.exe"));
+
+        let unmapped = config.unmapped_apps(&[], None);
+        assert!(unmapped.matches("spotify.exe"));
+        assert!(!unmapped.matches("anticheat-service.exe"));
+    }
+
+    #[test]
+    fn group_targets_control_the_group_apps() {
+        let config = load_toml(
+            r#"
+            [group.browsers]
+            apps = ["firefox", "chrome"]
+
+            [[slider]]
+            id = 0
+            target = { group = "browsers" }
+            "#,
+        )
+        .unwrap();
+        let VolumeTarget::Apps(apps) = &config.mappings()[&0].target else {
+            panic!("slider 0 should control the group apps");
+        };
+        let patterns: Vec<&str> = apps.iter().map(|app| app.pattern()).collect();
+        assert_eq!(patterns, ["firefox", "chrome"]);
+        assert!(!config.unmapped_apps(&[], None).matches("chrome.exe"));
+
+        let unknown = load_toml(
+            r#"
+            [[slider]]
+            id = 0
+            target = { group = "comms" }
+            "#,
+        );
+        assert!(unknown.is_err());
+    }
+
+    #[test]
+    fn aliases_stand_for_their_apps() {
+        let config = load_toml(
+            r#"
+            [general]
+            restore_on_exit = { music = 0.4, master = 0.8 }
+
+            [alias]
+            music = ["spotify.exe", "foobar"]
+            foobar = "foobar2000.exe"
+            chat = "Discord.exe"
+
+            [group.comms]
+            apps = ["chat", "Teams.exe"]
+
+            [[slider]]
+            id = 0
+            target = { apps = [{ name = "music", weight = 0.5 }, "vlc.exe"] }
+
+            [[slider]]
+            id = 1
+            target = { group = "comms" }
+
+            [[slider]]
+            id = 2
+            target = { solo = { app = "chat", duck_to = 0.2 } }
+            "#,
+        )
+        .unwrap();
+        let patterns = |id| {
+            let VolumeTarget::Apps(apps) = &config.mappings()[&id].target else {
+                panic!("slider {} should target apps", id);
+            };
+            apps.iter()
+                .map(|app| (app.pattern().to_string(), app.weighted(1.0)))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            patterns(0),
+            [
+                ("spotify.exe".into(), 0.5),
+                ("foobar2000.exe".into(), 0.5),
+                ("vlc.exe".into(), 1.0)
+            ]
+        );
+        assert_eq!(
+            patterns(1),
+            [("Discord.exe".into(), 1.0), ("Teams.exe".into(), 1.0)]
+        );
+        let VolumeTarget::Solo { app, .. } = &config.mappings()[&2].target else {
+            panic!("slider 2 should be a solo slider");
+        };
+        assert_eq!(app.pattern(), "Discord.exe");
+
+        let mut restored = config
+            .restore_volumes
+            .iter()
+            .map(|(target, volume)| match target {
+                VolumeTarget::Apps(apps) => {
+                    (apps.iter().map(AppMatcher::pattern).collect(), *volume)
+                }
+                _ => (vec!["master"], *volume),
+            })
+            .collect::<Vec<_>>();
+        restored.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            restored,
+            [
+                (vec!["master"], 0.8),
+                (vec!["spotify.exe", "foobar2000.exe"], 0.4)
+            ]
+        );
+    }
+
+    #[test]
+    fn alias_cycles_and_empty_aliases_are_rejected() {
+        let cycle = load_toml(
+            r#"
+            [alias]
+            music = ["spotify.exe", "tunes"]
+            tunes = "music"
+            "#,
+        );
+        assert!(
+            cycle
+                .err()
+                .unwrap()
+                .to_string()
+                .contains("music -> tunes -> music")
+        );
+
+        let empty = load_toml(
+            r#"
+            [alias]
+            music = []
+            "#,
+        );
+        assert!(empty.is_err());
+
+        let solo = load_toml(
+            r#"
+            [alias]
+            music = ["spotify.exe", "foobar2000.exe"]
+
+            [[slider]]
+            id = 0
+            target = { solo = { app = "music", duck_to = 0.2 } }
+            "#,
+        );
+        assert!(solo.is_err());
+    }
+
+    const PROFILES: &str = r#"
+        [general]
+        volume_step = 0.01
+        invert_direction = false
+
+        [[slider]]
+        id = 0
+        target = "master"
+
+        [[slider]]
+        id = 1
+        target = "unmapped"
+
+        [[profile.gaming.slider]]
+        id = 1
+        target = { apps = ["game.exe"] }
+
+        [[profile.work.slider]]
+        id = 2
+        target = "mic"
+    "#;
+
+    #[test]
+    fn profiles_override_top_level_sliders() {
+        let mut config = load_toml(PROFILES).unwrap();
+        assert_eq!(config.profile_name(), DEFAULT_PROFILE);
+        assert!(matches!(
+            config.mappings()[&1].target,
+            VolumeTarget::Unmapped { .. }
+        ));
+        assert!(config.mapped_apps().is_empty());
+
+        config.next_profile();
+        assert_eq!(config.profile_name(), "gaming");
+        assert!(matches!(config.mappings()[&0].target, VolumeTarget::Master));
+        assert!(matches!(
+            config.mappings()[&1].target,
+            VolumeTarget::Apps(_)
+        ));
+        assert_eq!(config.mapped_apps().len(), 1);
+    }
+
+    #[test]
+    fn rejects_sliders_mapped_twice() {
+        let twice = |profile: &str| {
+            let sliders = "id = 3\ntarget = \"master\"\n";
+            let toml = format!("[[{profile}slider]]\n{sliders}[[{profile}slider]]\n{sliders}");
+            load_toml(&toml).err().unwrap().to_string()
+        };
+        assert_eq!(
+            twice(""),
+            "Slider 3 is mapped more than once in profile default"
+        );
+        assert_eq!(
+            twice("profile.gaming."),
+            "Slider 3 is mapped more than once in profile gaming"
+        );
+    }
+
+    #[test]
+    fn next_profile_wraps_around() {
+        let mut config = load_toml(PROFILES).unwrap();
+        let names: Vec<String> = (0..4)
+            .map(|_| {
+                config.next_profile();
+                config.profile_name().to_string()
+            })
+            .collect();
+        assert_eq!(names, ["gaming", "work", "default", "gaming"]);
+    }
+
+    #[test]
+    fn startup_profile_must_exist() {
+        let with_profile = |name: &str| {
+            PROFILES.replace(
+                "invert_direction = false",
+                &format!("invert_direction = false\nprofile = \"{}\"", name),
+            )
+        };
+        assert_eq!(
+            load_toml(&with_profile("work")).unwrap().profile_name(),
+            "work"
+        );
+        assert!(load_toml(&with_profile("party")).is_err());
+    }
+
+    #[test]
+    fn accepts_volume_steps_up_to_one() {
+        assert!(load("volume_step = 0.01\ninvert_direction = false").is_ok());
+        assert!(load("volume_step = 1.0\ninvert_direction = false").is_ok());
+    }
+
+    #[test]
+    fn rejects_zero_volume_step() {
+        assert!(load("volume_step = 0.0\ninvert_direction = false").is_err());
+    }
+
+    #[test]
+    fn rejects_zero_read_timeouts() {
+        assert!(load_toml("[connection]\nread_timeout_ms = 250").is_ok());
+        assert!(load_toml("[connection]\nread_timeout_ms = 0").is_err());
+        assert!(load_toml("[[device]]\ncom_port = \"COM4\"\nread_timeout_ms = 0").is_err());
+    }
+
+    #[test]
+    fn rejects_gammas_that_arent_positive() {
+        assert!(load_toml("[general]\ncurve = { gamma = 2.0 }").is_ok());
+        for gamma in ["0.0", "-1.0", "nan"] {
+            let general = format!("[general]\ncurve = {{ gamma = {} }}", gamma);
+            let error = load_toml(&general).err().unwrap().to_string();
+            assert!(
+                error.starts_with("curve gamma must be above 0.0"),
+                "{}",
+                error
+            );
+
+            let slider = format!(
+                "[[slider]]\nid = 0\ntarget = \"master\"\ncurve = {{ gamma = {} }}",
+                gamma
+            );
+            let error = load_toml(&slider).err().unwrap().to_string();
+            assert!(error.starts_with("Slider 0: curve gamma"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn rejects_negative_volume_step() {
+        assert!(load("volume_step = -0.05\ninvert_direction = false").is_err());
+    }
+
+    #[test]
+    fn mute_below_mutes_low_volumes() {
+        let config =
+            load("volume_step = 0.01\ninvert_direction = false\nmute_below = 0.02").unwrap();
+        assert!(config.general.mutes_at(0.0));
+        assert!(config.general.mutes_at(0.01));
+        assert!(!config.general.mutes_at(0.02));
+        assert!(load("volume_step = 0.01\ninvert_direction = false\nmute_below = 1.5").is_err());
+    }
+
+    #[test]
+    fn zero_only_mutes_with_mute_on_zero() {
+        let config = load("volume_step = 0.01\ninvert_direction = false").unwrap();
+        assert!(!config.general.mutes_at(0.0));
+        let config =
+            load("volume_step = 0.01\ninvert_direction = false\nmute_on_zero = true").unwrap();
+        assert!(config.general.mutes_at(0.0));
+        assert!(!config.general.mutes_at(0.01));
+    }
+
+    #[test]
+    fn rejects_volume_steps_above_one() {
+        assert!(load("volume_step = 1.5\ninvert_direction = false").is_err());
+        assert!(load("volume_step = nan\ninvert_direction = false").is_err());
+    }
+}
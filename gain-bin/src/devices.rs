@@ -0,0 +1,733 @@
+use crate::{config::Connection, error::GainError, read_frame, stats::Stats, throttle::Throttle};
+#[cfg(not(feature = "tokio"))]
+use crate::{get_port, shutdown::Shutdown};
+use anyhow::Result;
+use gain_lib::{Hello, HostMessage, MAX_FRAME_LEN, Message, PROTOCOL_VERSION, Ping, Slider};
+use log::{debug, error, info, trace, warn};
+use serialport::SerialPort;
+use std::{
+    collections::BTreeMap,
+    io::{BufReader, Write},
+    time::{Duration, Instant},
+};
+#[cfg(not(feature = "tokio"))]
+use std::{
+    sync::{
+        Arc,
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+    },
+    thread::{self, JoinHandle},
+};
+
+/// Delay before the first attempt to reconnect to a serial device.
+pub const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+/// Upper bound for the exponential backoff between reconnect attempts.
+pub const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+/// Time without any frame, heartbeats included, after which a device is reported as
+/// stalled. The firmware sends a heartbeat every second.
+pub const STALL_TIMEOUT: Duration = Duration::from_secs(3);
+/// Time DTR is held low when the watchdog resets the port.
+pub const DTR_PULSE: Duration = Duration::from_millis(100);
+/// Frames in a row that must fail to decode before the likely cause is pointed out.
+const DECODE_HINT_FAILURES: u32 = 20;
+/// Events queued for the main loop before slider positions start being coalesced.
+pub const EVENT_QUEUE_LEN: usize = 64;
+/// Time between pings to a connected device.
+const PING_INTERVAL: Duration = Duration::from_secs(5);
+/// Time a device has to answer a ping before it is reported as not answering.
+const PONG_TIMEOUT: Duration = Duration::from_secs(2);
+/// Time a port found by scanning has to answer a ping before the next one is tried. Long
+/// enough for boards that reset when the port is opened to boot.
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+/// Time between pings while probing a port, since pings sent while the board boots are lost.
+pub const PROBE_RETRY: Duration = Duration::from_millis(250);
+/// Serial read timeout while slider positions wait for room in the queue, so they are
+/// retried soon even if the device goes quiet.
+#[cfg(not(feature = "tokio"))]
+const HELD_RETRY: Duration = Duration::from_millis(10);
+
+/// Something that happened on one of the devices, which are numbered in the order of
+/// [`crate::config::LoadedConfig::connections`].
+pub enum DeviceEvent {
+    /// The device was connected, with a handle to write frames to it.
+    Connected(usize, Box<dyn Write + Send>),
+    /// A device sent a message, with its IDs already offset by the device's `id_offset`.
+    Message(Message),
+    /// The connection was lost, and its reader is reconnecting.
+    Disconnected(usize),
+}
+
+/// Where the main loop receives the device events from: the reader threads of [`Devices`],
+/// or the reader tasks of the tokio runtime.
+pub trait DeviceEvents {
+    /// Waits up to `timeout` for the next event from any device.
+    fn recv(&mut self, timeout: Duration) -> Option<DeviceEvent>;
+}
+
+/// Serial devices read on a thread each, so every device connects, stalls and reconnects
+/// independently while their messages are handled in order by the main loop, and slow
+/// volume calls don't hold up serial reads. The readers are stopped when this is dropped.
+///
+/// The queue between them is bounded: while it is full, each reader keeps only the newest
+/// position of every slider and sends those once there is room again, so a busy main loop
+/// catches up on where the sliders are instead of where they were.
+#[cfg(not(feature = "tokio"))]
+pub struct Devices {
+    events: Receiver<DeviceEvent>,
+    stop: Shutdown,
+    readers: Vec<JoinHandle<()>>,
+}
+
+#[cfg(not(feature = "tokio"))]
+impl Devices {
+    /// Starts reading from every device in `connections`.
+    pub fn spawn<'a>(
+        connections: impl IntoIterator<Item = &'a Connection>,
+        stats: &Arc<Stats>,
+    ) -> Self {
+        let (sender, events) = mpsc::sync_channel(EVENT_QUEUE_LEN);
+        let stop = Shutdown::default();
+        let readers = connections
+            .into_iter()
+            .enumerate()
+            .map(|(index, connection)| {
+                let reader = DeviceReader {
+                    index,
+                    connection: connection.clone(),
+                    events: sender.clone(),
+                    stop: stop.clone(),
+                    stats: stats.clone(),
+                };
+                thread::spawn(move || reader.run())
+            })
+            .collect();
+
+        Devices {
+            events,
+            stop,
+            readers,
+        }
+    }
+}
+
+#[cfg(not(feature = "tokio"))]
+impl DeviceEvents for Devices {
+    fn recv(&mut self, timeout: Duration) -> Option<DeviceEvent> {
+        // The readers only stop when asked to, so this can only time out
+        self.events.recv_timeout(timeout).ok()
+    }
+}
+
+#[cfg(not(feature = "tokio"))]
+impl Drop for Devices {
+    fn drop(&mut self) {
+        self.stop.request();
+        // Readers waiting for room in the queue give up once nothing receives from it
+        drop(std::mem::replace(&mut self.events, mpsc::sync_channel(0).1));
+        for reader in self.readers.drain(..) {
+            let _ = reader.join();
+        }
+    }
+}
+
+/// Connects to one device, forwards its messages and reconnects it with an exponential
+/// backoff whenever the connection is lost.
+#[cfg(not(feature = "tokio"))]
+struct DeviceReader {
+    index: usize,
+    connection: Connection,
+    events: SyncSender<DeviceEvent>,
+    stop: Shutdown,
+    stats: Arc<Stats>,
+}
+
+#[cfg(not(feature = "tokio"))]
+impl DeviceReader {
+    fn run(self) {
+        let mut reconnects = Reconnects::default();
+
+        while !self.stop.requested() {
+            match get_port(&self.connection) {
+                Ok(port) => {
+                    reconnects.connected();
+                    let name = port
+                        .name()
+                        .unwrap_or_else(|| "the serial device".to_string());
+                    let result = self.read(port, &name);
+                    if self.stop.requested()
+                        || self
+                            .events
+                            .send(DeviceEvent::Disconnected(self.index))
+                            .is_err()
+                    {
+                        return;
+                    }
+                    if reconnects.lost(result, &name, &self.stats) {
+                        continue;
+                    }
+                }
+                Err(e) => reconnects.failed(&e),
+            }
+
+            if self.stop.sleep(reconnects.next_delay()) {
+                return;
+            }
+        }
+    }
+
+    /// Reads frames from `port`, called `name` in logs, until the connection fails, the
+    /// readers are stopped or the watchdog finds the port silent. The port is closed on
+    /// return.
+    fn read(&self, mut port: Box<dyn SerialPort>, name: &str) -> Result<()> {
+        send_frame(port.as_mut(), &HELLO)?;
+        let writer = port.try_clone()?;
+        if self
+            .events
+            .send(DeviceEvent::Connected(self.index, Box::new(writer)))
+            .is_err()
+        {
+            return Ok(());
+        }
+
+        let mut link = DeviceLink::new(&self.connection, name, &self.stats);
+        let mut reader = BufReader::new(port);
+        let mut buffer = Vec::new();
+        let mut timeout = self.connection.read_timeout();
+
+        while !self.stop.requested() {
+            if let Some(ping) = link.ping_due(Instant::now()) {
+                send_frame(reader.get_mut().as_mut(), &ping)?;
+            }
+            if !self.send_held(&mut link) {
+                return Ok(());
+            }
+            let wanted_timeout = if link.is_holding() {
+                HELD_RETRY
+            } else {
+                self.connection.read_timeout()
+            };
+            if wanted_timeout != timeout {
+                reader.get_mut().set_timeout(wanted_timeout)?;
+                timeout = wanted_timeout;
+            }
+
+            if let Some(frame) = read_frame(&mut reader, &mut buffer)? {
+                if let Some(message) = link.frame(frame)
+                    && self.events.send(DeviceEvent::Message(message)).is_err()
+                {
+                    return Ok(());
+                }
+                if !self.send_held(&mut link) {
+                    return Ok(());
+                }
+            }
+
+            if link.check(Instant::now()) {
+                reset_port(reader.get_mut().as_mut());
+                return Ok(());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Queues the slider positions `link` holds while there is room, see
+    /// [`DeviceLink::send_held`]. Returns false once the main loop is gone.
+    fn send_held(&self, link: &mut DeviceLink) -> bool {
+        link.send_held(|event| match self.events.try_send(event) {
+            Ok(()) => Queued::Sent,
+            Err(TrySendError::Full(_)) => Queued::Full,
+            Err(TrySendError::Disconnected(_)) => Queued::Closed,
+        })
+    }
+}
+
+/// Hello sent whenever a port is opened, which asks firmware that didn't reset when the port
+/// was opened to announce its version.
+pub const HELLO: HostMessage = HostMessage::Hello(Hello {
+    version: PROTOCOL_VERSION,
+});
+
+/// Reconnect bookkeeping of one device reader: the exponential backoff between attempts,
+/// and how failed attempts are reported.
+pub struct Reconnects {
+    retry_delay: Duration,
+    reconnecting: bool,
+    errors: Throttle,
+}
+
+impl Default for Reconnects {
+    fn default() -> Self {
+        Reconnects {
+            retry_delay: INITIAL_RETRY_DELAY,
+            reconnecting: false,
+            errors: Throttle::default(),
+        }
+    }
+}
+
+impl Reconnects {
+    /// Starts the backoff over once a port is open.
+    pub fn connected(&mut self) {
+        self.retry_delay = INITIAL_RETRY_DELAY;
+        self.errors.reset();
+    }
+
+    /// Handles the end of the connection to the device called `name`, with the result of
+    /// reading from it. Returns whether to reconnect right away rather than after
+    /// [`Reconnects::next_delay`], which is the case when the watchdog gave up on the port.
+    pub fn lost(&mut self, result: Result<()>, name: &str, stats: &Stats) -> bool {
+        stats.reconnect();
+        if let Err(e) = result {
+            error!("Serial connection to {} lost: {}", name, e);
+            info!("Reconnecting...");
+            self.reconnecting = true;
+            return false;
+        }
+        true
+    }
+
+    /// Reports a failed attempt to connect.
+    pub fn failed(&mut self, e: &dyn std::fmt::Display) {
+        // Already announced that we're reconnecting, only report retries now and then
+        if self.reconnecting {
+            self.errors
+                .warn(format_args!("Reconnect attempt failed: {}", e))
+        } else {
+            warn!("Failed to connect: {}", e)
+        }
+    }
+
+    /// Returns how long to wait before the next attempt, and doubles the wait after it.
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.retry_delay;
+        self.retry_delay = (delay * 2).min(MAX_RETRY_DELAY);
+        delay
+    }
+}
+
+/// Whether [`DeviceLink::send_held`]'s queue took an event.
+pub enum Queued {
+    Sent,
+    Full,
+    Closed,
+}
+
+/// The state of a connected device that doesn't depend on how its port is read: decode
+/// errors, pings, the stall warning and the watchdog, and the slider positions held while
+/// the event queue is full. While it is, only the newest position of each slider is kept and
+/// those are sent once there is room, so a busy main loop catches up on where the sliders
+/// are instead of where they were.
+pub struct DeviceLink<'a> {
+    connection: &'a Connection,
+    name: &'a str,
+    stats: &'a Stats,
+    last_frame: Instant,
+    stalled: bool,
+    decode_errors: DecodeErrors,
+    pinger: Pinger,
+    held: BTreeMap<u8, Slider>,
+}
+
+impl<'a> DeviceLink<'a> {
+    /// Starts tracking the device of `connection`, called `name` in logs, once its port is
+    /// open.
+    pub fn new(connection: &'a Connection, name: &'a str, stats: &'a Stats) -> Self {
+        info!("Listening for slider data on {}...", name);
+        DeviceLink {
+            connection,
+            name,
+            stats,
+            last_frame: Instant::now(),
+            stalled: false,
+            decode_errors: DecodeErrors::default(),
+            pinger: Pinger::default(),
+            held: BTreeMap::new(),
+        }
+    }
+
+    /// Handles a frame read from the device, returning the message to send to the main
+    /// loop, which has to wait for room in the queue. Slider positions are held instead, to
+    /// be sent with [`DeviceLink::send_held`], and pongs are handled here.
+    pub fn frame(&mut self, frame: Result<Message, GainError>) -> Option<Message> {
+        self.last_frame = Instant::now();
+        self.stats.frame(frame.is_ok());
+        if self.stalled {
+            info!("{} is responding again", self.name);
+            self.stalled = false;
+        }
+
+        match self.decode_errors.check(frame, self.connection)? {
+            Message::Pong(pong) => {
+                self.pinger.pong(pong, self.name);
+                None
+            }
+            message => match offset_ids(message, self.connection.id_offset) {
+                Message::Slider(slider) => {
+                    self.held.insert(slider.id, slider);
+                    None
+                }
+                message => Some(message),
+            },
+        }
+    }
+
+    /// Returns whether slider positions are waiting for room in the queue.
+    pub fn is_holding(&self) -> bool {
+        !self.held.is_empty()
+    }
+
+    /// Sends the held slider positions through `try_send` while it takes them, keeping the
+    /// rest held so a newer position of the same slider replaces them. Returns false once
+    /// the queue is closed.
+    pub fn send_held(&mut self, mut try_send: impl FnMut(DeviceEvent) -> Queued) -> bool {
+        while let Some((id, slider)) = self.held.pop_first() {
+            match try_send(DeviceEvent::Message(Message::Slider(slider))) {
+                Queued::Sent => {}
+                Queued::Full => {
+                    trace!(
+                        "Event queue full, holding the newest position of slider {}",
+                        id
+                    );
+                    self.held.insert(id, slider);
+                    return true;
+                }
+                Queued::Closed => return false,
+            }
+        }
+        true
+    }
+
+    /// Returns the ping to send at `now`, if one is due.
+    pub fn ping_due(&mut self, now: Instant) -> Option<HostMessage> {
+        self.pinger.due(now).map(HostMessage::Ping)
+    }
+
+    /// Returns when [`DeviceLink::check`] next has something to report, if the device
+    /// stays silent until then.
+    // Only the tokio reader sleeps until then, the reader threads check on every read
+    #[cfg(feature = "tokio")]
+    pub fn next_deadline(&self) -> Option<Instant> {
+        let stall = (!self.stalled).then_some(self.last_frame + STALL_TIMEOUT);
+        let watchdog = self
+            .connection
+            .watchdog_secs
+            .map(|secs| self.last_frame + Duration::from_secs(secs));
+        stall.into_iter().chain(watchdog).min()
+    }
+
+    /// Reports a ping left unanswered and a device that went silent, as of `now`. Returns
+    /// true once the watchdog gives up on the port, which the reader then resets with
+    /// [`begin_reset`] and [`end_reset`] and closes.
+    pub fn check(&mut self, now: Instant) -> bool {
+        self.pinger.check(now, self.name);
+        let silent = now.duration_since(self.last_frame);
+        if !self.stalled && silent >= STALL_TIMEOUT {
+            warn!(
+                "No data from {} for {:?}, it may have crashed or been unplugged",
+                self.name, STALL_TIMEOUT
+            );
+            self.stalled = true;
+        }
+        if let Some(secs) = self.connection.watchdog_secs
+            && silent >= Duration::from_secs(secs)
+        {
+            warn!(
+                "No data from {} for {}s, resetting the serial port",
+                self.name, secs
+            );
+            return true;
+        }
+        false
+    }
+}
+
+/// Reports the frames of a device that fail to decode, rate limited, and points out a
+/// likely baud rate mismatch once [`DECODE_HINT_FAILURES`] of them come in a row.
+#[derive(Default)]
+pub struct DecodeErrors {
+    in_a_row: u32,
+    throttle: Throttle,
+}
+
+impl DecodeErrors {
+    /// Returns the message in `frame`, read from the device of `connection`, or reports why
+    /// it didn't decode.
+    pub fn check(
+        &mut self,
+        frame: Result<Message, GainError>,
+        connection: &Connection,
+    ) -> Option<Message> {
+        let e = match frame {
+            Ok(message) => {
+                self.in_a_row = 0;
+                return Some(message);
+            }
+            Err(e) => e,
+        };
+        self.in_a_row += 1;
+        if self.in_a_row == DECODE_HINT_FAILURES {
+            warn!(
+                "Decoding has failed for {} frames in a row; check that baud_rate ({}) matches the firmware",
+                DECODE_HINT_FAILURES, connection.baud_rate
+            );
+        }
+        match e {
+            GainError::Decode(postcard::Error::DeserializeBadCrc) => self
+                .throttle
+                .warn(format_args!("Dropping corrupted frame: checksum mismatch")),
+            e => self
+                .throttle
+                .warn(format_args!("Deserialization failed: {}", e)),
+        }
+        None
+    }
+}
+
+/// Pings a connected device every [`PING_INTERVAL`], and reports when it stops answering
+/// within [`PONG_TIMEOUT`] and when it answers again.
+#[derive(Default)]
+pub struct Pinger {
+    next_token: u8,
+    last_sent: Option<Instant>,
+    /// Token and send time of the ping that hasn't been answered yet.
+    waiting: Option<(u8, Instant)>,
+    /// Whether the device answered any ping, which firmware that predates them never does.
+    answered: bool,
+    missing: bool,
+}
+
+impl Pinger {
+    /// Returns the ping to send at `now`, if one is due.
+    pub fn due(&mut self, now: Instant) -> Option<Ping> {
+        // The first ping waits an interval too, as boards that reset on connect are booting
+        let Some(sent) = self.last_sent else {
+            self.last_sent = Some(now);
+            return None;
+        };
+        if now.duration_since(sent) < PING_INTERVAL {
+            return None;
+        }
+        let token = self.next_token;
+        self.next_token = token.wrapping_add(1);
+        self.last_sent = Some(now);
+        self.waiting = Some((token, now));
+        Some(Ping { token })
+    }
+
+    /// Handles a pong from the device called `name`.
+    pub fn pong(&mut self, pong: Ping, name: &str) {
+        if self.waiting.is_some_and(|(token, _)| token == pong.token) {
+            self.waiting = None;
+            if self.missing && self.answered {
+                info!("{} answers pings again", name);
+            }
+            self.missing = false;
+            self.answered = true;
+        }
+    }
+
+    /// Reports the device called `name` if it left the last ping unanswered for too long.
+    pub fn check(&mut self, now: Instant, name: &str) {
+        let Some((_, sent)) = self.waiting else {
+            return;
+        };
+        if self.missing || now.duration_since(sent) < PONG_TIMEOUT {
+            return;
+        }
+        // The ping stays expected, so a pong that is only late still counts as an answer
+        self.missing = true;
+        if self.answered {
+            warn!("{} stopped answering pings; its firmware may be hung", name);
+        } else {
+            debug!(
+                "{} doesn't answer pings, its firmware may predate them",
+                name
+            );
+        }
+    }
+}
+
+/// Ping sent while probing a port.
+pub const PROBE_PING: Ping = Ping { token: 0 };
+
+/// Returns whether the device on `port` answers a ping within [`PROBE_TIMEOUT`], i.e. runs
+/// the gain firmware, then sets the port's read timeout back to `timeout`. Used to pick
+/// between several ports found by scanning.
+pub fn answers_ping(port: &mut dyn SerialPort, timeout: Duration) -> bool {
+    let probe = |port: &mut dyn SerialPort| -> Result<bool> {
+        port.set_timeout(PROBE_RETRY)?;
+        let ping = PROBE_PING;
+        let started = Instant::now();
+        let mut buffer = Vec::new();
+        // Kept across retries, so bytes read past one frame, possibly the pong, aren't lost
+        let mut reader = BufReader::new(port);
+        while started.elapsed() < PROBE_TIMEOUT {
+            send_frame(*reader.get_mut(), &HostMessage::Ping(ping))?;
+            let sent = Instant::now();
+            while sent.elapsed() < PROBE_RETRY {
+                match read_frame(&mut reader, &mut buffer)? {
+                    Some(Ok(Message::Pong(pong))) if pong == ping => return Ok(true),
+                    Some(_) => {}
+                    None => break,
+                }
+            }
+        }
+        Ok(false)
+    };
+    let answered = probe(port).unwrap_or(false);
+    let _ = port.set_timeout(timeout);
+    answered
+}
+
+/// Writes `message` to `port` as one frame.
+fn send_frame(port: &mut dyn SerialPort, message: &HostMessage) -> Result<()> {
+    port.write_all(&encode(message)?)?;
+    Ok(())
+}
+
+/// Encodes `message` as one frame, with its `0x00` terminator.
+pub fn encode(message: &HostMessage) -> Result<Vec<u8>> {
+    let mut buf = [0; MAX_FRAME_LEN];
+    Ok(gain_lib::encode_frame(message, &mut buf)?.to_vec())
+}
+
+/// Adds `offset` to the ID of a message from a device, so each device's sliders can be
+/// mapped separately. IDs that would overflow are capped at 255.
+pub fn offset_ids(message: Message, offset: u8) -> Message {
+    match message {
+        Message::Slider(mut slider) => {
+            slider.id = slider.id.saturating_add(offset);
+            Message::Slider(slider)
+        }
+        Message::Button(mut button) => {
+            button.id = button.id.saturating_add(offset);
+            Message::Button(button)
+        }
+        Message::Encoder(mut encoder) => {
+            encoder.id = encoder.id.saturating_add(offset);
+            Message::Encoder(encoder)
+        }
+        Message::Raw(mut reading) => {
+            reading.id = reading.id.saturating_add(offset);
+            Message::Raw(reading)
+        }
+        Message::PinConfig(mut pin) => {
+            pin.id = pin.id.saturating_add(offset);
+            Message::PinConfig(pin)
+        }
+        Message::Heartbeat | Message::Hello(_) | Message::Pong(_) => message,
+    }
+}
+
+/// Pulses DTR before the port is closed, which resets boards with an auto-reset circuit
+/// (such as the Arduino Uno and Nano) and unsticks some USB-serial adapters.
+#[cfg(not(feature = "tokio"))]
+fn reset_port(port: &mut dyn SerialPort) {
+    if begin_reset(port) {
+        thread::sleep(DTR_PULSE);
+        end_reset(port);
+    }
+}
+
+/// Starts the DTR pulse of [`reset_port`], returning whether it has to be ended with
+/// [`end_reset`] after [`DTR_PULSE`].
+pub fn begin_reset(port: &mut dyn SerialPort) -> bool {
+    let pulsed = port.write_data_terminal_ready(false);
+    if let Err(e) = &pulsed {
+        debug!("Failed to toggle DTR: {}", e);
+    }
+    pulsed.is_ok()
+}
+
+/// Ends the DTR pulse started by [`begin_reset`].
+pub fn end_reset(port: &mut dyn SerialPort) {
+    if let Err(e) = port.write_data_terminal_ready(true) {
+        debug!("Failed to toggle DTR: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gain_lib::{Button, Slider};
+    use std::sync::mpsc::{self, TrySendError};
+
+    #[test]
+    fn ids_are_offset_per_device() {
+        let slider = offset_ids(Message::Slider(Slider { id: 2, value: 5 }), 8);
+        assert!(matches!(
+            slider,
+            Message::Slider(Slider { id: 10, value: 5 })
+        ));
+
+        let button = Message::Button(Button {
+            id: 250,
+            pressed: true,
+        });
+        assert!(matches!(
+            offset_ids(button, 8),
+            Message::Button(Button { id: 255, .. })
+        ));
+        assert!(matches!(
+            offset_ids(Message::Heartbeat, 8),
+            Message::Heartbeat
+        ));
+    }
+
+    #[test]
+    fn pings_wait_an_interval_and_expect_their_own_token() {
+        let mut pinger = Pinger::default();
+        let start = Instant::now();
+        assert_eq!(pinger.due(start), None);
+        assert_eq!(pinger.due(start + PING_INTERVAL / 2), None);
+
+        let ping = pinger.due(start + PING_INTERVAL).unwrap();
+        pinger.pong(
+            Ping {
+                token: ping.token + 1,
+            },
+            "COM3",
+        );
+        assert!(pinger.waiting.is_some());
+        pinger.pong(ping, "COM3");
+        assert!(pinger.waiting.is_none() && pinger.answered);
+
+        let next = pinger.due(start + PING_INTERVAL * 2).unwrap();
+        assert_ne!(next, ping);
+        pinger.check(start + PING_INTERVAL * 2 + PONG_TIMEOUT, "COM3");
+        assert!(pinger.missing);
+        pinger.pong(next, "COM3");
+        assert!(!pinger.missing);
+    }
+
+    #[test]
+    fn held_sliders_keep_only_their_newest_position() {
+        let connection = Connection::default();
+        let stats = Stats::default();
+        let mut link = DeviceLink::new(&connection, "COM3", &stats);
+        let (events, queue) = mpsc::sync_channel(1);
+        let send_held = |link: &mut DeviceLink| {
+            link.send_held(|event| match events.try_send(event) {
+                Ok(()) => Queued::Sent,
+                Err(TrySendError::Full(_)) => Queued::Full,
+                Err(TrySendError::Disconnected(_)) => Queued::Closed,
+            })
+        };
+        for value in [1, 2, 3] {
+            let slider = Message::Slider(Slider { id: 0, value });
+            assert!(link.frame(Ok(slider)).is_none());
+            assert!(send_held(&mut link));
+        }
+
+        // The first position filled the queue and the newest one replaced the second
+        let value = |event| match event {
+            Ok(DeviceEvent::Message(Message::Slider(slider))) => Some(slider.value),
+            _ => None,
+        };
+        assert_eq!(value(queue.try_recv()), Some(1));
+        assert!(send_held(&mut link));
+        assert_eq!(value(queue.try_recv()), Some(3));
+        assert!(!link.is_holding());
+    }
+}
@@ -0,0 +1,79 @@
+use serialport::ErrorKind;
+use std::io;
+use thiserror::Error;
+
+/// Failures of gain's main flow that are worth telling apart, so callers can match on them
+/// and the CLI can point at the likely fix. Anything else is reported with anyhow as is.
+#[derive(Debug, Error)]
+pub enum GainError {
+    /// The config file couldn't be read, parsed or validated.
+    #[error("Failed to load config file {path}")]
+    Config {
+        path: String,
+        #[source]
+        source: anyhow::Error,
+    },
+    /// The serial port was found but couldn't be opened.
+    #[error("Failed to open port {port}")]
+    SerialOpen {
+        port: String,
+        #[source]
+        source: serialport::Error,
+    },
+    /// No port was configured and no USB serial device qualified, `filtered` telling
+    /// whether some were left out by the connection filters.
+    #[error("{}", if *filtered { "No USB serial device matches the configured connection filters" } else { "No USB serial device found" })]
+    PortNotFound { filtered: bool },
+    /// The serial ports couldn't be listed.
+    #[error("Failed to list serial ports: {0}")]
+    ListPorts(#[source] serialport::Error),
+    /// A frame from the device didn't decode, or failed its checksum.
+    #[error(transparent)]
+    Decode(#[from] postcard::Error),
+}
+
+impl GainError {
+    /// Returns what the user can do about the error, if there is a usual fix.
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            GainError::Config { .. } => Some(
+                "Compare the file with the configuration options in the README, or run `gain init` to write a working one to start from",
+            ),
+            GainError::SerialOpen { source, .. } => match source.kind {
+                ErrorKind::NoDevice | ErrorKind::Io(io::ErrorKind::NotFound) => Some(
+                    "Check that the device is plugged in and that `connection.com_port` names its port; `gain list-ports` lists them",
+                ),
+                ErrorKind::Io(io::ErrorKind::PermissionDenied) => Some(
+                    "Your user isn't allowed to open the port; on Linux, add it to the group that owns the port (usually `dialout` or `uucp`) and log in again",
+                ),
+                _ => Some(
+                    "Make sure no other program, such as the Arduino IDE's serial monitor or another gain, has the port open",
+                ),
+            },
+            GainError::PortNotFound { filtered: true } => Some(
+                "Run `gain list-ports` to see the connected devices and compare them with the `connection` filters",
+            ),
+            GainError::PortNotFound { filtered: false } => Some(
+                "Plug the device in, or set `connection.com_port` if it isn't a USB serial device",
+            ),
+            GainError::ListPorts(_) => None,
+            GainError::Decode(_) => Some(
+                "Check that `connection.baud_rate` matches the firmware and that both were built from the same version of gain",
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_ports_say_whether_filters_were_applied() {
+        let filtered = GainError::PortNotFound { filtered: true };
+        let unfiltered = GainError::PortNotFound { filtered: false };
+        assert!(filtered.to_string().contains("filters"));
+        assert!(!unfiltered.to_string().contains("filters"));
+        assert_ne!(filtered.hint(), unfiltered.hint());
+    }
+}
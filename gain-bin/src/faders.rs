@@ -0,0 +1,84 @@
+use crate::{
+    config::{LoadedConfig, SliderMode},
+    get_target_volume,
+    ramp::Ramps,
+    slider,
+    volume::VolumeBackend,
+};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// How often the targets of motorized sliders are read back.
+pub const FADER_POLL: Duration = Duration::from_millis(250);
+/// Time after a slider last moved during which its fader is left alone, since a hand is
+/// probably still on it.
+const HANDS_OFF: Duration = Duration::from_secs(1);
+
+/// Motorized faders, moved to follow their targets' volumes when those are changed outside
+/// gain, such as in the system mixer.
+#[derive(Default)]
+pub struct Faders {
+    /// Last raw position each slider reported or was moved to, and when it last reported.
+    positions: HashMap<u8, (u16, Instant)>,
+    last_poll: Option<Instant>,
+}
+
+impl Faders {
+    /// Records a raw position slider `id` reported at `now`.
+    pub fn record(&mut self, id: u8, value: u16, now: Instant) {
+        self.positions.insert(id, (value, now));
+    }
+
+    /// Reads the target volume of every motorized slider, at most once per [`FADER_POLL`],
+    /// and returns the raw positions, by slider ID, to move the faders that are more than
+    /// `general.fader_threshold` away from it to. Faders that haven't reported a position
+    /// yet, moved within the last second or are ramping are left alone.
+    pub fn poll(
+        &mut self,
+        config: &LoadedConfig,
+        backend: &mut dyn VolumeBackend,
+        ramps: &Ramps,
+        now: Instant,
+    ) -> Vec<(u8, u16)> {
+        if self
+            .last_poll
+            .is_some_and(|last| now.duration_since(last) < FADER_POLL)
+        {
+            return Vec::new();
+        }
+        self.last_poll = Some(now);
+
+        let mut moves = Vec::new();
+        let motorized = config
+            .mappings()
+            .values()
+            .filter(|mapping| mapping.motorized && mapping.mode == SliderMode::Absolute);
+        for mapping in motorized {
+            let Some(&(position, reported)) = self.positions.get(&mapping.id) else {
+                continue;
+            };
+            if now.duration_since(reported) < HANDS_OFF || ramps.target(mapping.id).is_some() {
+                continue;
+            }
+            let Some(volume) = get_target_volume(&mapping.target, config, backend) else {
+                continue;
+            };
+
+            let wanted = slider::slider_position(volume, &config.general, mapping);
+            if wanted.abs_diff(position) > config.general.fader_threshold {
+                // Taken as the fader's position, so it isn't sent again while the motor is
+                // still getting there
+                self.positions.insert(mapping.id, (wanted, reported));
+                moves.push((mapping.id, wanted));
+            }
+        }
+        moves
+    }
+
+    /// Returns whether any slider is motorized, so its target has to be polled.
+    pub fn is_following(config: &LoadedConfig) -> bool {
+        config.mappings().values().any(|mapping| mapping.motorized)
+    }
+}
@@ -0,0 +1,220 @@
+use crate::config::HostFilter;
+use std::{collections::HashMap, f64::consts::PI, time::Instant};
+
+/// Distance, in raw units, at which a filtered value snaps to its input and stops moving.
+const SETTLED: f64 = 0.5;
+
+/// Host-side smoothing state, keyed by slider ID.
+///
+/// Since the firmware only reports changes, a filtered value that is still catching up when
+/// a slider stops would never get there. [`HostFilters::step`] keeps such values moving
+/// toward the last reading until they settle.
+#[derive(Default)]
+pub struct HostFilters {
+    states: HashMap<u8, FilterState>,
+}
+
+struct FilterState {
+    input: f64,
+    output: f64,
+    /// Smoothed rate of change of the output, used by the One Euro filter.
+    speed: f64,
+    updated: Instant,
+}
+
+impl HostFilters {
+    /// Feeds a reading from slider `id` taken at `now`, returning the filtered value.
+    pub fn push(&mut self, id: u8, value: u16, filter: HostFilter, now: Instant) -> u16 {
+        let input = value as f64;
+        let state = self.states.entry(id).or_insert(FilterState {
+            input,
+            output: input,
+            speed: 0.0,
+            updated: now,
+        });
+        state.input = input;
+        state.update(filter, now);
+        state.output.round() as u16
+    }
+
+    /// Moves every filtered value that hasn't caught up with its last reading closer to it,
+    /// returning the new values.
+    pub fn step(&mut self, filter: HostFilter, now: Instant) -> Vec<(u8, u16)> {
+        self.states
+            .iter_mut()
+            .filter(|(_, state)| !state.is_settled())
+            .map(|(&id, state)| {
+                state.update(filter, now);
+                (id, state.output.round() as u16)
+            })
+            .collect()
+    }
+
+    /// Returns whether any filtered value is still catching up with its reading.
+    pub fn is_settling(&self) -> bool {
+        self.states.values().any(|state| !state.is_settled())
+    }
+}
+
+impl FilterState {
+    fn is_settled(&self) -> bool {
+        self.output == self.input
+    }
+
+    fn update(&mut self, filter: HostFilter, now: Instant) {
+        let dt = now.duration_since(self.updated).as_secs_f64();
+        self.updated = now;
+
+        match filter {
+            HostFilter::None => self.output = self.input,
+            HostFilter::Ema { time_constant_ms } => {
+                let alpha = 1.0 - (-dt * 1000.0 / time_constant_ms).exp();
+                self.output += alpha * (self.input - self.output);
+            }
+            HostFilter::OneEuro {
+                min_cutoff,
+                beta,
+                d_cutoff,
+            } => {
+                if dt > 0.0 {
+                    let speed = (self.input - self.output) / dt;
+                    self.speed += smoothing(d_cutoff, dt) * (speed - self.speed);
+                }
+                let cutoff = min_cutoff + beta * self.speed.abs();
+                self.output += smoothing(cutoff, dt) * (self.input - self.output);
+            }
+        }
+
+        if (self.output - self.input).abs() < SETTLED {
+            self.output = self.input;
+        }
+    }
+}
+
+/// Weight of a new sample for a low-pass filter with the given cutoff (Hz), `dt` seconds
+/// after the previous one.
+fn smoothing(cutoff: f64, dt: f64) -> f64 {
+    let tau = 1.0 / (2.0 * PI * cutoff);
+    1.0 / (1.0 + tau / dt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::{ButtonMode, Curve, General, SliderMappings, SliderMode, VolumeTarget},
+        slider::Quantizer,
+    };
+    use std::time::Duration;
+
+    /// Firmware reporting interval.
+    const SAMPLE: Duration = Duration::from_millis(25);
+    /// Host tick while a filter is settling.
+    const TICK: Duration = Duration::from_millis(10);
+
+    /// Raw readings jittering around 504, close enough to the 0.49/0.50 step boundary
+    /// (about 506.4) that unfiltered readings cross it.
+    fn noisy_readings() -> impl Iterator<Item = u16> {
+        [501, 507, 503, 506, 502, 507, 505, 501].into_iter().cycle()
+    }
+
+    fn volume(value: u16) -> f64 {
+        let mapping = SliderMappings {
+            id: 0,
+            target: VolumeTarget::Master,
+            mode: SliderMode::Absolute,
+            button: ButtonMode::Momentary,
+            curve: Some(Curve::Linear),
+            midi: None,
+            osc: None,
+            min_raw: None,
+            max_raw: None,
+            offset_raw: 0,
+            min_scalar: None,
+            max_scalar: None,
+            invert: None,
+            motorized: false,
+        };
+        Quantizer::default().slider_volume(0, value, &General::default(), &mapping)
+    }
+
+    /// Feeds the noisy readings through `filter`, ticking in between like the host does,
+    /// and returns the volumes produced after the filter has warmed up.
+    fn filtered_volumes(filter: HostFilter) -> Vec<f64> {
+        let mut filters = HostFilters::default();
+        let mut now = Instant::now();
+        let mut volumes = Vec::new();
+
+        for (i, reading) in noisy_readings().take(200).enumerate() {
+            let mut outputs = vec![filters.push(0, reading, filter, now)];
+            let mut tick = now;
+            while tick + TICK < now + SAMPLE {
+                tick += TICK;
+                outputs.extend(filters.step(filter, tick).into_iter().map(|(_, v)| v));
+            }
+            now += SAMPLE;
+
+            if i >= 50 {
+                volumes.extend(outputs.into_iter().map(volume));
+            }
+        }
+        volumes
+    }
+
+    fn assert_steady(volumes: &[f64]) {
+        assert!(
+            volumes.iter().all(|&v| v == volumes[0]),
+            "volume oscillates: {:?}",
+            volumes
+        );
+    }
+
+    #[test]
+    fn unfiltered_noise_oscillates() {
+        let volumes = filtered_volumes(HostFilter::None);
+        assert!(volumes.iter().any(|&v| v != volumes[0]));
+    }
+
+    #[test]
+    fn ema_settles_noisy_input() {
+        let volumes = filtered_volumes(HostFilter::Ema {
+            time_constant_ms: 100.0,
+        });
+        assert_steady(&volumes);
+        assert_eq!(volumes[0], 0.49);
+    }
+
+    #[test]
+    fn one_euro_settles_noisy_input() {
+        let volumes = filtered_volumes(HostFilter::OneEuro {
+            min_cutoff: 1.0,
+            beta: 0.001,
+            d_cutoff: 1.0,
+        });
+        assert_steady(&volumes);
+        assert_eq!(volumes[0], 0.49);
+    }
+
+    #[test]
+    fn filtered_value_catches_up_after_the_slider_stops() {
+        let filter = HostFilter::Ema {
+            time_constant_ms: 50.0,
+        };
+        let mut filters = HostFilters::default();
+        let mut now = Instant::now();
+        filters.push(0, 0, filter, now);
+        now += SAMPLE;
+        assert!(filters.push(0, 800, filter, now) < 800);
+        assert!(filters.is_settling());
+
+        let mut last = 0;
+        while filters.is_settling() {
+            now += TICK;
+            for (_, value) in filters.step(filter, now) {
+                assert!(value >= last, "filtered value went backwards");
+                last = value;
+            }
+        }
+        assert_eq!(last, 800);
+    }
+}
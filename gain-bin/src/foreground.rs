@@ -0,0 +1,154 @@
+use crate::{ramp::Ramps, volume::VolumeBackend};
+use anyhow::Result;
+use log::debug;
+use std::time::{Duration, Instant};
+
+/// How often the focused window is checked while a `foreground` slider is in use, or while
+/// the focused application is expected to open an audio session.
+pub const FOREGROUND_POLL: Duration = Duration::from_millis(250);
+
+/// Follows the focused application for `foreground` sliders, reapplying the last volume
+/// they sent whenever another application with an audio session comes to the front.
+#[derive(Default)]
+pub struct Foreground {
+    /// Last `foreground` slider that moved, ramped for focus changes.
+    slider: u8,
+    /// Last volume sent by a `foreground` slider, if any moved yet.
+    volume: Option<f64>,
+    /// Process that `volume` was last applied to.
+    applied_to: Option<u32>,
+    last_poll: Option<Instant>,
+}
+
+impl Foreground {
+    /// Records a volume the `foreground` slider `slider` just applied to the process
+    /// `focused`.
+    pub fn record(&mut self, slider: u8, volume: f64, focused: Option<u32>) {
+        self.slider = slider;
+        self.volume = Some(volume);
+        self.applied_to = focused;
+    }
+
+    /// Returns whether there is a volume to follow the focus with.
+    pub fn is_tracking(&self) -> bool {
+        self.volume.is_some()
+    }
+
+    /// Reapplies the last volume if the focus moved to another application since it was
+    /// applied, fading it in from the application's own level over `ramp` unless that is
+    /// zero. Applications without an audio session are skipped until they open one, by
+    /// checking again on a later poll.
+    pub fn poll(
+        &mut self,
+        backend: &mut dyn VolumeBackend,
+        ramps: &mut Ramps,
+        ramp: Duration,
+    ) -> Result<()> {
+        let Some(volume) = self.volume else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        if self
+            .last_poll
+            .is_some_and(|last| now.duration_since(last) < FOREGROUND_POLL)
+        {
+            return Ok(());
+        }
+        self.last_poll = Some(now);
+
+        let focused = backend.focused_pid()?;
+        if focused.is_none() || focused == self.applied_to {
+            return Ok(());
+        }
+        let Some(current) = backend.get_current_app()? else {
+            return Ok(());
+        };
+
+        debug!(
+            "Focus moved to PID {:?}, applying volume {}",
+            focused, volume
+        );
+        if ramp.is_zero() {
+            backend.set_current_app(volume)?;
+        } else {
+            // A ramp still in progress was fading the previously focused application
+            ramps.cancel(self.slider);
+            ramps.start(self.slider, volume, ramp, || Some(current));
+        }
+        self.applied_to = focused;
+        Ok(())
+    }
+}
+
+/// Volume a `current` or `foreground` slider set while the focused application had no audio
+/// session yet, such as a player that only opens one once it starts playing. The volume is
+/// applied when a session of that process appears, as long as it is still focused.
+#[derive(Default)]
+pub struct SessionWait {
+    /// Process that was focused, and the volume it is waiting for.
+    pending: Option<(u32, f64)>,
+    last_poll: Option<Instant>,
+}
+
+impl SessionWait {
+    /// Records that `volume` was just applied to the focused application, to apply again
+    /// once it opens an audio session if it has none yet.
+    pub fn record(&mut self, volume: f64, backend: &mut dyn VolumeBackend) -> Result<()> {
+        self.pending = None;
+        let Some(pid) = backend.focused_pid()? else {
+            return Ok(());
+        };
+        if !has_session(pid, backend)? {
+            debug!(
+                "Focused app (PID {}) has no audio session yet, applying volume {} once it opens one",
+                pid, volume
+            );
+            self.pending = Some((pid, volume));
+        }
+        Ok(())
+    }
+
+    /// Forgets the waiting volume, such as when its slider muted the application instead.
+    pub fn cancel(&mut self) {
+        self.pending = None;
+    }
+
+    /// Returns whether a volume is waiting for the focused application's session.
+    pub fn is_waiting(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Applies the waiting volume if its application opened an audio session since, and
+    /// forgets it if another application came to the front.
+    pub fn poll(&mut self, backend: &mut dyn VolumeBackend) -> Result<()> {
+        let Some((pid, volume)) = self.pending else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        if self
+            .last_poll
+            .is_some_and(|last| now.duration_since(last) < FOREGROUND_POLL)
+        {
+            return Ok(());
+        }
+        self.last_poll = Some(now);
+
+        if backend.focused_pid()? != Some(pid) {
+            debug!("PID {} lost the focus before opening an audio session", pid);
+            self.pending = None;
+        } else if has_session(pid, backend)? {
+            debug!(
+                "PID {} opened an audio session, applying volume {}",
+                pid, volume
+            );
+            backend.set_current_app(volume)?;
+            self.pending = None;
+        }
+        Ok(())
+    }
+}
+
+/// Returns whether process `pid` has an audio session.
+fn has_session(pid: u32, backend: &mut dyn VolumeBackend) -> Result<bool> {
+    Ok(backend.sessions()?.iter().any(|session| session.pid == pid))
+}
@@ -0,0 +1,234 @@
+use anyhow::{Result, anyhow};
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+/// Longest time a press of the panic hotkey waits before the main loop picks it up.
+pub const PANIC_POLL: Duration = Duration::from_millis(50);
+
+/// A key combination such as `Ctrl+Alt+M`, parsed from the configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hotkey {
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    pub win: bool,
+    /// Windows virtual-key code of the key pressed with the modifiers.
+    pub key: u32,
+}
+
+impl Hotkey {
+    /// Parses modifiers and a key joined by `+`, ignoring case and spaces, e.g.
+    /// `Ctrl+Shift+F12`. Keys are letters, digits, `F1` to `F24`, `Pause`, `ScrollLock`,
+    /// `Escape` and `Space`.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let mut hotkey = Hotkey {
+            ctrl: false,
+            alt: false,
+            shift: false,
+            win: false,
+            key: 0,
+        };
+        let mut parts: Vec<String> = spec
+            .split('+')
+            .map(|part| part.trim().to_ascii_uppercase())
+            .collect();
+        let key = parts.pop().unwrap_or_default();
+        for modifier in parts {
+            match modifier.as_str() {
+                "CTRL" | "CONTROL" => hotkey.ctrl = true,
+                "ALT" => hotkey.alt = true,
+                "SHIFT" => hotkey.shift = true,
+                "WIN" | "SUPER" => hotkey.win = true,
+                _ => {
+                    return Err(anyhow!(
+                        "Unknown modifier {:?} in hotkey {:?}",
+                        modifier,
+                        spec
+                    ));
+                }
+            }
+        }
+
+        hotkey.key = match key.as_str() {
+            "PAUSE" => 0x13,
+            "ESCAPE" | "ESC" => 0x1B,
+            "SPACE" => 0x20,
+            "SCROLLLOCK" => 0x91,
+            _ if key.len() == 1 && key.chars().all(|c| c.is_ascii_alphanumeric()) => {
+                key.as_bytes()[0] as u32
+            }
+            _ => match key.strip_prefix('F').and_then(|n| n.parse::<u32>().ok()) {
+                Some(n @ 1..=24) => 0x70 + n - 1,
+                _ => return Err(anyhow!("Unknown key {:?} in hotkey {:?}", key, spec)),
+            },
+        };
+        Ok(hotkey)
+    }
+}
+
+/// Global hotkey that drops every volume to `general.panic_volume`, polled by the main loop.
+/// Only Windows has global hotkeys; elsewhere, and by default, it is never pressed.
+#[derive(Default)]
+pub struct PanicHotkey {
+    pressed: Arc<AtomicBool>,
+    #[cfg(windows)]
+    thread: Option<windows_hotkey::HotkeyThread>,
+}
+
+impl PanicHotkey {
+    /// Registers `hotkey` system wide. Failing to register it, such as when another program
+    /// has taken the combination, is only warned about.
+    pub fn start(hotkey: Option<Hotkey>) -> Self {
+        let Some(hotkey) = hotkey else {
+            return PanicHotkey::default();
+        };
+        let pressed = Arc::new(AtomicBool::new(false));
+
+        #[cfg(windows)]
+        return PanicHotkey {
+            thread: windows_hotkey::HotkeyThread::spawn(hotkey, pressed.clone())
+                .inspect_err(|e| log::warn!("Failed to register the panic hotkey: {}", e))
+                .ok(),
+            pressed,
+        };
+
+        #[cfg(not(windows))]
+        {
+            let _ = hotkey;
+            log::warn!("Global hotkeys are only supported on Windows; ignoring panic_hotkey");
+            PanicHotkey { pressed }
+        }
+    }
+
+    /// Returns whether the hotkey is registered, i.e. can be pressed at all.
+    pub fn is_registered(&self) -> bool {
+        #[cfg(windows)]
+        return self.thread.is_some();
+
+        #[cfg(not(windows))]
+        false
+    }
+
+    /// Returns whether the hotkey was pressed since the last call.
+    pub fn take_pressed(&self) -> bool {
+        self.pressed.swap(false, Ordering::Relaxed)
+    }
+}
+
+#[cfg(windows)]
+mod windows_hotkey {
+    use super::*;
+    use log::info;
+    use std::{
+        sync::mpsc,
+        thread::{self, JoinHandle},
+    };
+    use windows::Win32::{
+        Foundation::{LPARAM, WPARAM},
+        System::Threading::GetCurrentThreadId,
+        UI::Input::KeyboardAndMouse::{
+            HOT_KEY_MODIFIERS, MOD_ALT, MOD_CONTROL, MOD_NOREPEAT, MOD_SHIFT, MOD_WIN,
+            RegisterHotKey, UnregisterHotKey,
+        },
+        UI::WindowsAndMessaging::{GetMessageW, MSG, PostThreadMessageW, WM_HOTKEY, WM_QUIT},
+    };
+
+    /// ID of the hotkey among the ones the thread registers.
+    const HOTKEY_ID: i32 = 1;
+
+    /// Thread that owns the hotkey registration and waits for its messages. The hotkey is
+    /// unregistered when this is dropped.
+    pub struct HotkeyThread {
+        thread_id: u32,
+        handle: Option<JoinHandle<()>>,
+    }
+
+    impl HotkeyThread {
+        pub fn spawn(hotkey: Hotkey, pressed: Arc<AtomicBool>) -> Result<Self> {
+            let (started, result) = mpsc::channel();
+            let handle = thread::spawn(move || {
+                // WM_HOTKEY is posted to the thread that registered the hotkey
+                match unsafe { RegisterHotKey(None, HOTKEY_ID, modifiers(hotkey), hotkey.key) } {
+                    Ok(()) => {
+                        let _ = started.send(Ok(unsafe { GetCurrentThreadId() }));
+                        run_message_loop(&pressed);
+                        let _ = unsafe { UnregisterHotKey(None, HOTKEY_ID) };
+                    }
+                    Err(e) => {
+                        let _ = started.send(Err(anyhow::Error::from(e)));
+                    }
+                }
+            });
+
+            let thread_id = result
+                .recv()
+                .map_err(|_| anyhow!("Hotkey thread exited"))??;
+            Ok(HotkeyThread {
+                thread_id,
+                handle: Some(handle),
+            })
+        }
+    }
+
+    impl Drop for HotkeyThread {
+        fn drop(&mut self) {
+            unsafe {
+                let _ = PostThreadMessageW(self.thread_id, WM_QUIT, WPARAM(0), LPARAM(0));
+            }
+            if let Some(handle) = self.handle.take() {
+                let _ = handle.join();
+            }
+        }
+    }
+
+    fn modifiers(hotkey: Hotkey) -> HOT_KEY_MODIFIERS {
+        // Holding the keys down doesn't repeat the panic
+        let mut modifiers = MOD_NOREPEAT;
+        for (held, modifier) in [
+            (hotkey.ctrl, MOD_CONTROL),
+            (hotkey.alt, MOD_ALT),
+            (hotkey.shift, MOD_SHIFT),
+            (hotkey.win, MOD_WIN),
+        ] {
+            if held {
+                modifiers |= modifier;
+            }
+        }
+        modifiers
+    }
+
+    fn run_message_loop(pressed: &AtomicBool) {
+        let mut message = MSG::default();
+        unsafe {
+            while GetMessageW(&mut message, None, 0, 0).as_bool() {
+                if message.message == WM_HOTKEY {
+                    info!("Panic hotkey pressed");
+                    pressed.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hotkeys_parse_modifiers_and_keys() {
+        let hotkey = Hotkey::parse("Ctrl + alt+M").unwrap();
+        assert!(hotkey.ctrl && hotkey.alt && !hotkey.shift && !hotkey.win);
+        assert_eq!(hotkey.key, 'M' as u32);
+        assert_eq!(Hotkey::parse("shift+F12").unwrap().key, 0x7B);
+        assert_eq!(Hotkey::parse("Pause").unwrap().key, 0x13);
+
+        assert!(Hotkey::parse("Ctrl+F25").is_err());
+        assert!(Hotkey::parse("Hyper+M").is_err());
+        assert!(Hotkey::parse("Ctrl+").is_err());
+    }
+}
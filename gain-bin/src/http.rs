@@ -0,0 +1,163 @@
+use crate::config::LoadedConfig;
+use crate::stats::Stats;
+use crate::volume::VolumeBackend;
+use anyhow::Result;
+use std::time::Duration;
+
+/// Longest time an HTTP request waits before the main loop picks it up.
+pub const HTTP_POLL: Duration = Duration::from_millis(20);
+
+#[cfg(feature = "http")]
+pub use server::HttpApi;
+
+/// Stand-in used when gain is built without the `http` feature. It can't be constructed, so
+/// configuring `http_port` fails at startup instead of being silently ignored.
+#[cfg(not(feature = "http"))]
+pub enum HttpApi {}
+
+#[cfg(not(feature = "http"))]
+impl HttpApi {
+    pub fn bind(_port: u16) -> Result<Self> {
+        Err(anyhow::anyhow!(
+            "`http_port` is set, but gain was built without the `http` feature"
+        ))
+    }
+
+    pub fn serve_pending(
+        &mut self,
+        _config: &LoadedConfig,
+        _backend: &mut dyn VolumeBackend,
+        _stats: &Stats,
+    ) {
+        match *self {}
+    }
+}
+
+#[cfg(feature = "http")]
+mod server {
+    use super::*;
+    use crate::{config::VolumeTarget, get_target_volume, matcher::AppMatcher, set_target_volume};
+    use anyhow::anyhow;
+    use log::{debug, info, warn};
+    use std::net::Ipv4Addr;
+    use tiny_http::{Header, Method, Request, Response, Server};
+
+    /// Local HTTP API to read and set volumes:
+    ///
+    /// - `GET /volume/{target}` returns `{"volume": 0.5}`, or 404 if the volume is unknown
+    ///   (e.g. no matching app is running).
+    /// - `POST /volume/{target}` with a scalar body such as `0.5` sets the volume.
+    /// - `GET /stats` returns the lifetime [`Stats`] counters, e.g. `{"frames": 1200, ...}`.
+    ///
+    /// `{target}` is `master`, `current`, `unmapped`, `microphone`, `system_sounds`, or
+    /// `app/{name}`, with app
+    /// names matched like the ones in the configuration.
+    ///
+    /// Requests are answered from the main loop through the same functions sliders use, so
+    /// they behave identically and never touch the audio system from another thread.
+    pub struct HttpApi {
+        server: Server,
+    }
+
+    impl HttpApi {
+        /// Starts listening on `port` on the loopback interface.
+        pub fn bind(port: u16) -> Result<Self> {
+            let server = Server::http((Ipv4Addr::LOCALHOST, port)).map_err(|e| anyhow!(e))?;
+            info!("HTTP API listening on http://localhost:{}", port);
+            Ok(HttpApi { server })
+        }
+
+        /// Answers every request received since the last call, without blocking.
+        pub fn serve_pending(
+            &mut self,
+            config: &LoadedConfig,
+            backend: &mut dyn VolumeBackend,
+            stats: &Stats,
+        ) {
+            loop {
+                match self.server.try_recv() {
+                    Ok(Some(request)) => serve(request, config, backend, stats),
+                    Ok(None) => break,
+                    Err(e) => {
+                        warn!("HTTP API failed to receive a request: {}", e);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    fn serve(
+        mut request: Request,
+        config: &LoadedConfig,
+        backend: &mut dyn VolumeBackend,
+        stats: &Stats,
+    ) {
+        debug!("HTTP {} {}", request.method(), request.url());
+
+        let response = match parse_target(request.url(), config) {
+            _ if request.url() == "/stats" => match request.method() {
+                Method::Get => match serde_json::to_string(&stats.counts()) {
+                    Ok(counts) => Response::from_string(format!("{}\n", counts))
+                        .with_header(json_content_type()),
+                    Err(e) => text(500, &e.to_string()),
+                },
+                _ => text(405, "Only GET is supported"),
+            },
+            Ok(Some(target)) => match request.method() {
+                Method::Get => match get_target_volume(&target, config, backend) {
+                    Some(volume) => Response::from_string(format!("{{\"volume\":{}}}\n", volume))
+                        .with_header(json_content_type()),
+                    None => text(404, "Volume unknown"),
+                },
+                Method::Post => {
+                    let mut body = String::new();
+                    let volume = request
+                        .as_reader()
+                        .read_to_string(&mut body)
+                        .ok()
+                        .and_then(|_| body.trim().parse::<f64>().ok())
+                        .filter(|volume| (0.0..=1.0).contains(volume));
+                    match volume {
+                        Some(volume) => match set_target_volume(&target, volume, config, backend) {
+                            Ok(()) => Response::from_data(Vec::new()).with_status_code(204),
+                            Err(e) => text(500, &e.to_string()),
+                        },
+                        None => text(400, "Body must be a volume from 0.0 to 1.0"),
+                    }
+                }
+                _ => text(405, "Only GET and POST are supported"),
+            },
+            Ok(None) => text(404, "Unknown target"),
+            Err(e) => text(400, &e.to_string()),
+        };
+
+        if let Err(e) = request.respond(response) {
+            debug!("Failed to send HTTP response: {}", e);
+        }
+    }
+
+    /// Parses the target out of a `/volume/...` path.
+    fn parse_target(url: &str, config: &LoadedConfig) -> Result<Option<VolumeTarget>> {
+        let Some(target) = url.strip_prefix("/volume/") else {
+            return Ok(None);
+        };
+
+        Ok(match target.split_once('/') {
+            Some(("app", name)) if !name.is_empty() => Some(VolumeTarget::Apps(vec![
+                config.general.matcher(AppMatcher::from(name.to_string()))?,
+            ])),
+            Some(_) => None,
+            // App names only go under `app/`
+            None => VolumeTarget::builtin(target),
+        })
+    }
+
+    fn json_content_type() -> Header {
+        Header::from_bytes("Content-Type", "application/json").unwrap()
+    }
+
+    fn text(status: u16, message: &str) -> Response<std::io::Cursor<Vec<u8>>> {
+        Response::from_string(format!("{}\n", message)).with_status_code(status)
+    }
+}
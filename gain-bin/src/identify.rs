@@ -0,0 +1,26 @@
+use crate::{config::LoadedConfig, devices::offset_ids, open_port, read_message};
+use anyhow::Result;
+use gain_lib::Message;
+use std::{collections::BTreeSet, io::BufReader};
+
+/// Runs `gain identify`: prints the ID and value of every slider that moves until
+/// interrupted, so each physical slider can be matched to the ID it is configured by.
+/// Nothing is applied to any volume.
+pub fn run(config: &LoadedConfig, _config_path: &str) -> Result<()> {
+    let mut reader = BufReader::new(open_port(&config.connection)?);
+    let mut seen = BTreeSet::new();
+
+    println!("Move each slider to see its ID, press Ctrl-C to stop.");
+
+    let mut buffer = Vec::new();
+    loop {
+        let message = read_message(&mut reader, &mut buffer)?
+            .map(|message| offset_ids(message, config.connection.id_offset));
+        if let Some(Message::Slider(slider)) = message {
+            if seen.insert(slider.id) {
+                println!("Found slider {}", slider.id);
+            }
+            println!("  slider {:>3}  value {:>4}", slider.id, slider.value);
+        }
+    }
+}
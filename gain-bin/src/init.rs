@@ -0,0 +1,113 @@
+use anyhow::{Result, anyhow};
+use serialport::SerialPortType;
+use std::{fs, path::Path};
+
+/// Starter configuration written by `gain init`. `{connection}` is replaced by the detected
+/// port settings.
+const TEMPLATE: &str = r#"# Generated by `gain init`. See the README for every option.
+
+[connection]
+{connection}
+# If baud_rate is not specified, 57600 will be used. It must match the firmware
+baud_rate = 57600
+
+[general]
+# The values from the hardware mixer will be rounded to the nearest multiple of volume_step.
+# If your potentiometers are very noisy, you may want to increase this value.
+volume_step = 0.01
+# If true, inverts the slider direction (i.e., turning the potentiometer clockwise decreases volume)
+invert_direction = false
+# If true, moving a slider to 0 mutes its target and keeps the previous volume level for when it is unmuted
+mute_on_zero = false
+
+[[slider]]
+# The ID of the slider, starting from 0
+id = 0
+# `master` controls the master volume
+target = "master"
+
+[[slider]]
+id = 1
+# `current` controls the volume of the focused application
+target = "current"
+
+[[slider]]
+id = 2
+# Replace these with the apps you want on this slider; `gain list-sessions` shows their names
+target = { apps = ["spotify.exe", "firefox.exe"] }
+
+[[slider]]
+id = 3
+# `unmapped` controls every application not mapped to another slider
+target = "unmapped"
+
+[[slider]]
+id = 4
+# `microphone` controls the input level of the default recording device
+target = "microphone"
+"#;
+
+/// Runs `gain init`: writes a starter configuration to `path`, with the first USB serial
+/// port found filled in, refusing to overwrite an existing file.
+pub fn run(path: &str) -> Result<()> {
+    if Path::new(path).exists() {
+        return Err(anyhow!("{} already exists, not overwriting it", path));
+    }
+
+    let port = serialport::available_ports()?
+        .into_iter()
+        .find(|port| matches!(port.port_type, SerialPortType::UsbPort(_)))
+        .map(|port| port.port_name);
+    match &port {
+        Some(port) => println!("Found USB serial port {}", port),
+        None => println!("No USB serial port found, the first port will be used"),
+    }
+
+    if let Some(dir) = Path::new(path).parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, starter_config(port.as_deref()))?;
+    println!("Wrote {}, edit its slider targets to taste", path);
+    Ok(())
+}
+
+/// Fills in the template for the serial port `port`, if one was found.
+fn starter_config(port: Option<&str>) -> String {
+    let connection = match port {
+        Some(port) => format!(
+            "# Detected when this file was generated; `gain list-ports` shows the others\n\
+             com_port = {:?}",
+            port
+        ),
+        None => {
+            "# If com_port is not specified, the first port that passes all filters will be used\n\
+                 # com_port = \"COM3\""
+                .to_string()
+        }
+    };
+    TEMPLATE.replace("{connection}", &connection)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, LoadedConfig};
+
+    fn load(contents: &str) -> LoadedConfig {
+        let config: Config = toml::from_str(contents).unwrap();
+        LoadedConfig::new(config, std::time::SystemTime::now()).unwrap()
+    }
+
+    #[test]
+    fn starter_config_loads() {
+        let config = load(&starter_config(None));
+        assert_eq!(config.connection.com_port, None);
+        assert_eq!(config.mappings().len(), 5);
+    }
+
+    #[test]
+    fn detected_port_is_filled_in() {
+        let config = load(&starter_config(Some(r"\\.\COM12")));
+        assert_eq!(config.connection.com_port.as_deref(), Some(r"\\.\COM12"));
+    }
+}
@@ -0,0 +1,35 @@
+use anyhow::{Result, anyhow};
+use std::fs::{File, OpenOptions, TryLockError};
+
+/// Name of the lock file in the system's temporary directory.
+const LOCK_FILE: &str = "gain.lock";
+
+/// Exclusive lock held for as long as gain runs, so a second instance doesn't fight the
+/// first one over the serial port and the volumes.
+///
+/// The operating system releases the lock when the file is closed, including when the
+/// process crashes, so a stale lock file never blocks a restart.
+pub struct InstanceLock {
+    _file: File,
+}
+
+impl InstanceLock {
+    /// Takes the lock, failing if another instance already holds it.
+    pub fn acquire() -> Result<Self> {
+        let path = std::env::temp_dir().join(LOCK_FILE);
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(&path)?;
+
+        match file.try_lock() {
+            Ok(()) => Ok(InstanceLock { _file: file }),
+            Err(TryLockError::WouldBlock) => Err(anyhow!(
+                "Another instance of gain is already running (lock file {})",
+                path.display()
+            )),
+            Err(TryLockError::Error(e)) => Err(e.into()),
+        }
+    }
+}
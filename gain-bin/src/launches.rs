@@ -0,0 +1,83 @@
+use crate::{
+    config::{LoadedConfig, VolumeTarget},
+    volume::VolumeBackend,
+};
+use anyhow::Result;
+use log::debug;
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
+
+/// How often audio sessions are checked for newly launched apps while
+/// `general.reapply_on_launch` has volumes to apply.
+pub const LAUNCH_POLL: Duration = Duration::from_secs(1);
+
+/// Applies the last volume of app and `unmapped` sliders to audio sessions that appear
+/// after the slider moved, such as an app launched (or restarted, under a new process ID)
+/// mid-session, which would otherwise start at whatever volume the system remembers.
+#[derive(Default)]
+pub struct Launches {
+    /// Last volume sent by each app or `unmapped` slider, by slider ID.
+    volumes: HashMap<u8, f64>,
+    /// Sessions seen on the last poll, by process ID and name, or `None` before the first.
+    known: Option<HashSet<(u32, String)>>,
+    last_poll: Option<Instant>,
+}
+
+impl Launches {
+    /// Records a volume slider `id` just applied to its app or `unmapped` target.
+    pub fn record(&mut self, id: u8, volume: f64) {
+        self.volumes.insert(id, volume);
+    }
+
+    /// Applies the recorded volumes to every session that appeared since the last poll.
+    /// The first poll only learns which sessions already exist.
+    pub fn poll(&mut self, config: &LoadedConfig, backend: &mut dyn VolumeBackend) -> Result<()> {
+        if self.volumes.is_empty() {
+            return Ok(());
+        }
+        let now = Instant::now();
+        if self
+            .last_poll
+            .is_some_and(|last| now.duration_since(last) < LAUNCH_POLL)
+        {
+            return Ok(());
+        }
+        self.last_poll = Some(now);
+
+        let sessions: HashSet<(u32, String)> = backend
+            .sessions()?
+            .into_iter()
+            .filter_map(|session| Some((session.pid, session.name?)))
+            .collect();
+        let Some(known) = self.known.replace(sessions.clone()) else {
+            return Ok(());
+        };
+
+        for (pid, name) in sessions.difference(&known) {
+            for (id, &volume) in &self.volumes {
+                let Some(mapping) = config.mappings().get(id) else {
+                    continue;
+                };
+                match &mapping.target {
+                    VolumeTarget::Apps(apps) => {
+                        for app in apps.iter().filter(|app| app.matches(name)) {
+                            debug!("{} (PID {}) started, applying volume {}", name, pid, volume);
+                            backend.set_app(app, app.weighted(volume))?;
+                        }
+                    }
+                    VolumeTarget::Unmapped { exclude, include } => {
+                        let unmapped = config.unmapped_apps(exclude, include.as_deref());
+                        if unmapped.matches(name) {
+                            debug!("{} (PID {}) started, applying volume {}", name, pid, volume);
+                            backend.set_unmapped(volume, &unmapped)?;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+}
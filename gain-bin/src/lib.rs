@@ -0,0 +1,1847 @@
+//! The gain desktop host. The `gain-bin` binary is a thin wrapper over [`main`]. With the
+//! `tokio` feature, [`run`] runs the host on an existing tokio runtime instead, for
+//! embedding it into a larger async app.
+
+mod calibrate;
+mod coalesce;
+mod config;
+mod devices;
+mod error;
+mod faders;
+mod filter;
+mod foreground;
+mod hotkey;
+mod http;
+mod identify;
+mod init;
+mod instance;
+mod launches;
+mod logging;
+mod matcher;
+mod midi;
+mod monitor;
+mod mqtt;
+mod osc;
+mod pending;
+mod pickup;
+mod pins;
+mod ramp;
+#[cfg(feature = "tokio")]
+mod runtime;
+mod shutdown;
+mod slider;
+mod solo;
+mod stats;
+mod status;
+mod throttle;
+mod tray;
+mod volume;
+
+#[cfg(not(feature = "tokio"))]
+use config::LoadedConfig;
+#[cfg(feature = "tokio")]
+pub use config::LoadedConfig;
+#[cfg(feature = "tokio")]
+pub use runtime::run;
+#[cfg(feature = "tokio")]
+pub use tokio_util::sync::CancellationToken;
+
+use anyhow::{Result, anyhow};
+use gain_lib::{
+    Button, Encoder, FaderPosition, Hello, HostMessage, LedState, LevelState, MAX_FRAME_LEN,
+    Message, PROTOCOL_VERSION, Slider,
+};
+use log::{debug, info, trace, warn};
+use serialport::{SerialPort, SerialPortInfo, SerialPortType, UsbPortInfo};
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    io::{BufRead, Read, Write},
+    process::ExitCode,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    coalesce::Coalescer,
+    config::{AudioBackend, ButtonMode, Connection, RestoreOnExit, SliderMode, VolumeTarget},
+    devices::{DeviceEvent, DeviceEvents},
+    error::GainError,
+    faders::{FADER_POLL, Faders},
+    filter::HostFilters,
+    foreground::{FOREGROUND_POLL, Foreground, SessionWait},
+    hotkey::{Hotkey, PANIC_POLL, PanicHotkey},
+    http::{HTTP_POLL, HttpApi},
+    instance::InstanceLock,
+    launches::Launches,
+    matcher::AppMatcher,
+    midi::MidiOut,
+    mqtt::MqttPublisher,
+    osc::OscOut,
+    pending::Pending,
+    pickup::Pickups,
+    ramp::{RAMP_TICK, Ramps},
+    shutdown::Shutdown,
+    slider::{Deadzone, Quantizer},
+    solo::Solos,
+    stats::{Stats, StatsLog},
+    status::{SliderStatus, StatusServer},
+    throttle::Throttle,
+    tray::Tray,
+    volume::{DryRunBackend, SkipUnchanged, VolumeBackend},
+};
+
+/// Time to wait for device messages while nothing else needs the main loop, kept short so
+/// a shutdown request is noticed soon.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(1);
+/// Delay before retrying a port that failed to open in [`open_port`].
+const STARTUP_RETRY_DELAY: Duration = Duration::from_millis(500);
+/// Upper bound for the exponential backoff between attempts in [`open_port`].
+const MAX_STARTUP_RETRY_DELAY: Duration = Duration::from_secs(4);
+
+/// Optional outputs and servers that run alongside the volume backend.
+#[derive(Default)]
+struct Services {
+    status: Option<StatusServer>,
+    http: Option<HttpApi>,
+    mqtt: Option<MqttPublisher>,
+    midi: MidiOut,
+    osc: OscOut,
+    tray: Tray,
+    panic: PanicHotkey,
+    /// Shared with the device readers, and kept across reconnects and reloads.
+    stats: Arc<Stats>,
+    stats_log: StatsLog,
+}
+
+/// Per-slider state, kept while the devices are connected and reset when the connection
+/// settings change.
+#[derive(Default)]
+struct Session {
+    ramps: Ramps,
+    deadzone: Deadzone,
+    quantizer: Quantizer,
+    leds: Pending<bool>,
+    levels: Pending<u8>,
+    filters: HostFilters,
+    coalescer: Coalescer,
+    foreground: Foreground,
+    session_wait: SessionWait,
+    launches: Launches,
+    solos: Solos,
+    faders: Faders,
+    pickups: Pickups,
+    /// Rate limits for errors that can repeat on every message or poll.
+    logic_errors: Throttle,
+    poll_errors: Throttle,
+    /// Last volume each relative slider set, for targets whose volume can't be read back.
+    encoder_levels: HashMap<u8, f64>,
+    /// Mute state each toggle button last set, for targets whose mute can't be read back.
+    toggled: HashMap<u8, bool>,
+}
+
+/// Runs the command given on the command line, the host by default, and reports its error
+/// if it fails. This is the whole binary.
+pub fn main() -> ExitCode {
+    match run_command() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            if let Some(hint) = e.downcast_ref::<GainError>().and_then(GainError::hint) {
+                eprintln!("\nHint: {}", hint);
+            }
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run_command() -> Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    // Listing commands, given the config path that follows them, if any
+    let command: Option<fn(Option<String>) -> Result<()>> = match args.first().map(String::as_str) {
+        Some("list-ports") => Some(|_| list_ports()),
+        Some("list-sessions") => Some(list_sessions),
+        Some("list-devices") => Some(list_devices),
+        _ => None,
+    };
+    if let Some(command) = command {
+        pretty_env_logger::init();
+        return command(args.get(1).cloned());
+    }
+    if args.first().map(String::as_str) == Some("init") {
+        pretty_env_logger::init();
+        let path = args
+            .get(1)
+            .cloned()
+            .or_else(|| std::env::var("GAIN_CONFIG").ok());
+        return init::run(path.as_deref().unwrap_or(config::CONFIG_FILE_NAME));
+    }
+
+    // Modes that only talk to the device, using the config's connection settings. `skip` is
+    // how many arguments the mode itself takes, counting its name
+    type Watcher = Box<dyn FnOnce(&LoadedConfig, &str) -> Result<()>>;
+    let (watcher, skip): (Option<Watcher>, usize) = match args.first().map(String::as_str) {
+        Some("calibrate") => (Some(Box::new(calibrate::run)), 1),
+        Some("monitor") => (Some(Box::new(monitor::run)), 1),
+        Some("identify") => (Some(Box::new(identify::run)), 1),
+        Some("set-pin") => {
+            let pin = pins::parse(&args[1..])?;
+            (Some(Box::new(move |config, _| pins::run(config, pin))), 3)
+        }
+        _ => (None, 0),
+    };
+    let mut config_path = None;
+    let mut dry_run = false;
+    let mut args = args.into_iter().skip(skip);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dry-run" => dry_run = true,
+            "--config" => {
+                let path = args
+                    .next()
+                    .ok_or_else(|| anyhow!("--config needs a path"))?;
+                config_path = Some(path);
+            }
+            _ => {
+                config_path.get_or_insert(arg);
+            }
+        }
+    }
+    let config_path = config::resolve_path(config_path);
+    if !std::path::Path::new(&config_path).exists() {
+        return Err(anyhow!(
+            "Config file {} not found, run `gain init` to create a starter one",
+            config_path
+        ));
+    }
+
+    // Held until main returns, so the log file is flushed on exit
+    let _logger = logging::init(config::log_file(&config_path)?.as_deref())?;
+    info!("Using config file: {}", config_path);
+
+    if let Some(watcher) = watcher {
+        return watcher(&LoadedConfig::new_from_file(&config_path)?, &config_path);
+    }
+
+    // Held until main returns
+    let _instance = InstanceLock::acquire()?;
+    let config = LoadedConfig::new_from_file(&config_path)?;
+
+    #[cfg(feature = "tokio")]
+    return runtime::run_until_ctrl_c(config, &config_path, dry_run);
+
+    #[cfg(not(feature = "tokio"))]
+    host(
+        config,
+        &config_path,
+        dry_run,
+        &Shutdown::install()?,
+        &|config, stats| Box::new(devices::Devices::spawn(config.connections(), stats)),
+    )
+}
+
+/// Starts reading the devices of a configuration, sharing the stats with their readers.
+type SpawnDevices<'a> = &'a dyn Fn(&LoadedConfig, &Arc<Stats>) -> Box<dyn DeviceEvents>;
+
+/// Applies the messages of every device to the volume backend until a shutdown is
+/// requested, then applies `restore_on_exit`. The devices are read with `spawn_devices`,
+/// again whenever a reload changes the connection settings. The volume backend is created
+/// and used on the calling thread only.
+fn host(
+    mut config: LoadedConfig,
+    config_path: &str,
+    dry_run: bool,
+    shutdown: &Shutdown,
+    spawn_devices: SpawnDevices,
+) -> Result<()> {
+    let mut backend = volume::new_backend(config.general.backend)?;
+    if dry_run {
+        info!("Dry run: volume changes will be logged but not applied");
+        backend = Box::new(DryRunBackend::new(backend));
+    }
+    let mut backend = SkipUnchanged::new(backend);
+
+    let startup_volumes = capture_volumes(&config, &mut backend);
+
+    let mut services = Services {
+        status: match config.general.status_port {
+            Some(port) => Some(StatusServer::bind(port)?),
+            None => None,
+        },
+        http: match config.general.http_port {
+            Some(port) => Some(HttpApi::bind(port)?),
+            None => None,
+        },
+        mqtt: match &config.general.mqtt {
+            Some(mqtt) => Some(MqttPublisher::connect(mqtt)?),
+            None => None,
+        },
+        midi: MidiOut::default(),
+        osc: OscOut::default(),
+        tray: Tray::start(shutdown),
+        panic: PanicHotkey::start(
+            config
+                .general
+                .panic_hotkey
+                .as_deref()
+                .map(Hotkey::parse)
+                .transpose()?,
+        ),
+        stats: Arc::default(),
+        stats_log: StatsLog::default(),
+    };
+
+    while !shutdown.requested() {
+        process_devices(
+            &mut config,
+            config_path,
+            &mut backend,
+            shutdown,
+            &mut services,
+            spawn_devices,
+        );
+    }
+
+    info!("Shutting down");
+    restore_volumes(&config, &startup_volumes, &mut backend);
+    // Release the audio system (and COM on Windows) before exiting
+    drop(backend);
+    Ok(())
+}
+
+/// Records the current volume of every slider target in any profile, so
+/// `restore_on_exit = true` can set them back on exit whichever profiles were used. Each app
+/// is recorded separately, and targets whose volume can't be read (e.g. apps that aren't
+/// running) are skipped.
+fn capture_volumes(
+    config: &LoadedConfig,
+    backend: &mut dyn VolumeBackend,
+) -> Vec<(VolumeTarget, f64)> {
+    if !matches!(
+        config.general.restore_on_exit,
+        Some(RestoreOnExit::Startup(true))
+    ) {
+        return Vec::new();
+    }
+
+    let mut targets: Vec<VolumeTarget> = Vec::new();
+    for mapping in config.all_mappings() {
+        let split = match &mapping.target {
+            VolumeTarget::Apps(apps) => apps
+                .iter()
+                .map(|app| VolumeTarget::Apps(vec![app.clone()]))
+                .collect(),
+            target => vec![target.clone()],
+        };
+        for target in split {
+            // The top-level sliders are part of every profile
+            if !targets.contains(&target) {
+                targets.push(target);
+            }
+        }
+    }
+
+    targets
+        .into_iter()
+        .filter_map(|target| {
+            let volume = get_target_volume(&target, config, backend)?;
+            Some((target, volume))
+        })
+        .collect()
+}
+
+/// Applies `restore_on_exit`: either the volumes captured at startup or the explicit ones
+/// from the configuration. Apps that aren't running are skipped.
+fn restore_volumes(
+    config: &LoadedConfig,
+    startup_volumes: &[(VolumeTarget, f64)],
+    backend: &mut dyn VolumeBackend,
+) {
+    let volumes = match config.general.restore_on_exit {
+        Some(RestoreOnExit::Startup(true)) => startup_volumes,
+        Some(RestoreOnExit::Volumes(_)) => &config.restore_volumes,
+        _ => return,
+    };
+
+    for (target, volume) in volumes {
+        if let Err(e) = set_target_volume(target, *volume, config, backend) {
+            warn!("Failed to restore volume: {}", e);
+        }
+    }
+}
+
+/// Prints every serial port with the USB details that the connection filters match on.
+fn list_ports() -> Result<()> {
+    let mut ports = serialport::available_ports()?;
+    ports.sort_by(|a, b| a.port_name.cmp(&b.port_name));
+    if ports.is_empty() {
+        println!("No serial ports found");
+    }
+
+    for port in ports {
+        println!("{}", port.port_name);
+        match port.port_type {
+            SerialPortType::UsbPort(info) => {
+                println!("  vid_filter = 0x{:04X}", info.vid);
+                println!("  pid_filter = 0x{:04X}", info.pid);
+                if let Some(serial_number) = info.serial_number {
+                    println!("  serial_number_filter = {:?}", serial_number);
+                }
+                if let Some(manufacturer) = info.manufacturer {
+                    println!("  manufacturer_filter = {:?}", manufacturer);
+                }
+                if let Some(product) = info.product {
+                    println!("  product_filter = {:?}", product);
+                }
+            }
+            SerialPortType::BluetoothPort => println!("  Bluetooth port"),
+            SerialPortType::PciPort => println!("  PCI port"),
+            SerialPortType::Unknown => println!("  Unknown port type"),
+        }
+    }
+    Ok(())
+}
+
+/// Returns the audio backend that `general.backend` chooses in the config file at
+/// `config_path`, or the default one when there is no config file.
+fn configured_backend(config_path: Option<String>) -> Result<AudioBackend> {
+    let config_path = config::resolve_path(config_path);
+    if !std::path::Path::new(&config_path).exists() {
+        return Ok(AudioBackend::default());
+    }
+    Ok(LoadedConfig::new_from_file(&config_path)?.general.backend)
+}
+
+/// Prints the process name and ID of every current audio session, i.e. the names that app
+/// targets can use.
+fn list_sessions(config_path: Option<String>) -> Result<()> {
+    let mut backend = volume::new_backend(configured_backend(config_path)?)?;
+    let sessions = backend.sessions()?;
+    if sessions.is_empty() {
+        println!("No audio sessions found");
+    }
+
+    for session in sessions {
+        let name = match (session.name, session.pid) {
+            (Some(name), _) => name,
+            (None, 0) => "(system sounds)".into(),
+            (None, _) => "(unknown)".into(),
+        };
+        println!("{:>8}  {}", session.pid, name);
+    }
+    Ok(())
+}
+
+/// Prints the name of every output device, i.e. the names that device targets can use.
+fn list_devices(config_path: Option<String>) -> Result<()> {
+    let mut backend = volume::new_backend(configured_backend(config_path)?)?;
+    let devices = backend.devices()?;
+    if devices.is_empty() {
+        println!("No output devices found");
+    }
+
+    for device in devices {
+        println!("{}", device);
+    }
+    Ok(())
+}
+
+/// Resolves and opens the serial port described by the connection configuration. When
+/// scanning finds several candidates, the first that answers a ping is used, so another USB
+/// serial device that passes the filters isn't mistaken for the board.
+fn get_port(connection_config: &Connection) -> Result<Box<dyn SerialPort>, GainError> {
+    let names = resolve_port_names(connection_config)?;
+    if let [name] = names.as_slice() {
+        return open_named(connection_config, name);
+    }
+
+    for name in &names {
+        let mut port = match open_named(connection_config, name) {
+            Ok(port) => port,
+            Err(e) => {
+                warn!("{}, trying the next port", e);
+                continue;
+            }
+        };
+        if devices::answers_ping(port.as_mut(), connection_config.read_timeout()) {
+            return Ok(port);
+        }
+        info!("{} didn't answer a ping, trying the next port", name);
+    }
+
+    // Firmware that predates pings never answers, so it is used the way it was before
+    warn!(
+        "No port answered a ping, using {} as the first that matches",
+        names[0]
+    );
+    open_named(connection_config, &names[0])
+}
+
+/// Opens the serial port called `name` with the configured baud rate.
+fn open_named(
+    connection_config: &Connection,
+    name: &str,
+) -> Result<Box<dyn SerialPort>, GainError> {
+    info!("Connecting to {}...", name);
+    serialport::new(name, connection_config.baud_rate)
+        .timeout(connection_config.read_timeout())
+        .open()
+        .map_err(|source| GainError::SerialOpen {
+            port: name.to_string(),
+            source,
+        })
+}
+
+/// Opens the serial port like [`get_port`], for the modes that only talk to the device.
+/// Failures are retried with an exponential backoff for up to the connection's
+/// `startup_retry_secs`, so the device can be plugged in after the mode was started.
+fn open_port(connection_config: &Connection) -> Result<Box<dyn SerialPort>, GainError> {
+    let deadline = Instant::now() + Duration::from_secs(connection_config.startup_retry_secs);
+    let mut retry_delay = STARTUP_RETRY_DELAY;
+    loop {
+        match get_port(connection_config) {
+            Ok(port) => return Ok(port),
+            Err(e) => {
+                let left = deadline.saturating_duration_since(Instant::now());
+                if left.is_zero() {
+                    return Err(e);
+                }
+                warn!("{}, retrying for {}s", e, left.as_secs().max(1));
+                std::thread::sleep(retry_delay.min(left));
+                retry_delay = (retry_delay * 2).min(MAX_STARTUP_RETRY_DELAY);
+            }
+        }
+    }
+}
+
+/// Reads the next message from a port opened by [`open_port`], for the modes that only
+/// watch the device. Returns `None` if the read timed out or the frame was dropped.
+fn read_message(reader: &mut impl BufRead, buffer: &mut Vec<u8>) -> Result<Option<Message>> {
+    Ok(read_frame(reader, buffer)?
+        .and_then(|frame| frame.inspect_err(|e| warn!("Dropping frame: {}", e)).ok()))
+}
+
+/// Resolves the serial port names to try, in order. If a port name is provided in the
+/// configuration, it is the only one. Otherwise, the function scans for available USB serial
+/// ports and returns every one that passes all configured filters, sorted by port name.
+fn resolve_port_names(connection_config: &Connection) -> Result<Vec<String>, GainError> {
+    if let Some(name) = &connection_config.com_port {
+        return Ok(vec![name.clone()]);
+    }
+
+    info!("No port specified, scanning for USB devices...");
+    let mut ports = serialport::available_ports().map_err(GainError::ListPorts)?;
+    // Sort so the same device is picked every time when several of them match
+    ports.sort_by(|a, b| a.port_name.cmp(&b.port_name));
+
+    let matching: Vec<SerialPortInfo> = ports
+        .into_iter()
+        .filter(|p| match &p.port_type {
+            SerialPortType::UsbPort(info) => matches_filters(connection_config, info),
+            _ => false,
+        })
+        .collect();
+
+    for port in &matching {
+        if let SerialPortType::UsbPort(info) = &port.port_type {
+            info!(
+                "Found USB device on {}: VID=0x{:04X}, PID=0x{:04X}, SN={:?}, MN={:?}, PN={:?}",
+                port.port_name,
+                info.vid,
+                info.pid,
+                info.serial_number,
+                info.manufacturer,
+                info.product
+            );
+        }
+    }
+
+    if matching.is_empty() {
+        return Err(GainError::PortNotFound {
+            filtered: connection_config.has_filters(),
+        });
+    }
+    Ok(matching.into_iter().map(|port| port.port_name).collect())
+}
+
+/// Checks a USB device against every filter set in the connection configuration.
+fn matches_filters(connection_config: &Connection, info: &UsbPortInfo) -> bool {
+    let vid_ok = connection_config
+        .vid_filter
+        .is_none_or(|vid| info.vid == vid);
+    let pid_ok = connection_config
+        .pid_filter
+        .is_none_or(|pid| info.pid == pid);
+    let sn_ok = connection_config
+        .serial_number_filter
+        .as_ref()
+        .is_none_or(|sn| info.serial_number.as_ref() == Some(sn));
+    let mn_ok = connection_config
+        .manufacturer_filter
+        .as_ref()
+        .is_none_or(|mn| info.manufacturer.as_ref() == Some(mn));
+    let prod_ok = connection_config
+        .product_filter
+        .as_ref()
+        .is_none_or(|pn| info.product.as_ref() == Some(pn));
+
+    vid_ok && pid_ok && sn_ok && mn_ok && prod_ok
+}
+
+/// Handles the messages of every serial device until a shutdown is requested or a config
+/// reload changes the connection settings, so the devices can be reopened with them. The
+/// devices are read apart from this loop, such as on threads of their own with [`Devices`],
+/// and closed on return.
+fn process_devices(
+    config: &mut LoadedConfig,
+    config_path: &str,
+    backend: &mut dyn VolumeBackend,
+    shutdown: &Shutdown,
+    services: &mut Services,
+    spawn_devices: SpawnDevices,
+) {
+    let mut devices = spawn_devices(config, &services.stats);
+    let offsets: Vec<u8> = config.connections().map(|c| c.id_offset).collect();
+    let mut ports: HashMap<usize, Box<dyn Write + Send>> = HashMap::new();
+    let mut session = Session::default();
+
+    while !shutdown.requested() {
+        // Wake up regularly while ramping, filtering or coalescing so volumes keep moving
+        // without serial data, and while serving HTTP so requests don't wait on the slider
+        let timeout = if session.ramps.is_active()
+            || session.filters.is_settling()
+            || session.coalescer.is_pending()
+        {
+            RAMP_TICK
+        } else if services.http.is_some() {
+            HTTP_POLL
+        } else if services.panic.is_registered() {
+            PANIC_POLL
+        } else if session.foreground.is_tracking() || session.session_wait.is_waiting() {
+            FOREGROUND_POLL
+        } else if Faders::is_following(config) {
+            FADER_POLL
+        } else {
+            IDLE_TIMEOUT
+        };
+
+        match devices.recv(timeout) {
+            Some(DeviceEvent::Connected(device, port)) => {
+                ports.insert(device, port);
+                // The device starts with every LED off
+                session.leds.resend();
+                session.levels.resend();
+            }
+            Some(DeviceEvent::Disconnected(device)) => {
+                ports.remove(&device);
+            }
+            Some(DeviceEvent::Message(message)) => {
+                if let Err(e) = dispatch(message, config, backend, &mut session, services) {
+                    session
+                        .logic_errors
+                        .warn(format_args!("Logic Error: {}", e));
+                }
+            }
+            None => {}
+        }
+
+        let leds = session.leds.take_pending();
+        pending::send_all(&mut ports, &offsets, &leds, "update the LEDs", |id, on| {
+            HostMessage::Led(LedState { id, on })
+        });
+        let levels = session.levels.take_pending();
+        pending::send_all(
+            &mut ports,
+            &offsets,
+            &levels,
+            "update the level LEDs",
+            |id, level| HostMessage::Level(LevelState { id, level }),
+        );
+        let moves = session
+            .faders
+            .poll(config, backend, &session.ramps, Instant::now());
+        pending::send_all(
+            &mut ports,
+            &offsets,
+            &moves,
+            "move the faders",
+            |id, value| HostMessage::Fader(FaderPosition { id, value }),
+        );
+
+        if services.tray.take_reload() {
+            config.request_reload();
+        }
+        if services.panic.take_pressed() {
+            panic_volume(config, backend, &mut session);
+        }
+        match config.reload_if_needed(config_path) {
+            Ok(true) => {
+                info!("Connection settings changed, reopening the serial ports");
+                break;
+            }
+            Ok(false) => {}
+            Err(e) => warn!("Config reload failed: {}", e),
+        }
+
+        step_sliders(&mut session, config, backend, services);
+        step_ramps(&mut session.ramps, config, backend);
+        let focus_ramp = Duration::from_millis(config.general.focus_ramp_ms);
+        if let Err(e) = session
+            .foreground
+            .poll(backend, &mut session.ramps, focus_ramp)
+        {
+            session
+                .poll_errors
+                .warn(format_args!("Failed to follow the focused app: {}", e));
+        }
+        if let Err(e) = session.session_wait.poll(backend) {
+            session.poll_errors.warn(format_args!(
+                "Failed to check the focused app for an audio session: {}",
+                e
+            ));
+        }
+        if let Err(e) = session.launches.poll(config, backend) {
+            session.poll_errors.warn(format_args!(
+                "Failed to check for newly launched apps: {}",
+                e
+            ));
+        }
+
+        if let Some(http) = &mut services.http {
+            http.serve_pending(config, backend, &services.stats);
+        }
+        services.stats_log.poll(&services.stats, Instant::now());
+    }
+
+    // Solo sliders are engaged again when they next move
+    session.solos.restore_all(backend);
+}
+
+/// Reads the next frame from `reader` and decodes it, without its `0x00` terminator.
+/// Returns `None` if the read timed out before a whole frame arrived, leaving the partial
+/// frame in `buffer` for the next call.
+///
+/// Every delimiter starts a new frame, so a frame that fails to decode (such as the tail of
+/// one that was cut off by a reconnect) only costs that frame. Empty frames between
+/// consecutive delimiters are skipped, and bytes that run past the longest possible frame
+/// without a delimiter are dropped up to the next one, so garbage can't grow `buffer`.
+fn read_frame(
+    reader: &mut impl BufRead,
+    buffer: &mut Vec<u8>,
+) -> Result<Option<Result<Message, GainError>>> {
+    loop {
+        let limit = MAX_FRAME_LEN.saturating_sub(buffer.len()) as u64;
+        match reader.by_ref().take(limit).read_until(0x00, buffer) {
+            // Timeouts surface as errors, so reading nothing means the device went away
+            Ok(0) => return Err(anyhow!("Serial device disconnected")),
+            Ok(_) if buffer.last() == Some(&0x00) => {
+                buffer.pop();
+                if buffer.is_empty() {
+                    continue;
+                }
+                let message = gain_lib::decode_frame(buffer).map_err(GainError::from);
+                buffer.clear();
+                return Ok(Some(message));
+            }
+            Ok(_) if buffer.len() >= MAX_FRAME_LEN => {
+                buffer.clear();
+                match reader.skip_until(0x00) {
+                    Ok(_) => {}
+                    // The rest is dropped as a frame that fails to decode
+                    Err(e) if e.kind() == std::io::ErrorKind::TimedOut => {}
+                    Err(e) => return Err(e.into()),
+                }
+                return Ok(Some(Err(GainError::Decode(
+                    postcard::Error::DeserializeBadEncoding,
+                ))));
+            }
+            // The stream ended mid-frame, which the next read reports
+            Ok(_) => return Ok(None),
+            Err(e) if e.kind() == std::io::ErrorKind::TimedOut => return Ok(None),
+            Err(e) => return Err(e.into()), // Critical IO error, break the loop to reconnect
+        }
+    }
+}
+
+/// Acts on a message received from the device.
+fn dispatch(
+    message: Message,
+    config: &mut LoadedConfig,
+    backend: &mut dyn VolumeBackend,
+    session: &mut Session,
+    services: &mut Services,
+) -> Result<()> {
+    match message {
+        Message::Slider(_) | Message::Button(_) | Message::Encoder(_) if services.tray.paused() => {
+            trace!("Paused, ignoring {:?}", message);
+            Ok(())
+        }
+        Message::Slider(slider) => manage_slider(slider, config, backend, session, services),
+        Message::Encoder(encoder) => manage_encoder(encoder, config, backend, session, services),
+        Message::Button(button) => manage_button(button, config, backend, session),
+        Message::Heartbeat => {
+            trace!("Heartbeat");
+            Ok(())
+        }
+        Message::Raw(reading) => {
+            trace!("Raw reading: {:?}", reading);
+            Ok(())
+        }
+        Message::Hello(hello) => {
+            check_protocol(hello);
+            Ok(())
+        }
+        Message::PinConfig(pin) => {
+            debug!("Pin settings: {:?}", pin);
+            Ok(())
+        }
+        // Pongs are matched to their pings by the device readers
+        Message::Pong(pong) => {
+            trace!("Pong: {:?}", pong);
+            Ok(())
+        }
+    }
+}
+
+/// Checks the protocol version the firmware announced. A mismatch is only warned about:
+/// frames that still decode are used as usual, and the others are dropped like corrupted
+/// ones, so a device that is mostly compatible keeps working.
+fn check_protocol(hello: Hello) {
+    match hello.version.cmp(&PROTOCOL_VERSION) {
+        Ordering::Equal => info!("Firmware speaks protocol version {}", hello.version),
+        Ordering::Less => warn!(
+            "Firmware speaks protocol version {}, older than this host's {}; reflash it from this version of gain",
+            hello.version, PROTOCOL_VERSION
+        ),
+        Ordering::Greater => warn!(
+            "Firmware speaks protocol version {}, newer than this host's {}; update gain to match it",
+            hello.version, PROTOCOL_VERSION
+        ),
+    }
+}
+
+/// Manages the volume adjustment logic based on the received slider data and configuration.
+fn manage_slider(
+    slider: Slider,
+    config: &LoadedConfig,
+    backend: &mut dyn VolumeBackend,
+    session: &mut Session,
+    services: &mut Services,
+) -> Result<()> {
+    if !config.mappings().contains_key(&slider.id) {
+        trace!("Unmapped slider ID: {}", slider.id);
+        return Ok(());
+    }
+
+    let now = Instant::now();
+    let window = Duration::from_millis(config.general.coalesce_ms);
+    let value = session
+        .filters
+        .push(slider.id, slider.value, config.general.host_filter, now);
+    match session.coalescer.offer(slider.id, value, now, window) {
+        Some(value) => apply_slider(
+            Slider { value, ..slider },
+            config,
+            backend,
+            session,
+            services,
+        ),
+        None => {
+            services.stats.skipped();
+            Ok(())
+        }
+    }
+}
+
+/// Moves every host-filtered slider value that is still settling, and applies the result
+/// along with the values coalescing held back.
+fn step_sliders(
+    session: &mut Session,
+    config: &LoadedConfig,
+    backend: &mut dyn VolumeBackend,
+    services: &mut Services,
+) {
+    let now = Instant::now();
+    let window = Duration::from_millis(config.general.coalesce_ms);
+    let settled: Vec<(u8, u16)> = session
+        .filters
+        .step(config.general.host_filter, now)
+        .into_iter()
+        .filter_map(|(id, value)| Some((id, session.coalescer.offer(id, value, now, window)?)))
+        .collect();
+    let held_back = session.coalescer.due(now, window);
+
+    for (id, value) in settled.into_iter().chain(held_back) {
+        if let Err(e) = apply_slider(Slider { id, value }, config, backend, session, services) {
+            session
+                .logic_errors
+                .warn(format_args!("Logic Error: {}", e));
+        }
+    }
+}
+
+/// Applies a (filtered) slider position to its target.
+fn apply_slider(
+    slider: Slider,
+    config: &LoadedConfig,
+    backend: &mut dyn VolumeBackend,
+    session: &mut Session,
+    services: &mut Services,
+) -> Result<()> {
+    let Some(mapping) = config.mappings().get(&slider.id) else {
+        return Ok(());
+    };
+
+    session
+        .faders
+        .record(slider.id, slider.value, Instant::now());
+    let value = slider::calibrate(slider.value, mapping);
+    if !session
+        .deadzone
+        .accept(slider.id, value, config.general.deadzone)
+    {
+        trace!("Ignoring jitter on slider {}: {}", slider.id, slider.value);
+        services.stats.skipped();
+        return Ok(());
+    }
+
+    let volume = session
+        .quantizer
+        .slider_volume(slider.id, value, &config.general, mapping);
+    let final_vol = slider::scale_volume(volume, mapping);
+    if config.general.pickup_mode
+        && !session.pickups.catches(
+            slider.id,
+            &mapping.target,
+            final_vol,
+            config.general.volume_step,
+            || get_target_volume(&mapping.target, config, backend),
+        )
+    {
+        trace!("Slider {} hasn't picked up its target yet", slider.id);
+        services.stats.skipped();
+        return Ok(());
+    }
+
+    services.stats.applied();
+    // Setting a volume unmutes the target
+    if session.toggled.remove(&slider.id) == Some(true) {
+        session.leds.set(slider.id, false);
+    }
+    apply_volume(slider, final_vol, config, backend, session, services)
+}
+
+/// Manages rotary encoder ticks for sliders in relative mode, moving the target's current
+/// volume by one `volume_step` per tick.
+fn manage_encoder(
+    encoder: Encoder,
+    config: &LoadedConfig,
+    backend: &mut dyn VolumeBackend,
+    session: &mut Session,
+    services: &mut Services,
+) -> Result<()> {
+    let Some(mapping) = config.mappings().get(&encoder.id) else {
+        trace!("Unmapped encoder ID: {}", encoder.id);
+        return Ok(());
+    };
+    if mapping.mode != SliderMode::Relative {
+        warn!(
+            "Slider {} sent encoder ticks, set `mode = \"relative\"` to use them",
+            encoder.id
+        );
+        return Ok(());
+    }
+
+    // A ramp in progress is where earlier ticks are heading, not where the target is now
+    let current = session
+        .ramps
+        .target(encoder.id)
+        .or_else(|| get_target_volume(&mapping.target, config, backend))
+        .or_else(|| session.encoder_levels.get(&encoder.id).copied())
+        .unwrap_or(0.0);
+    let ticks = if mapping.inverted(&config.general) {
+        encoder.delta.saturating_neg()
+    } else {
+        encoder.delta
+    };
+    let (min, max) = mapping.volume_range();
+    let final_vol = slider::step_volume(current, ticks, config.general.volume_step).clamp(min, max);
+    session.encoder_levels.insert(encoder.id, final_vol);
+
+    let slider = Slider {
+        id: encoder.id,
+        value: (final_vol * slider::MAX_RAW_VALUE as f64).round() as u16,
+    };
+    apply_volume(slider, final_vol, config, backend, session, services)
+}
+
+/// Sends a volume computed for a slider to its target and to everything that follows it.
+fn apply_volume(
+    slider: Slider,
+    final_vol: f64,
+    config: &LoadedConfig,
+    backend: &mut dyn VolumeBackend,
+    session: &mut Session,
+    services: &mut Services,
+) -> Result<()> {
+    let Some(mapping) = config.mappings().get(&slider.id) else {
+        return Ok(());
+    };
+
+    let status = SliderStatus {
+        slider,
+        volume: final_vol,
+        target: &mapping.target,
+    };
+    if let Some(server) = &mut services.status {
+        server.broadcast(&status);
+    }
+    if let Some(mqtt) = &mut services.mqtt {
+        mqtt.publish(&status);
+    }
+
+    for cc in mapping.midi_ccs() {
+        services
+            .midi
+            .send(cc, final_vol, config.general.midi_port.as_deref());
+    }
+    for osc in mapping.osc_controls() {
+        services.osc.send(osc, final_vol);
+    }
+
+    // Both muting and a zero volume leave the target muted
+    let mute = config.general.mutes_at(final_vol);
+    session.leds.set(slider.id, mute || final_vol <= 0.0);
+    if config.general.level_leds {
+        // Sent from 0 to 255
+        let level = if mute { 0.0 } else { final_vol.clamp(0.0, 1.0) };
+        session
+            .levels
+            .set(slider.id, (level * u8::MAX as f64).round() as u8);
+    }
+
+    match &mapping.target {
+        VolumeTarget::Foreground => {
+            let focused = backend.focused_pid()?;
+            session.foreground.record(slider.id, final_vol, focused)
+        }
+        VolumeTarget::Solo { app, duck_to } => {
+            let volume = if mute { 0.0 } else { final_vol };
+            session
+                .solos
+                .apply(slider.id, app, *duck_to, volume, backend)?
+        }
+        VolumeTarget::Apps(_) | VolumeTarget::Unmapped { .. }
+            if config.general.reapply_on_launch =>
+        {
+            session.launches.record(slider.id, final_vol)
+        }
+        _ => {}
+    }
+    if matches!(
+        mapping.target,
+        VolumeTarget::CurrentApp | VolumeTarget::Foreground
+    ) {
+        if mute {
+            session.session_wait.cancel();
+        } else {
+            session.session_wait.record(final_vol, backend)?;
+        }
+    }
+
+    if mute {
+        session.ramps.cancel(slider.id);
+        // Leave the scalar untouched so unmuting restores the previous level
+        set_target_mute(&mapping.target, true, config, backend)
+    } else if config.general.ramp_ms > 0 {
+        let duration = Duration::from_millis(config.general.ramp_ms);
+        session.ramps.start(slider.id, final_vol, duration, || {
+            get_target_volume(&mapping.target, config, backend)
+        });
+        Ok(())
+    } else {
+        set_target_volume(&mapping.target, final_vol, config, backend)
+    }
+}
+
+/// Moves every ramp in progress one step closer to its target volume.
+fn step_ramps(ramps: &mut Ramps, config: &LoadedConfig, backend: &mut dyn VolumeBackend) {
+    for (id, volume) in ramps.step() {
+        if let Some(mapping) = config.mappings().get(&id)
+            && let Err(e) = set_target_volume(&mapping.target, volume, config, backend)
+        {
+            warn!("Logic Error: {}", e);
+        }
+    }
+}
+
+/// Drops master, and with `panic_apps` every named audio session, to `panic_volume`,
+/// leaving quieter targets alone. Ramps in progress are stopped so they don't raise the
+/// volume again; sliders take over as soon as they move.
+fn panic_volume(config: &LoadedConfig, backend: &mut dyn VolumeBackend, session: &mut Session) {
+    let level = config.general.panic_volume;
+    warn!("Panic hotkey: dropping the volume to {}", level);
+    session.ramps.cancel_all();
+    session.session_wait.cancel();
+    // The sliders' last volumes no longer match, so the next position is applied even if
+    // the slider barely moved
+    backend.forget_applied();
+
+    let mut targets = vec![VolumeTarget::Master];
+    if config.general.panic_apps {
+        match backend.sessions() {
+            Ok(sessions) => {
+                let mut names: Vec<String> = sessions.into_iter().filter_map(|s| s.name).collect();
+                names.sort_unstable_by_key(|name| name.to_lowercase());
+                names.dedup_by_key(|name| name.to_lowercase());
+                targets.extend(
+                    names
+                        .into_iter()
+                        .map(|name| AppMatcher::from(name).ignoring(&config.general.ignore))
+                        .map(|app| VolumeTarget::Apps(vec![app])),
+                );
+            }
+            Err(e) => warn!("Failed to list the audio sessions: {}", e),
+        }
+    }
+
+    for target in targets {
+        if get_target_volume(&target, config, backend).is_some_and(|volume| volume <= level) {
+            continue;
+        }
+        if let Err(e) = set_target_volume(&target, level, config, backend) {
+            warn!("Failed to apply the panic volume: {}", e);
+        }
+    }
+}
+
+/// Manages button presses. A button shares its ID with a slider and mutes that slider's
+/// target for as long as it is held down, or with `button = "toggle"` mutes or unmutes it
+/// on every press, except for the profile button, which switches to the next profile on
+/// every press.
+fn manage_button(
+    button: Button,
+    config: &mut LoadedConfig,
+    backend: &mut dyn VolumeBackend,
+    session: &mut Session,
+) -> Result<()> {
+    if config.general.profile_button == Some(button.id) {
+        if button.pressed {
+            config.next_profile();
+        }
+        return Ok(());
+    }
+
+    let Some(mapping) = config.mappings().get(&button.id) else {
+        trace!("Unmapped button ID: {}", button.id);
+        return Ok(());
+    };
+    match mapping.button {
+        ButtonMode::Momentary => {
+            session.leds.set(button.id, button.pressed);
+            set_target_mute(&mapping.target, button.pressed, config, backend)
+        }
+        ButtonMode::Toggle if !button.pressed => Ok(()),
+        ButtonMode::Toggle => {
+            // Read back where possible, so a mute changed elsewhere is flipped from where it
+            // actually is
+            let muted = get_target_mute(&mapping.target, backend)
+                .unwrap_or_else(|| session.toggled.get(&button.id).copied().unwrap_or(false));
+            set_target_mute(&mapping.target, !muted, config, backend)?;
+            session.toggled.insert(button.id, !muted);
+            session.leds.set(button.id, !muted);
+            Ok(())
+        }
+    }
+}
+
+/// Sets the volume of every application or device covered by `target`.
+fn set_target_volume(
+    target: &VolumeTarget,
+    volume: f64,
+    config: &LoadedConfig,
+    backend: &mut dyn VolumeBackend,
+) -> Result<()> {
+    match target {
+        VolumeTarget::Master => backend.set_master(volume),
+        VolumeTarget::Microphone => backend.set_microphone(volume),
+        VolumeTarget::SystemSounds => backend.set_system_sounds(volume),
+        VolumeTarget::CurrentApp | VolumeTarget::Foreground => backend.set_current_app(volume),
+        VolumeTarget::WindowTitle(title) if focus_has_title(title, backend)? => {
+            backend.set_current_app(volume)
+        }
+        VolumeTarget::WindowTitle(_) => Ok(()),
+        VolumeTarget::Unmapped { exclude, include } => {
+            backend.set_unmapped(volume, &config.unmapped_apps(exclude, include.as_deref()))
+        }
+        VolumeTarget::Device { name } => backend.set_device(name, volume),
+        // The other apps are ducked by `apply_volume`
+        VolumeTarget::Solo { app, .. } => backend.set_app(app, volume),
+        // Sent by `apply_volume`, there's no audio volume to set. Groups are resolved to
+        // their apps on loading
+        VolumeTarget::Midi(_) | VolumeTarget::Osc(_) | VolumeTarget::Group(_) => Ok(()),
+        VolumeTarget::Apps(apps) => {
+            for app in apps {
+                if let Err(e) = backend.set_app(app, app.weighted(volume)) {
+                    warn!("Failed to set volume for app {}: {}", app, e);
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Returns the current volume of `target`, or `None` if it can't be determined (e.g. no
+/// matching application is running).
+fn get_target_volume(
+    target: &VolumeTarget,
+    config: &LoadedConfig,
+    backend: &mut dyn VolumeBackend,
+) -> Option<f64> {
+    let volume = match target {
+        VolumeTarget::Master => backend.get_master().map(Some),
+        VolumeTarget::Microphone => backend.get_microphone().map(Some),
+        VolumeTarget::SystemSounds => backend.get_system_sounds(),
+        VolumeTarget::CurrentApp | VolumeTarget::Foreground => backend.get_current_app(),
+        VolumeTarget::WindowTitle(title) => match focus_has_title(title, backend) {
+            Ok(true) => backend.get_current_app(),
+            result => result.map(|_| None),
+        },
+        VolumeTarget::Unmapped { exclude, include } => {
+            backend.get_unmapped(&config.unmapped_apps(exclude, include.as_deref()))
+        }
+        VolumeTarget::Device { name } => backend.get_device(name).map(Some),
+        VolumeTarget::Solo { app, .. } => backend.get_app(app),
+        VolumeTarget::Midi(_) | VolumeTarget::Osc(_) | VolumeTarget::Group(_) => Ok(None),
+        VolumeTarget::Apps(apps) => {
+            return apps.iter().find_map(|app| {
+                let volume = backend.get_app(app).ok().flatten()?;
+                app.unweighted(volume)
+            });
+        }
+    };
+
+    volume.unwrap_or_else(|e| {
+        debug!("Failed to read current volume: {}", e);
+        None
+    })
+}
+
+/// Returns whether `target` is muted, for the targets whose mute can be read back.
+fn get_target_mute(target: &VolumeTarget, backend: &mut dyn VolumeBackend) -> Option<bool> {
+    let muted = match target {
+        VolumeTarget::Master => backend.get_master_mute(),
+        VolumeTarget::Microphone => backend.get_microphone_mute(),
+        VolumeTarget::Device { name } => backend.get_device_mute(name),
+        _ => return None,
+    };
+
+    muted
+        .inspect_err(|e| debug!("Failed to read current mute: {}", e))
+        .ok()
+}
+
+/// Mutes or unmutes every application or device covered by `target`, without changing
+/// its volume level.
+fn set_target_mute(
+    target: &VolumeTarget,
+    mute: bool,
+    config: &LoadedConfig,
+    backend: &mut dyn VolumeBackend,
+) -> Result<()> {
+    match target {
+        VolumeTarget::Master => backend.set_master_mute(mute),
+        VolumeTarget::Microphone => backend.set_microphone_mute(mute),
+        VolumeTarget::SystemSounds => backend.set_system_sounds_mute(mute),
+        VolumeTarget::CurrentApp | VolumeTarget::Foreground => backend.set_current_app_mute(mute),
+        VolumeTarget::WindowTitle(title) if focus_has_title(title, backend)? => {
+            backend.set_current_app_mute(mute)
+        }
+        VolumeTarget::WindowTitle(_) => Ok(()),
+        VolumeTarget::Unmapped { exclude, include } => {
+            backend.set_unmapped_mute(mute, &config.unmapped_apps(exclude, include.as_deref()))
+        }
+        VolumeTarget::Device { name } => backend.set_device_mute(name, mute),
+        VolumeTarget::Solo { app, .. } => backend.set_app_mute(app, mute),
+        VolumeTarget::Midi(_) | VolumeTarget::Osc(_) | VolumeTarget::Group(_) => Ok(()),
+        VolumeTarget::Apps(apps) => {
+            for app in apps {
+                if let Err(e) = backend.set_app_mute(app, mute) {
+                    warn!("Failed to set mute for app {}: {}", app, e);
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Returns whether the title of the focused window contains `title`, ignoring case.
+fn focus_has_title(title: &str, backend: &mut dyn VolumeBackend) -> Result<bool> {
+    Ok(backend
+        .focused_title()?
+        .is_some_and(|focused| focused.to_lowercase().contains(&title.to_lowercase())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::{Config, MatchMode},
+        matcher::UnmappedApps,
+        volume::AudioSession,
+    };
+    use std::io::Cursor;
+
+    /// Backend that records every change made to it.
+    #[derive(Default)]
+    struct Recorder {
+        calls: Vec<String>,
+        /// Title reported for the focused window.
+        title: Option<String>,
+        /// Process reported as focused.
+        focused: Option<u32>,
+        /// Processes that have an unnamed audio session.
+        session_pids: Vec<u32>,
+        /// Volume of each running app, by name, which `set_app` keeps up to date.
+        volumes: HashMap<String, f64>,
+        /// Master mute, which `set_master_mute` keeps up to date.
+        master_muted: bool,
+    }
+
+    impl Recorder {
+        fn record(&mut self, call: String) -> Result<()> {
+            self.calls.push(call);
+            Ok(())
+        }
+    }
+
+    impl VolumeBackend for Recorder {
+        fn set_master(&mut self, volume: f64) -> Result<()> {
+            self.record(format!("master {volume}"))
+        }
+        fn set_master_mute(&mut self, mute: bool) -> Result<()> {
+            self.master_muted = mute;
+            self.record(format!("master mute {mute}"))
+        }
+        fn set_microphone(&mut self, volume: f64) -> Result<()> {
+            self.record(format!("microphone {volume}"))
+        }
+        fn set_microphone_mute(&mut self, mute: bool) -> Result<()> {
+            self.record(format!("microphone mute {mute}"))
+        }
+        fn set_current_app(&mut self, volume: f64) -> Result<()> {
+            self.record(format!("current {volume}"))
+        }
+        fn set_current_app_mute(&mut self, mute: bool) -> Result<()> {
+            self.record(format!("current mute {mute}"))
+        }
+        fn set_app(&mut self, app: &AppMatcher, volume: f64) -> Result<()> {
+            if let Some(level) = self.volumes.get_mut(app.pattern()) {
+                *level = volume;
+            }
+            self.record(format!("app {app} {volume}"))
+        }
+        fn set_app_mute(&mut self, app: &AppMatcher, mute: bool) -> Result<()> {
+            self.record(format!("app {app} mute {mute}"))
+        }
+        fn set_unmapped(&mut self, volume: f64, _unmapped: &UnmappedApps) -> Result<()> {
+            self.record(format!("unmapped {volume}"))
+        }
+        fn set_unmapped_mute(&mut self, mute: bool, _unmapped: &UnmappedApps) -> Result<()> {
+            self.record(format!("unmapped mute {mute}"))
+        }
+        fn set_system_sounds(&mut self, volume: f64) -> Result<()> {
+            self.record(format!("system sounds {volume}"))
+        }
+        fn set_system_sounds_mute(&mut self, mute: bool) -> Result<()> {
+            self.record(format!("system sounds mute {mute}"))
+        }
+        fn set_device(&mut self, device: &str, volume: f64) -> Result<()> {
+            self.record(format!("device {device} {volume}"))
+        }
+        fn set_device_mute(&mut self, device: &str, mute: bool) -> Result<()> {
+            self.record(format!("device {device} mute {mute}"))
+        }
+        fn get_master(&mut self) -> Result<f64> {
+            Ok(0.5)
+        }
+        fn get_microphone(&mut self) -> Result<f64> {
+            Ok(0.5)
+        }
+        fn get_master_mute(&mut self) -> Result<bool> {
+            Ok(self.master_muted)
+        }
+        fn get_microphone_mute(&mut self) -> Result<bool> {
+            Ok(false)
+        }
+        fn get_current_app(&mut self) -> Result<Option<f64>> {
+            Ok(None)
+        }
+        fn get_app(&mut self, app: &AppMatcher) -> Result<Option<f64>> {
+            Ok(self.volumes.get(app.pattern()).copied())
+        }
+        fn get_unmapped(&mut self, _unmapped: &UnmappedApps) -> Result<Option<f64>> {
+            Ok(None)
+        }
+        fn get_system_sounds(&mut self) -> Result<Option<f64>> {
+            Ok(None)
+        }
+        fn get_device(&mut self, _device: &str) -> Result<f64> {
+            Ok(0.5)
+        }
+        fn get_device_mute(&mut self, _device: &str) -> Result<bool> {
+            Ok(false)
+        }
+        fn devices(&mut self) -> Result<Vec<String>> {
+            Ok(Vec::new())
+        }
+        fn focused_pid(&mut self) -> Result<Option<u32>> {
+            Ok(self.focused)
+        }
+        fn focused_title(&mut self) -> Result<Option<String>> {
+            Ok(self.title.clone())
+        }
+        fn sessions(&mut self) -> Result<Vec<AudioSession>> {
+            Ok(self
+                .volumes
+                .keys()
+                .map(|name| AudioSession {
+                    pid: 0,
+                    name: Some(name.clone()),
+                })
+                .chain(
+                    self.session_pids
+                        .iter()
+                        .map(|&pid| AudioSession { pid, name: None }),
+                )
+                .collect())
+        }
+    }
+
+    const SLIDERS: &str = r#"
+        [[slider]]
+        id = 0
+        target = "master"
+
+        [[slider]]
+        id = 1
+        target = { apps = ["game.exe"] }
+    "#;
+
+    fn config() -> LoadedConfig {
+        let config: Config = toml::from_str(SLIDERS).unwrap();
+        LoadedConfig::new(config, std::time::SystemTime::now()).unwrap()
+    }
+
+    /// Encodes `messages` into one stream of frames, as the firmware sends them.
+    fn stream(messages: &[Message]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for message in messages {
+            let mut buf = [0; MAX_FRAME_LEN];
+            bytes.extend_from_slice(gain_lib::encode_frame(message, &mut buf).unwrap());
+        }
+        bytes
+    }
+
+    /// Reads every frame from `bytes` and dispatches it, returning the backend calls made.
+    fn run(bytes: Vec<u8>) -> Vec<String> {
+        let mut config = config();
+        let mut backend = Recorder::default();
+        let mut session = Session::default();
+        let mut services = Services::default();
+
+        let mut reader = Cursor::new(bytes);
+        let mut buffer = Vec::new();
+        while let Ok(Some(frame)) = read_frame(&mut reader, &mut buffer) {
+            if let Ok(message) = frame {
+                dispatch(
+                    message,
+                    &mut config,
+                    &mut backend,
+                    &mut session,
+                    &mut services,
+                )
+                .unwrap();
+            }
+        }
+        backend.calls
+    }
+
+    fn slider(id: u8, value: u16) -> Message {
+        Message::Slider(Slider { id, value })
+    }
+
+    #[test]
+    fn slider_frames_set_their_target() {
+        let calls = run(stream(&[slider(0, 1023), slider(1, 0)]));
+        assert_eq!(calls, ["master 1", "app game.exe 0"]);
+    }
+
+    #[test]
+    fn unmapped_sliders_are_ignored() {
+        assert!(run(stream(&[slider(7, 512)])).is_empty());
+    }
+
+    #[test]
+    fn buttons_mute_their_slider_target() {
+        let press = |pressed| Message::Button(Button { id: 1, pressed });
+        let calls = run(stream(&[press(true), press(false)]));
+        assert_eq!(calls, ["app game.exe mute true", "app game.exe mute false"]);
+    }
+
+    #[test]
+    fn weighted_apps_get_their_share_of_the_volume() {
+        let config: Config = toml::from_str(
+            r#"
+            [[slider]]
+            id = 0
+            target = { apps = [{ name = "spotify", weight = 1.0 }, { name = "discord", weight = 0.5 }] }
+            "#,
+        )
+        .unwrap();
+        let config = LoadedConfig::new(config, std::time::SystemTime::now()).unwrap();
+        let target = &config.mappings()[&0].target;
+        let mut backend = Recorder::default();
+        backend.volumes.insert("discord".into(), 0.0);
+
+        set_target_volume(target, 0.8, &config, &mut backend).unwrap();
+        assert_eq!(backend.calls, ["app spotify 0.8", "app discord 0.4"]);
+        // Read back as the slider volume that set it
+        assert_eq!(get_target_volume(target, &config, &mut backend), Some(0.8));
+    }
+
+    #[test]
+    fn toggle_buttons_flip_the_mute_on_each_press() {
+        let config: Config = toml::from_str(
+            r#"
+            [[slider]]
+            id = 0
+            target = "master"
+            button = "toggle"
+
+            [[slider]]
+            id = 1
+            target = { apps = ["game.exe"] }
+            button = "toggle"
+            "#,
+        )
+        .unwrap();
+        let mut config = LoadedConfig::new(config, std::time::SystemTime::now()).unwrap();
+        let mut backend = Recorder::default();
+        let mut session = Session::default();
+        let mut press = |id, backend: &mut Recorder| {
+            for pressed in [true, false] {
+                manage_button(Button { id, pressed }, &mut config, backend, &mut session).unwrap();
+            }
+        };
+
+        press(1, &mut backend);
+        press(1, &mut backend);
+        press(0, &mut backend);
+        // Unmuted elsewhere, so the next press mutes again
+        backend.master_muted = false;
+        press(0, &mut backend);
+        assert_eq!(
+            backend.calls,
+            [
+                "app game.exe mute true",
+                "app game.exe mute false",
+                "master mute true",
+                "master mute true"
+            ]
+        );
+    }
+
+    #[test]
+    fn startup_volumes_cover_every_profile() {
+        let config: Config = toml::from_str(
+            r#"
+            [general]
+            restore_on_exit = true
+
+            [[slider]]
+            id = 0
+            target = "master"
+
+            [[profile.gaming.slider]]
+            id = 1
+            target = { apps = ["game.exe"] }
+            "#,
+        )
+        .unwrap();
+        let config = LoadedConfig::new(config, std::time::SystemTime::now()).unwrap();
+        let mut backend = Recorder::default();
+        backend.volumes.insert("game.exe".into(), 0.3);
+
+        // The master volume is captured once, though both profiles map it
+        let game = VolumeTarget::Apps(vec![AppMatcher::from("game.exe".to_string())]);
+        assert_eq!(
+            capture_volumes(&config, &mut backend),
+            [(VolumeTarget::Master, 0.5), (game, 0.3)]
+        );
+    }
+
+    #[test]
+    fn sliders_that_havent_picked_up_leave_a_toggled_mute_alone() {
+        let config: Config = toml::from_str(
+            r#"
+            [general]
+            pickup_mode = true
+
+            [[slider]]
+            id = 0
+            target = "master"
+            button = "toggle"
+            "#,
+        )
+        .unwrap();
+        let mut config = LoadedConfig::new(config, std::time::SystemTime::now()).unwrap();
+        let mut backend = Recorder::default();
+        let mut session = Session::default();
+        let mut services = Services::default();
+        // The slider last controlled another target, so it has to pick up the master
+        // volume (0.5) first
+        session
+            .pickups
+            .catches(0, &VolumeTarget::Microphone, 0.5, 0.01, || None);
+        for pressed in [true, false] {
+            manage_button(
+                Button { id: 0, pressed },
+                &mut config,
+                &mut backend,
+                &mut session,
+            )
+            .unwrap();
+        }
+        session.leds.take_pending();
+
+        let slider = Slider { id: 0, value: 1023 };
+        apply_slider(slider, &config, &mut backend, &mut session, &mut services).unwrap();
+        assert_eq!(backend.calls, ["master mute true"]);
+        assert_eq!(session.toggled.get(&0), Some(&true));
+        assert!(session.leds.take_pending().is_empty());
+        let counts = services.stats.counts();
+        assert_eq!((counts.applied, counts.skipped), (0, 1));
+    }
+
+    #[test]
+    fn corrupted_frames_are_dropped() {
+        let mut bytes = stream(&[slider(0, 1023)]);
+        // Flip a bit of the slider value, inside the first frame
+        bytes[3] ^= 0x01;
+        bytes.extend(stream(&[slider(1, 1023)]));
+
+        let mut reader = Cursor::new(bytes.clone());
+        let mut buffer = Vec::new();
+        assert!(matches!(
+            read_frame(&mut reader, &mut buffer).unwrap(),
+            Some(Err(GainError::Decode(postcard::Error::DeserializeBadCrc)))
+        ));
+        // The next frame is still read in full
+        assert_eq!(run(bytes), ["app game.exe 1"]);
+    }
+
+    #[test]
+    fn truncated_frames_are_dropped_up_to_the_next_delimiter() {
+        // Reading starts in the middle of a frame, as after a reconnect, then two empty
+        // frames follow
+        let mut bytes = stream(&[slider(0, 1023)])[3..].to_vec();
+        bytes.extend([0x00, 0x00]);
+        bytes.extend(stream(&[slider(1, 1023)]));
+
+        let mut reader = Cursor::new(bytes.clone());
+        let mut buffer = Vec::new();
+        assert!(matches!(
+            read_frame(&mut reader, &mut buffer),
+            Ok(Some(Err(_)))
+        ));
+        assert!(matches!(
+            read_frame(&mut reader, &mut buffer),
+            Ok(Some(Ok(Message::Slider(Slider { id: 1, .. }))))
+        ));
+        assert_eq!(run(bytes), ["app game.exe 1"]);
+    }
+
+    #[test]
+    fn garbage_without_delimiters_is_skipped() {
+        let mut bytes = vec![0x55; 4 * MAX_FRAME_LEN];
+        bytes.push(0x00);
+        bytes.extend(stream(&[slider(1, 1023)]));
+
+        let mut reader = Cursor::new(bytes.clone());
+        let mut buffer = Vec::new();
+        assert!(matches!(
+            read_frame(&mut reader, &mut buffer),
+            Ok(Some(Err(_)))
+        ));
+        assert!(buffer.len() <= MAX_FRAME_LEN);
+        assert_eq!(run(bytes), ["app game.exe 1"]);
+    }
+
+    #[test]
+    fn end_of_stream_is_a_disconnect() {
+        let mut reader = Cursor::new(Vec::new());
+        assert!(read_frame(&mut reader, &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn window_title_targets_follow_matching_windows() {
+        let config = config();
+        let target = VolumeTarget::WindowTitle("youtube".into());
+        let mut backend = Recorder {
+            title: Some("Music - YouTube - Firefox".into()),
+            ..Recorder::default()
+        };
+        set_target_volume(&target, 0.5, &config, &mut backend).unwrap();
+
+        backend.title = Some("Inbox - Firefox".into());
+        set_target_volume(&target, 0.2, &config, &mut backend).unwrap();
+        set_target_mute(&target, true, &config, &mut backend).unwrap();
+        assert_eq!(backend.calls, ["current 0.5"]);
+    }
+
+    #[test]
+    fn focused_apps_get_the_volume_once_they_open_a_session() {
+        let config: Config = toml::from_str(
+            r#"
+            [[slider]]
+            id = 0
+            target = "current"
+            "#,
+        )
+        .unwrap();
+        let config = LoadedConfig::new(config, std::time::SystemTime::now()).unwrap();
+        let mut session = Session::default();
+        let mut backend = Recorder {
+            focused: Some(7),
+            ..Recorder::default()
+        };
+
+        apply_volume(
+            Slider { id: 0, value: 0 },
+            0.4,
+            &config,
+            &mut backend,
+            &mut session,
+            &mut Services::default(),
+        )
+        .unwrap();
+        assert!(session.session_wait.is_waiting());
+
+        backend.session_pids.push(7);
+        session.session_wait.poll(&mut backend).unwrap();
+        assert!(!session.session_wait.is_waiting());
+        assert_eq!(backend.calls, ["current 0.4", "current 0.4"]);
+    }
+
+    #[test]
+    fn unchanged_volumes_are_set_once_until_muted() {
+        let config = config();
+        let mut recorder = Recorder::default();
+        let mut backend = SkipUnchanged::new(&mut recorder);
+        let app = VolumeTarget::Apps(vec![AppMatcher::new("game.exe", MatchMode::Exact).unwrap()]);
+
+        set_target_volume(&VolumeTarget::Master, 0.5, &config, &mut backend).unwrap();
+        set_target_volume(&VolumeTarget::Master, 0.50001, &config, &mut backend).unwrap();
+        set_target_volume(&app, 0.5, &config, &mut backend).unwrap();
+        set_target_mute(&VolumeTarget::Master, true, &config, &mut backend).unwrap();
+        set_target_volume(&VolumeTarget::Master, 0.5, &config, &mut backend).unwrap();
+        set_target_volume(&app, 0.5, &config, &mut backend).unwrap();
+        assert_eq!(
+            recorder.calls,
+            [
+                "master 0.5",
+                "app game.exe 0.5",
+                "master mute true",
+                "master 0.5"
+            ]
+        );
+    }
+
+    #[test]
+    fn solo_ducks_the_other_apps_until_lowered() {
+        let mut backend = Recorder {
+            volumes: HashMap::from([
+                ("obs.exe".to_string(), 0.9),
+                ("game.exe".to_string(), 0.8),
+                ("chat.exe".to_string(), 0.6),
+                ("quiet.exe".to_string(), 0.1),
+            ]),
+            ..Recorder::default()
+        };
+        let app = |name| AppMatcher::new(name, MatchMode::Exact).unwrap();
+        let mut solos = Solos::default();
+
+        solos
+            .apply(0, &app("obs.exe"), 0.2, 0.7, &mut backend)
+            .unwrap();
+        assert_eq!(backend.volumes["game.exe"], 0.2);
+        assert_eq!(backend.volumes["chat.exe"], 0.2);
+        // Apps already below the ducked level and the solo app itself are left alone
+        assert_eq!(backend.volumes["quiet.exe"], 0.1);
+        assert_eq!(backend.volumes["obs.exe"], 0.9);
+
+        // Another slider takes over chat while it is ducked
+        backend.set_app(&app("chat.exe"), 0.5).unwrap();
+        solos
+            .apply(0, &app("obs.exe"), 0.2, 0.0, &mut backend)
+            .unwrap();
+        assert_eq!(backend.volumes["game.exe"], 0.8);
+        assert_eq!(backend.volumes["chat.exe"], 0.5);
+    }
+
+    #[test]
+    fn motorized_faders_follow_volumes_set_elsewhere() {
+        let config: Config = toml::from_str(
+            r#"
+            [[slider]]
+            id = 0
+            target = "master"
+            motorized = true
+            "#,
+        )
+        .unwrap();
+        let config = LoadedConfig::new(config, std::time::SystemTime::now()).unwrap();
+        let mut backend = Recorder::default();
+        let ramps = Ramps::default();
+        let mut faders = Faders::default();
+        let start = Instant::now();
+
+        // The recorder's master is at 0.5, far from where the fader was left
+        faders.record(0, 100, start);
+        assert!(faders.poll(&config, &mut backend, &ramps, start).is_empty());
+
+        let later = start + Duration::from_secs(2);
+        let moves = faders.poll(&config, &mut backend, &ramps, later);
+        assert_eq!(moves.len(), 1);
+        assert_eq!(moves[0].0, 0);
+        assert!(moves[0].1.abs_diff(512) <= 1, "moved to {}", moves[0].1);
+
+        // Not sent again while the motor gets there
+        let much_later = later + Duration::from_secs(2);
+        assert!(
+            faders
+                .poll(&config, &mut backend, &ramps, much_later)
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn panic_lowers_only_louder_targets() {
+        let mut config = config();
+        config.general.panic_volume = 0.2;
+        config.general.panic_apps = true;
+        let mut backend = Recorder {
+            volumes: HashMap::from([("game.exe".to_string(), 0.9), ("chat.exe".to_string(), 0.1)]),
+            ..Recorder::default()
+        };
+        let mut session = Session::default();
+        session
+            .ramps
+            .start(0, 1.0, Duration::from_secs(1), || Some(0.5));
+
+        panic_volume(&config, &mut backend, &mut session);
+        assert_eq!(backend.calls, ["master 0.2", "app game.exe 0.2"]);
+        assert!(!session.ramps.is_active());
+    }
+}
@@ -0,0 +1,38 @@
+use anyhow::Result;
+use flexi_logger::{
+    Cleanup, Criterion, Duplicate, FileSpec, Logger, LoggerHandle, Naming, default_format,
+    detailed_format,
+};
+
+/// Log level used when `RUST_LOG` isn't set while logging to a file, so the file is
+/// useful without extra setup.
+const DEFAULT_FILE_LEVEL: &str = "info";
+/// Size after which the log file is rotated.
+const MAX_LOG_SIZE: u64 = 10 * 1024 * 1024;
+/// Number of rotated log files kept besides the current one.
+const KEPT_LOG_FILES: usize = 5;
+
+/// Sets up logging to stderr, filtered by `RUST_LOG`. With `log_file`, every message is also
+/// written to that file with a timestamp, rotating it once it grows past [`MAX_LOG_SIZE`].
+///
+/// The returned handle flushes the file when dropped, so it must be kept until exit.
+pub fn init(log_file: Option<&str>) -> Result<Option<LoggerHandle>> {
+    let Some(log_file) = log_file else {
+        pretty_env_logger::init();
+        return Ok(None);
+    };
+
+    let logger = Logger::try_with_env_or_str(DEFAULT_FILE_LEVEL)?
+        .log_to_file(FileSpec::try_from(log_file)?)
+        .append()
+        .format_for_files(detailed_format)
+        .duplicate_to_stderr(Duplicate::All)
+        .format_for_stderr(default_format)
+        .rotate(
+            Criterion::Size(MAX_LOG_SIZE),
+            Naming::Timestamps,
+            Cleanup::KeepLogFiles(KEPT_LOG_FILES),
+        )
+        .start()?;
+    Ok(Some(logger))
+}
@@ -1,195 +1,5 @@
-mod config;
-mod volume;
+use std::process::ExitCode;
 
-use anyhow::{Result, anyhow};
-use gain_lib::Slider;
-use log::{error, info, trace, warn};
-use serialport::{SerialPort, SerialPortType};
-use std::{
-    io::{BufRead, BufReader},
-    time::Duration,
-};
-
-use crate::{
-    config::{Connection, LoadedConfig, VolumeTarget},
-    volume::{set_app_volume, set_current_app_volume, set_master_volume, set_unmapped_volume},
-};
-
-fn main() -> Result<()> {
-    pretty_env_logger::init();
-    volume::windows_init()?;
-
-    let config_path = std::env::args().nth(1).unwrap_or("gain.toml".into());
-    info!("Using config file: {}", config_path);
-
-    let mut config = LoadedConfig::new_from_file(&config_path)?;
-
-    loop {
-        if let Err(e) = config.reload_if_needed(&config_path) {
-            warn!("Failed to reload config: {}", e);
-        }
-
-        let port_name_result = resolve_port_name(&config.connection);
-
-        match port_name_result {
-            Ok(name) => {
-                info!("Connecting to {}...", name);
-
-                match serialport::new(&name, config.connection.baud_rate)
-                    .timeout(Duration::from_secs(30))
-                    .open()
-                {
-                    Ok(port) => {
-                        if let Err(e) = process_serial_stream(port, &mut config, &config_path) {
-                            error!("Serial connection lost: {}", e);
-                        }
-                    }
-                    Err(e) => warn!("Failed to open port {}: {}", name, e),
-                }
-            }
-            Err(e) => warn!("Port detection failed: {}", e),
-        }
-
-        std::thread::sleep(Duration::from_secs(5));
-    }
-}
-
-/// Resolves the serial port name to use. If a port name is provided in the configuration,
-/// it is used directly. Otherwise, the function scans for available USB serial ports
-/// and returns the first one found.
-fn resolve_port_name(connection_config: &Connection) -> Result<String> {
-    match &connection_config.com_port {
-        Some(name) => Ok(name.clone()),
-        None => {
-            info!("No port specified, scanning for USB devices...");
-            let ports = serialport::available_ports()?;
-
-            ports.into_iter().filter(|p| match &p.port_type {
-                SerialPortType::UsbPort(info) => {
-                    let vid_ok = connection_config
-                        .vid_filter
-                        .map_or(true, |vid| info.vid == vid);
-                    let pid_ok = connection_config
-                        .pid_filter
-                        .map_or(true, |pid| info.pid == pid);
-                    let sn_ok =
-                        connection_config
-                            .serial_number_filter
-                            .as_ref()
-                            .map_or(true, |sn| {
-                                info.serial_number
-                                    .as_ref()
-                                    .map_or(false, |device_sn| device_sn == sn)
-                            });
-                    let mn_ok = connection_config
-                        .manufacturer_filter
-                        .as_ref()
-                        .map_or(true, |mn| {
-                            info.manufacturer
-                                .as_ref()
-                                .map_or(false, |device_mn| device_mn == mn)
-                        });
-                    let prod_ok = connection_config
-                        .product_filter
-                        .as_ref()
-                        .map_or(true, |pn| {
-                            info.product
-                                .as_ref()
-                                .map_or(false, |device_pn| device_pn == pn)
-                        });
-                    if vid_ok && pid_ok && sn_ok && mn_ok && prod_ok {
-                        info!(
-                            "Found USB device: VID=0x{:04X}, PID=0x{:04X}, SN={:?}, MN={:?}, PN={:?}",
-                            info.vid, info.pid, info.serial_number, info.manufacturer, info.product
-                        );
-                        true
-                    } else {
-                        false
-                    }
-                }
-                _ => false,
-            }).nth(0).map(|p| {
-                info!("Found USB device on {}", p.port_name);
-                p.port_name
-            })
-            .ok_or_else(|| anyhow!("No USB serial device found"))
-        }
-    }
-}
-
-/// Processes incoming data from the serial port.
-/// Loops indefinitely, reading slider data, deserializing it.
-fn process_serial_stream(
-    port: Box<dyn SerialPort>,
-    config: &mut LoadedConfig,
-    config_path: &str,
-) -> Result<()> {
-    let mut reader = BufReader::new(port);
-    let mut buffer = Vec::new();
-
-    info!("Listening for slider data...");
-
-    loop {
-        buffer.clear();
-
-        match reader.read_until(0x00, &mut buffer) {
-            Ok(bytes_read) if bytes_read > 0 => {
-                if let Err(e) = config.reload_if_needed(config_path) {
-                    warn!("Config reload failed: {}", e);
-                }
-
-                if buffer.last() == Some(&0x00) {
-                    buffer.pop();
-                }
-
-                match postcard::from_bytes_cobs::<Slider>(&mut buffer) {
-                    Ok(slider) => {
-                        if let Err(e) = manage_slider(slider, config) {
-                            warn!("Logic Error: {}", e);
-                        }
-                    }
-                    Err(e) => warn!("Deserialization failed: {}", e),
-                }
-            }
-            Ok(_) => continue, // 0 bytes read, just loop
-            Err(e) if e.kind() == std::io::ErrorKind::TimedOut => continue,
-            Err(e) => return Err(e.into()), // Critical IO error, break the loop to reconnect
-        }
-    }
-}
-
-/// Manages the volume adjustment logic based on the received slider data and configuration.
-fn manage_slider(slider: Slider, config: &LoadedConfig) -> Result<()> {
-    let step = config.general.volume_step;
-    let raw_percent = slider.value as f64 / 1023.0;
-
-    // Snap to nearest step (e.g., if step is 0.05, snaps to 0.00, 0.05, 0.10)
-    let quantized = (raw_percent / step).round() * step;
-    let final_vol = quantized.clamp(0.0, 1.0);
-
-    let final_vol = if config.general.invert_direction {
-        1.0 - final_vol
-    } else {
-        final_vol
-    };
-
-    match config.mappings.get(&slider.id) {
-        Some(mapping) => match &mapping.target {
-            VolumeTarget::Master => set_master_volume(final_vol),
-            VolumeTarget::CurrentApp => set_current_app_volume(final_vol),
-            VolumeTarget::Unmapped => set_unmapped_volume(final_vol, &config.mapped_apps),
-            VolumeTarget::Apps(apps) => {
-                for app in apps {
-                    if let Err(e) = set_app_volume(app, final_vol) {
-                        warn!("Failed to set volume for app {}: {}", app, e);
-                    }
-                }
-                Ok(())
-            }
-        },
-        None => {
-            trace!("Unmapped slider ID: {}", slider.id);
-            Ok(())
-        }
-    }
+fn main() -> ExitCode {
+    gain::main()
 }
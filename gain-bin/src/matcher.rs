@@ -0,0 +1,366 @@
+// Only the Windows and PulseAudio backends have per-app volume to match apps against
+#![cfg_attr(
+    not(any(windows, all(target_os = "linux", feature = "pulseaudio"))),
+    allow(dead_code)
+)]
+
+use crate::config::MatchMode;
+use anyhow::{Context, Result, anyhow};
+use regex::{Regex, RegexBuilder};
+use serde::ser::SerializeStruct;
+use std::fmt;
+
+/// An application name from the configuration, matched against process names according
+/// to the configured [`MatchMode`]. All modes are case-insensitive.
+///
+/// A pattern containing a path separator is matched against the full path of the process
+/// executable instead, where the backend can tell it. In [`MatchMode::Regex`] only `/`
+/// counts, since `\` escapes, and paths are compared with `/` separators.
+///
+/// Deserializing only captures the pattern and matches exactly; [`AppMatcher::with_mode`]
+/// switches it to the configured mode once the whole config is known. An app is written as
+/// its name, or as `{ name = "discord", weight = 0.5 }` to scale the volumes set on it.
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(try_from = "AppEntry")]
+pub struct AppMatcher {
+    pattern: String,
+    rule: Rule,
+    /// Processes never matched, whatever the pattern, from `general.ignore`.
+    ignored: Vec<AppMatcher>,
+    /// Share of the slider's volume the matched apps are set to, from 0.0 to 1.0.
+    weight: f64,
+}
+
+/// An app as written in the configuration.
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum AppEntry {
+    Name(String),
+    Weighted {
+        name: String,
+        #[serde(default = "default_weight")]
+        weight: f64,
+    },
+}
+
+fn default_weight() -> f64 {
+    1.0
+}
+
+impl TryFrom<AppEntry> for AppMatcher {
+    type Error = anyhow::Error;
+
+    fn try_from(entry: AppEntry) -> Result<Self> {
+        match entry {
+            AppEntry::Name(name) => Ok(AppMatcher::from(name)),
+            AppEntry::Weighted { name, weight } => {
+                if !(0.0..=1.0).contains(&weight) {
+                    return Err(anyhow!(
+                        "The weight of app {} must be from 0.0 to 1.0, got {}",
+                        name,
+                        weight
+                    ));
+                }
+                Ok(AppMatcher {
+                    weight,
+                    ..AppMatcher::from(name)
+                })
+            }
+        }
+    }
+}
+
+/// Matchers are equal when they match the same apps the same way, see [`AppKey`].
+impl PartialEq for AppMatcher {
+    fn eq(&self, other: &Self) -> bool {
+        self.pattern == other.pattern && self.mode() == other.mode()
+    }
+}
+
+/// What an [`AppMatcher`] matches: its pattern and how it is matched, without the weight
+/// and ignore list, so it can identify the apps a volume was set on.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AppKey {
+    pattern: String,
+    mode: MatchMode,
+}
+
+#[derive(Debug, Clone)]
+enum Rule {
+    Exact(String),
+    Contains(String),
+    Regex(Regex),
+}
+
+impl From<String> for AppMatcher {
+    fn from(pattern: String) -> Self {
+        AppMatcher {
+            rule: Rule::Exact(base_name(&normalize_path(&pattern))),
+            pattern,
+            ignored: Vec::new(),
+            weight: default_weight(),
+        }
+    }
+}
+
+impl serde::Serialize for AppMatcher {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.weight == default_weight() {
+            return serializer.serialize_str(&self.pattern);
+        }
+        let mut entry = serializer.serialize_struct("AppMatcher", 2)?;
+        entry.serialize_field("name", &self.pattern)?;
+        entry.serialize_field("weight", &self.weight)?;
+        entry.end()
+    }
+}
+
+impl fmt::Display for AppMatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.pattern)
+    }
+}
+
+impl AppMatcher {
+    /// Creates a matcher for `pattern` using the given mode.
+    pub fn new(pattern: &str, mode: MatchMode) -> Result<Self> {
+        AppMatcher::from(pattern.to_string()).with_mode(mode)
+    }
+
+    /// Rebuilds this matcher for the given mode, failing if the pattern is not a valid
+    /// regular expression in [`MatchMode::Regex`].
+    pub fn with_mode(self, mode: MatchMode) -> Result<Self> {
+        let rule = match mode {
+            MatchMode::Exact => Rule::Exact(base_name(&normalize_path(&self.pattern))),
+            MatchMode::Contains => Rule::Contains(normalize_path(&self.pattern).to_lowercase()),
+            MatchMode::Regex => Rule::Regex(
+                RegexBuilder::new(&self.pattern)
+                    .case_insensitive(true)
+                    .build()
+                    .with_context(|| format!("Invalid app name pattern `{}`", self.pattern))?,
+            ),
+        };
+        Ok(AppMatcher { rule, ..self })
+    }
+
+    /// Returns a matcher for `pattern` with this matcher's weight, for an alias that stands
+    /// for `pattern`.
+    pub fn renamed(&self, pattern: String) -> Self {
+        AppMatcher {
+            weight: self.weight,
+            ..AppMatcher::from(pattern)
+        }
+    }
+
+    /// Makes this matcher skip every process matched by one of `ignored`.
+    pub fn ignoring(self, ignored: &[AppMatcher]) -> Self {
+        AppMatcher {
+            ignored: ignored.to_vec(),
+            ..self
+        }
+    }
+
+    /// The processes this matcher skips, see [`AppMatcher::ignoring`].
+    pub fn ignored(&self) -> &[AppMatcher] {
+        &self.ignored
+    }
+
+    /// Scales a slider's volume to the one set on the matched apps.
+    pub fn weighted(&self, volume: f64) -> f64 {
+        volume * self.weight
+    }
+
+    /// Turns the volume of a matched app back into the slider volume that set it, or
+    /// `None` if the weight is 0, as any slider volume sets it to 0.
+    pub fn unweighted(&self, volume: f64) -> Option<f64> {
+        (self.weight > 0.0).then(|| (volume / self.weight).min(1.0))
+    }
+
+    /// The pattern as written in the configuration.
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    /// How the pattern is matched.
+    pub fn mode(&self) -> MatchMode {
+        match self.rule {
+            Rule::Exact(_) => MatchMode::Exact,
+            Rule::Contains(_) => MatchMode::Contains,
+            Rule::Regex(_) => MatchMode::Regex,
+        }
+    }
+
+    /// Identifies the apps this matcher matches, see [`AppKey`].
+    pub fn key(&self) -> AppKey {
+        AppKey {
+            pattern: self.pattern.clone(),
+            mode: self.mode(),
+        }
+    }
+
+    /// Returns whether the pattern names an executable path rather than a process name.
+    pub fn is_path(&self) -> bool {
+        match self.rule {
+            Rule::Regex(_) => self.pattern.contains('/'),
+            _ => self.pattern.contains(['/', '\\']),
+        }
+    }
+
+    /// Returns whether matching needs the executable path, because the pattern or one of
+    /// the ignored ones is a path pattern. Backends only look the path up when it does.
+    // Only the Windows backend looks paths up
+    #[cfg_attr(not(windows), allow(dead_code))]
+    pub fn uses_path(&self) -> bool {
+        self.is_path() || self.ignored.iter().any(AppMatcher::uses_path)
+    }
+
+    /// Returns whether the process `name` is matched by this pattern. Path patterns never
+    /// match, as there is no path to compare them with.
+    pub fn matches(&self, name: &str) -> bool {
+        self.matches_process(name, None)
+    }
+
+    /// Returns whether the process `name`, whose executable is at `path` if known, is
+    /// matched by this pattern.
+    pub fn matches_process(&self, name: &str, path: Option<&str>) -> bool {
+        if self
+            .ignored
+            .iter()
+            .any(|ignored| ignored.matches_process(name, path))
+        {
+            return false;
+        }
+        if self.is_path() {
+            path.is_some_and(|path| self.matches_name(&normalize_path(path)))
+        } else {
+            self.matches_name(name)
+        }
+    }
+
+    fn matches_name(&self, name: &str) -> bool {
+        match &self.rule {
+            Rule::Exact(pattern) => base_name(name) == *pattern,
+            Rule::Contains(pattern) => name.to_lowercase().contains(pattern),
+            Rule::Regex(regex) => regex.is_match(name),
+        }
+    }
+}
+
+/// The applications an `unmapped` target controls: those not matched by `mapped` (the apps
+/// of other sliders), `exclude` or `ignored` (`general.ignore`), and, if `include` is given,
+/// matched by one of its entries. Exclusion wins, so an app that is both included and
+/// excluded is left alone.
+#[derive(Debug, Clone, Copy)]
+pub struct UnmappedApps<'a> {
+    pub mapped: &'a [AppMatcher],
+    pub exclude: &'a [AppMatcher],
+    pub include: Option<&'a [AppMatcher]>,
+    pub ignored: &'a [AppMatcher],
+}
+
+/// Identifies the apps an [`UnmappedApps`] set controls, by the [`AppKey`]s of its lists.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct UnmappedKey {
+    mapped: Vec<AppKey>,
+    exclude: Vec<AppKey>,
+    include: Option<Vec<AppKey>>,
+    ignored: Vec<AppKey>,
+}
+
+impl UnmappedApps<'_> {
+    /// Identifies the apps this set controls, see [`UnmappedKey`].
+    pub fn key(&self) -> UnmappedKey {
+        let keys = |apps: &[AppMatcher]| apps.iter().map(AppMatcher::key).collect();
+        UnmappedKey {
+            mapped: keys(self.mapped),
+            exclude: keys(self.exclude),
+            include: self.include.map(keys),
+            ignored: keys(self.ignored),
+        }
+    }
+
+    /// Returns whether matching needs the executable path, see [`AppMatcher::uses_path`].
+    // Only the Windows backend looks paths up
+    #[cfg_attr(not(windows), allow(dead_code))]
+    pub fn uses_path(&self) -> bool {
+        let uses_path = |apps: &[AppMatcher]| apps.iter().any(AppMatcher::uses_path);
+        uses_path(self.mapped)
+            || uses_path(self.exclude)
+            || uses_path(self.ignored)
+            || self.include.is_some_and(uses_path)
+    }
+
+    /// Returns whether the process `name` is controlled by the `unmapped` target.
+    pub fn matches(&self, name: &str) -> bool {
+        self.matches_process(name, None)
+    }
+
+    /// Returns whether the process `name`, whose executable is at `path` if known, is
+    /// controlled by the `unmapped` target.
+    pub fn matches_process(&self, name: &str, path: Option<&str>) -> bool {
+        let matched_by =
+            |apps: &[AppMatcher]| apps.iter().any(|app| app.matches_process(name, path));
+        !matched_by(self.mapped)
+            && !matched_by(self.exclude)
+            && !matched_by(self.ignored)
+            && self.include.is_none_or(matched_by)
+    }
+}
+
+/// Lowercases a process name and strips its `.exe` extension, so `Chrome` and
+/// `chrome.exe` compare equal.
+fn base_name(name: &str) -> String {
+    let name = name.to_lowercase();
+    match name.strip_suffix(".exe") {
+        Some(stripped) => stripped.to_string(),
+        None => name,
+    }
+}
+
+/// Uses `/` for every path separator, so Windows paths can be written either way.
+fn normalize_path(path: &str) -> String {
+    path.replace('\\', "/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn path_patterns_match_the_executable_path() {
+        let exact = AppMatcher::new(r"C:\Games\Steam\game.exe", MatchMode::Exact).unwrap();
+        assert!(exact.is_path());
+        assert!(exact.matches_process("game.exe", Some(r"c:\games\steam\GAME.exe")));
+        assert!(!exact.matches_process("game.exe", Some(r"C:\Other\game.exe")));
+        assert!(!exact.matches("game.exe"));
+
+        let contains = AppMatcher::new("Steam/", MatchMode::Contains).unwrap();
+        assert!(contains.matches_process("game", Some(r"C:\Games\Steam\game.exe")));
+
+        let regex = AppMatcher::new(r"^c:/games/.*\.exe$", MatchMode::Regex).unwrap();
+        assert!(regex.matches_process("game", Some(r"C:\Games\game.exe")));
+        assert!(
+            !AppMatcher::new(r"game\.exe", MatchMode::Regex)
+                .unwrap()
+                .is_path()
+        );
+    }
+
+    #[test]
+    fn paths_are_only_needed_for_path_patterns() {
+        let steam = [AppMatcher::new("Steam/", MatchMode::Contains).unwrap()];
+        let game = AppMatcher::new("game.exe", MatchMode::Exact).unwrap();
+        assert!(!game.uses_path());
+        assert!(game.clone().ignoring(&steam).uses_path());
+
+        let mut unmapped = UnmappedApps {
+            mapped: std::slice::from_ref(&game),
+            exclude: &[],
+            include: None,
+            ignored: &[],
+        };
+        assert!(!unmapped.uses_path());
+        unmapped.include = Some(&steam);
+        assert!(unmapped.uses_path());
+    }
+}
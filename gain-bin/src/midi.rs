@@ -0,0 +1,114 @@
+use crate::config::MidiCc;
+
+#[cfg(feature = "midi")]
+pub use output::MidiOut;
+
+/// Stand-in used when gain is built without the `midi` feature, which warns once that MIDI
+/// targets are ignored.
+#[cfg(not(feature = "midi"))]
+#[derive(Default)]
+pub struct MidiOut {
+    warned: bool,
+}
+
+#[cfg(not(feature = "midi"))]
+impl MidiOut {
+    pub fn send(&mut self, _cc: MidiCc, _volume: f64, _port: Option<&str>) {
+        if !self.warned {
+            log::warn!("gain was built without the `midi` feature; ignoring MIDI targets");
+            self.warned = true;
+        }
+    }
+}
+
+#[cfg(feature = "midi")]
+mod output {
+    use super::*;
+    use anyhow::{Result, anyhow};
+    use log::{info, warn};
+    use midir::{MidiOutput, MidiOutputConnection};
+    use std::{
+        collections::HashMap,
+        time::{Duration, Instant},
+    };
+
+    /// Time between attempts to open the MIDI port after a failure.
+    const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+    /// MIDI output for slider control-change messages. The port is opened on first use, so
+    /// MIDI targets added by a config reload work without a restart.
+    #[derive(Default)]
+    pub struct MidiOut {
+        connection: Option<MidiOutputConnection>,
+        last_attempt: Option<Instant>,
+        /// Last value sent for each (channel, controller), to skip repeats.
+        last_sent: HashMap<(u8, u8), u8>,
+    }
+
+    impl MidiOut {
+        /// Sends `volume` as a control change on `cc`, through the first output port whose
+        /// name contains `port` (or the first port at all).
+        pub fn send(&mut self, cc: MidiCc, volume: f64, port: Option<&str>) {
+            let value = cc_value(volume);
+            if self.last_sent.get(&(cc.channel, cc.cc)) == Some(&value) {
+                return;
+            }
+
+            let Some(connection) = self.connection(port) else {
+                return;
+            };
+            // Control change on the 0-based channel
+            let message = [0xB0 | (cc.channel - 1), cc.cc, value];
+            match connection.send(&message) {
+                Ok(()) => {
+                    self.last_sent.insert((cc.channel, cc.cc), value);
+                }
+                Err(e) => {
+                    warn!("Failed to send MIDI message, reopening the port: {}", e);
+                    self.connection = None;
+                }
+            }
+        }
+
+        fn connection(&mut self, port: Option<&str>) -> Option<&mut MidiOutputConnection> {
+            let retry_due = self
+                .last_attempt
+                .is_none_or(|attempt| attempt.elapsed() >= RETRY_INTERVAL);
+            if self.connection.is_none() && retry_due {
+                self.last_attempt = Some(Instant::now());
+                match connect(port) {
+                    Ok(connection) => {
+                        self.connection = Some(connection);
+                        self.last_sent.clear();
+                    }
+                    Err(e) => warn!("Failed to open MIDI output: {}", e),
+                }
+            }
+            self.connection.as_mut()
+        }
+    }
+
+    /// Converts a volume (0.0 to 1.0) into a 7-bit control-change value.
+    fn cc_value(volume: f64) -> u8 {
+        (volume.clamp(0.0, 1.0) * 127.0).round() as u8
+    }
+
+    fn connect(port: Option<&str>) -> Result<MidiOutputConnection> {
+        let output = MidiOutput::new("gain")?;
+        let (name, port) = output
+            .ports()
+            .into_iter()
+            .filter_map(|p| Some((output.port_name(&p).ok()?, p)))
+            .find(|(name, _)| port.is_none_or(|wanted| name.contains(wanted)))
+            .ok_or_else(|| match port {
+                Some(wanted) => anyhow!("No MIDI output port matches `{}`", wanted),
+                None => anyhow!("No MIDI output port found"),
+            })?;
+
+        let connection = output
+            .connect(&port, "gain")
+            .map_err(|e| anyhow!("Failed to connect to {}: {}", name, e))?;
+        info!("Sending MIDI to {}", name);
+        Ok(connection)
+    }
+}
@@ -0,0 +1,65 @@
+use crate::{config::LoadedConfig, open_port, read_message};
+use anyhow::Result;
+use gain_lib::Message;
+use std::{
+    io::BufReader,
+    time::{Duration, Instant},
+};
+
+/// Width of the bar that plots readings of 0 to 1023.
+const GRAPH_WIDTH: usize = 64;
+/// Time without raw readings after which the user is told how to enable them.
+const RAW_HINT_DELAY: Duration = Duration::from_secs(3);
+
+/// Runs `gain monitor`: prints every reading the device sends until interrupted, plotting
+/// raw readings against the filter's smoothed value for firmware built with `DEBUG_RAW`.
+pub fn run(config: &LoadedConfig, _config_path: &str) -> Result<()> {
+    let mut reader = BufReader::new(open_port(&config.connection)?);
+    let started = Instant::now();
+    let mut seen_raw = false;
+    let mut hinted = false;
+
+    println!("Watching the device, press Ctrl-C to stop.");
+
+    let mut buffer = Vec::new();
+    loop {
+        match read_message(&mut reader, &mut buffer)? {
+            Some(Message::Raw(reading)) => {
+                seen_raw = true;
+                println!(
+                    "{:>3}  raw {:>4}  smoothed {:>4}  {}",
+                    reading.id,
+                    reading.raw,
+                    reading.smoothed,
+                    graph(reading.raw, reading.smoothed)
+                );
+            }
+            Some(Message::Slider(slider)) => {
+                println!("{:>3}  reported {:>4}", slider.id, slider.value);
+            }
+            _ => {}
+        }
+
+        if !seen_raw && !hinted && started.elapsed() >= RAW_HINT_DELAY {
+            println!(
+                "No raw readings yet; set DEBUG_RAW to true in the firmware to compare them with the smoothed values"
+            );
+            hinted = true;
+        }
+    }
+}
+
+/// Draws the smoothed value as a bar, with the raw reading marked by `o`.
+fn graph(raw: u16, smoothed: u16) -> String {
+    let column = |value: u16| value.min(1023) as usize * (GRAPH_WIDTH - 1) / 1023;
+    let (raw, smoothed) = (column(raw), column(smoothed));
+    (0..GRAPH_WIDTH)
+        .map(|i| match i {
+            _ if i == raw => 'o',
+            _ if i <= smoothed => '=',
+            _ => ' ',
+        })
+        .collect::<String>()
+        .trim_end()
+        .to_string()
+}
@@ -0,0 +1,102 @@
+use crate::{config::Mqtt, status::SliderStatus};
+use anyhow::Result;
+
+#[cfg(feature = "mqtt")]
+pub use client::MqttPublisher;
+
+/// Stand-in used when gain is built without the `mqtt` feature. It can't be constructed,
+/// so configuring `mqtt` fails at startup instead of being silently ignored.
+#[cfg(not(feature = "mqtt"))]
+pub enum MqttPublisher {}
+
+#[cfg(not(feature = "mqtt"))]
+impl MqttPublisher {
+    pub fn connect(_config: &Mqtt) -> Result<Self> {
+        Err(anyhow::anyhow!(
+            "`mqtt` is set, but gain was built without the `mqtt` feature"
+        ))
+    }
+
+    pub fn publish(&mut self, _status: &SliderStatus) {
+        match *self {}
+    }
+}
+
+#[cfg(feature = "mqtt")]
+mod client {
+    use super::*;
+    use crate::throttle::Throttle;
+    use log::{debug, info};
+    use rumqttc::{Client, Event, MqttOptions, Packet, QoS};
+    use std::{thread, time::Duration};
+
+    /// Time between attempts to reach the broker after the connection fails.
+    const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+    /// Updates queued for the broker before new ones are dropped.
+    const QUEUE_LEN: usize = 32;
+
+    /// Publishes slider updates to an MQTT broker, as the same JSON objects the status
+    /// server sends.
+    ///
+    /// The connection is driven by a background thread that reconnects on its own, and
+    /// publishing only queues the message, so a slow or unreachable broker never stalls
+    /// the audio loop. Updates that don't fit in the queue are dropped.
+    pub struct MqttPublisher {
+        client: Client,
+        topic: String,
+    }
+
+    impl MqttPublisher {
+        /// Starts connecting to the broker in `config`.
+        pub fn connect(config: &Mqtt) -> Result<Self> {
+            let mut options = MqttOptions::new("gain", &config.host, config.port);
+            options.set_keep_alive(Duration::from_secs(30));
+            if let Some(username) = &config.username {
+                options.set_credentials(username, config.password.as_deref().unwrap_or(""));
+            }
+
+            let (client, mut connection) = Client::new(options, QUEUE_LEN);
+            let broker = format!("{}:{}", config.host, config.port);
+            thread::Builder::new().name("mqtt".into()).spawn(move || {
+                let mut errors = Throttle::default();
+                for event in connection.iter() {
+                    match event {
+                        Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                            info!("Connected to MQTT broker {}", broker);
+                            errors.reset();
+                        }
+                        Ok(_) => {}
+                        Err(e) => {
+                            errors
+                                .warn(format_args!("MQTT connection to {} failed: {}", broker, e));
+                            thread::sleep(RETRY_INTERVAL);
+                        }
+                    }
+                }
+            })?;
+
+            Ok(MqttPublisher {
+                client,
+                topic: config.topic.clone(),
+            })
+        }
+
+        /// Queues `status` for publishing to the topic of its slider.
+        pub fn publish(&mut self, status: &SliderStatus) {
+            let payload = match serde_json::to_vec(status) {
+                Ok(payload) => payload,
+                Err(e) => {
+                    debug!("Failed to serialize slider status: {}", e);
+                    return;
+                }
+            };
+            let topic = self.topic.replace("{id}", &status.slider.id.to_string());
+            if let Err(e) = self
+                .client
+                .try_publish(topic, QoS::AtMostOnce, false, payload)
+            {
+                debug!("Dropped MQTT update: {}", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use crate::config::OscControl;
+
+#[cfg(feature = "osc")]
+pub use output::OscOut;
+
+/// Stand-in used when gain is built without the `osc` feature, which warns once that OSC
+/// targets are ignored.
+#[cfg(not(feature = "osc"))]
+#[derive(Default)]
+pub struct OscOut {
+    warned: bool,
+}
+
+#[cfg(not(feature = "osc"))]
+impl OscOut {
+    pub fn send(&mut self, _osc: &OscControl, _volume: f64) {
+        if !self.warned {
+            log::warn!("gain was built without the `osc` feature; ignoring OSC targets");
+            self.warned = true;
+        }
+    }
+}
+
+#[cfg(feature = "osc")]
+mod output {
+    use super::*;
+    use anyhow::{Result, anyhow};
+    use log::warn;
+    use rosc::{OscMessage, OscPacket, OscType};
+    use std::{
+        collections::{HashMap, HashSet},
+        net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    };
+
+    /// OSC output for slider levels, sent as UDP datagrams. The socket is bound on first use,
+    /// and endpoints are resolved once, so OSC targets added by a config reload work without
+    /// a restart.
+    #[derive(Default)]
+    pub struct OscOut {
+        socket: Option<UdpSocket>,
+        /// Resolved endpoint of each configured address.
+        endpoints: HashMap<String, SocketAddr>,
+        /// Addresses that failed to resolve, so the failure is only logged once.
+        unresolved: HashSet<String>,
+        /// Last value sent to each (address, path), to skip repeats.
+        last_sent: HashMap<(String, String), f32>,
+    }
+
+    impl OscOut {
+        /// Sends `volume` as a float argument to `osc.path` at `osc.address`.
+        pub fn send(&mut self, osc: &OscControl, volume: f64) {
+            let value = volume.clamp(0.0, 1.0) as f32;
+            let key = (osc.address.clone(), osc.path.clone());
+            if self.last_sent.get(&key) == Some(&value) {
+                return;
+            }
+
+            match self.send_to(osc, value) {
+                Ok(true) => {
+                    self.last_sent.insert(key, value);
+                }
+                Ok(false) => {}
+                Err(e) => warn!("Failed to send OSC message to {}: {}", osc.address, e),
+            }
+        }
+
+        /// Sends the message, returning whether the endpoint could be resolved.
+        fn send_to(&mut self, osc: &OscControl, value: f32) -> Result<bool> {
+            let Some(endpoint) = self.endpoint(&osc.address) else {
+                return Ok(false);
+            };
+            let packet = OscPacket::Message(OscMessage {
+                addr: osc.path.clone(),
+                args: vec![OscType::Float(value)],
+            });
+            let bytes = rosc::encoder::encode(&packet)?;
+
+            if self.socket.is_none() {
+                self.socket = Some(UdpSocket::bind(("0.0.0.0", 0))?);
+            }
+            if let Some(socket) = &self.socket {
+                socket.send_to(&bytes, endpoint)?;
+            }
+            Ok(true)
+        }
+
+        fn endpoint(&mut self, address: &str) -> Option<SocketAddr> {
+            if let Some(endpoint) = self.endpoints.get(address) {
+                return Some(*endpoint);
+            }
+            if self.unresolved.contains(address) {
+                return None;
+            }
+
+            match resolve(address) {
+                Ok(endpoint) => {
+                    self.endpoints.insert(address.to_string(), endpoint);
+                    Some(endpoint)
+                }
+                Err(e) => {
+                    warn!("Failed to resolve OSC address {}: {}", address, e);
+                    self.unresolved.insert(address.to_string());
+                    None
+                }
+            }
+        }
+    }
+
+    fn resolve(address: &str) -> Result<SocketAddr> {
+        address
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| anyhow!("No address found for {}", address))
+    }
+}
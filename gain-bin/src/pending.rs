@@ -0,0 +1,117 @@
+use gain_lib::{HostMessage, MAX_FRAME_LEN, encode_frame};
+use log::debug;
+use std::{collections::HashMap, io::Write};
+
+/// Per-slider state mirrored on the devices, such as the mute LEDs or the level strips.
+/// Changes are queued by [`Pending::set`] and taken by [`Pending::take_pending`], and only
+/// when the state actually changes.
+pub struct Pending<T> {
+    states: HashMap<u8, T>,
+    pending: Vec<(u8, T)>,
+}
+
+impl<T> Default for Pending<T> {
+    fn default() -> Self {
+        Self {
+            states: HashMap::new(),
+            pending: Vec::new(),
+        }
+    }
+}
+
+impl<T: Copy + PartialEq> Pending<T> {
+    /// Sets slider `id`'s state.
+    pub fn set(&mut self, id: u8, state: T) {
+        if self.states.insert(id, state) != Some(state) {
+            self.pending.retain(|&(pending, _)| pending != id);
+            self.pending.push((id, state));
+        }
+    }
+
+    /// Takes the queued changes, to be sent to every device with [`send_all`].
+    pub fn take_pending(&mut self) -> Vec<(u8, T)> {
+        std::mem::take(&mut self.pending)
+    }
+
+    /// Queues every state again, for a device that just connected and shows none.
+    pub fn resend(&mut self) {
+        self.pending = self
+            .states
+            .iter()
+            .map(|(&id, &state)| (id, state))
+            .collect();
+    }
+}
+
+/// Sends `states` to a device whose IDs are offset by `id_offset`, renumbered the way the
+/// device numbers them and turned into frames by `message`. States below its offset belong
+/// to other devices and are skipped.
+pub fn send<T: Copy>(
+    port: &mut dyn Write,
+    states: &[(u8, T)],
+    id_offset: u8,
+    message: impl Fn(u8, T) -> HostMessage,
+) -> anyhow::Result<()> {
+    let mut buf = [0; MAX_FRAME_LEN];
+    for &(id, state) in states {
+        let Some(id) = id.checked_sub(id_offset) else {
+            continue;
+        };
+        port.write_all(encode_frame(&message(id, state), &mut buf)?)?;
+    }
+    Ok(())
+}
+
+/// Sends `states` to every connected device with [`send`], using the ID offsets of their
+/// connections. Failures are only logged, as `Failed to <action>`, since the device's
+/// reader reports a lost connection anyway.
+pub fn send_all<T: Copy>(
+    ports: &mut HashMap<usize, Box<dyn Write + Send>>,
+    offsets: &[u8],
+    states: &[(u8, T)],
+    action: &str,
+    message: impl Fn(u8, T) -> HostMessage,
+) {
+    if states.is_empty() {
+        return;
+    }
+    for (device, port) in ports {
+        if let Err(e) = send(port.as_mut(), states, offsets[*device], &message) {
+            debug!("Failed to {}: {}", action, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gain_lib::{LedState, decode_frame};
+
+    #[test]
+    fn only_changed_states_are_queued() {
+        let mut levels = Pending::default();
+        levels.set(0, 128u8);
+        levels.set(1, 255);
+        // Replaces the queued state rather than queueing another
+        levels.set(0, 0);
+        assert_eq!(levels.take_pending(), [(1, 255), (0, 0)]);
+
+        levels.set(1, 255);
+        assert!(levels.take_pending().is_empty());
+    }
+
+    #[test]
+    fn states_are_renumbered_for_the_device() {
+        let mut port = Vec::new();
+        let states = [(1, true), (3, false)];
+        send(&mut port, &states, 2, |id, on| {
+            HostMessage::Led(LedState { id, on })
+        })
+        .unwrap();
+
+        // Only slider 3 is on this device, as its slider 1
+        assert_eq!(port.pop(), Some(0));
+        let message: HostMessage = decode_frame(&mut port).unwrap();
+        assert_eq!(message, HostMessage::Led(LedState { id: 1, on: false }));
+    }
+}
@@ -0,0 +1,91 @@
+use crate::config::VolumeTarget;
+use std::collections::HashMap;
+
+/// Pickup (takeover) for `general.pickup_mode`: once a slider's target changes, such as on
+/// a reload or a profile switch, the slider leaves the new target alone until its volume
+/// crosses the target's current one, so the target doesn't jump to where the slider
+/// happens to be. The first target a slider moves after startup is taken over right away.
+#[derive(Default)]
+pub struct Pickups {
+    sliders: HashMap<u8, Pickup>,
+}
+
+struct Pickup {
+    /// The target the slider controls.
+    target: VolumeTarget,
+    /// The target's volume when the slider switched to it, and whether the slider was
+    /// below it, until the slider crosses it.
+    waiting: Option<(f64, bool)>,
+}
+
+impl Pickups {
+    /// Returns whether slider `id`, now at `volume` on `target`, controls it. `current` reads the target's volume when the target changed;
+    /// targets it can't read are taken over right away, and so are volumes within
+    /// `tolerance` of it.
+    pub fn catches(
+        &mut self,
+        id: u8,
+        target: &VolumeTarget,
+        volume: f64,
+        tolerance: f64,
+        current: impl FnOnce() -> Option<f64>,
+    ) -> bool {
+        let pickup = self.sliders.entry(id).or_insert_with(|| Pickup {
+            target: target.clone(),
+            waiting: None,
+        });
+        if pickup.target != *target {
+            pickup.target = target.clone();
+            pickup.waiting = current().map(|current| (current, volume < current));
+        }
+
+        let Some((current, below)) = pickup.waiting else {
+            return true;
+        };
+        let crossed = if below {
+            volume >= current - tolerance
+        } else {
+            volume <= current + tolerance
+        };
+        if crossed {
+            pickup.waiting = None;
+        }
+        crossed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matcher::AppMatcher;
+
+    fn app(name: &str) -> VolumeTarget {
+        VolumeTarget::Apps(vec![AppMatcher::from(name.to_string())])
+    }
+
+    #[test]
+    fn sliders_pick_up_a_new_target_once_they_cross_its_volume() {
+        let mut pickups = Pickups::default();
+        let mut catches =
+            |target: VolumeTarget, volume| pickups.catches(0, &target, volume, 0.01, || Some(0.5));
+        // The first target is taken over right away
+        assert!(catches(VolumeTarget::Master, 0.9));
+
+        // The new target stays at 0.5 until the slider comes down to it
+        assert!(!catches(app("spotify"), 0.9));
+        assert!(!catches(app("spotify"), 0.6));
+        assert!(catches(app("spotify"), 0.505));
+        assert!(catches(app("spotify"), 0.8));
+
+        // Crossing from below works the same
+        assert!(!catches(app("discord"), 0.1));
+        assert!(catches(app("discord"), 0.7));
+    }
+
+    #[test]
+    fn unreadable_targets_are_taken_over_right_away() {
+        let mut pickups = Pickups::default();
+        assert!(pickups.catches(0, &VolumeTarget::Master, 0.9, 0.01, || Some(0.5)));
+        assert!(pickups.catches(0, &app("midi"), 0.1, 0.01, || None));
+    }
+}
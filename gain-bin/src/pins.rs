@@ -0,0 +1,151 @@
+use crate::{config::LoadedConfig, open_port, read_message};
+use anyhow::{Result, anyhow};
+use gain_lib::{
+    Hello, HostMessage, MAX_FRAME_LEN, MAX_THRESHOLD, Message, PROTOCOL_VERSION, PinConfig,
+    PinSettings,
+};
+use std::{
+    io::{BufReader, Write},
+    time::{Duration, Instant},
+};
+
+/// Threshold given to pins that are turned off, for when they are turned back on.
+const DEFAULT_THRESHOLD: i16 = 4;
+/// How long to wait for each reply, which includes the time boards that reset when the port
+/// is opened take to boot.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Parses the arguments of `gain set-pin <id> <threshold|off>`.
+pub fn parse(args: &[String]) -> Result<PinConfig> {
+    let usage = || anyhow!("Usage: gain set-pin <slider id> <threshold|off>");
+    let [id, value, ..] = args else {
+        return Err(usage());
+    };
+    let id = id.parse().map_err(|_| usage())?;
+    let settings = match value.as_str() {
+        "off" => PinSettings {
+            enabled: false,
+            threshold: DEFAULT_THRESHOLD,
+        },
+        threshold => PinSettings {
+            enabled: true,
+            threshold: threshold.parse().map_err(|_| usage())?,
+        },
+    };
+    if !settings.is_valid() {
+        return Err(anyhow!(
+            "Threshold must be between 0 and {}, got {}",
+            MAX_THRESHOLD,
+            settings.threshold
+        ));
+    }
+    Ok(PinConfig { id, settings })
+}
+
+/// Runs `gain set-pin`: sends new settings for the pin of one slider, which the firmware
+/// stores in EEPROM, and reports what the device kept.
+pub fn run(config: &LoadedConfig, pin: PinConfig) -> Result<()> {
+    let mut port = open_port(&config.connection)?;
+    let mut buf = [0; MAX_FRAME_LEN];
+    let mut buffer = Vec::new();
+
+    // Waits for the firmware to announce itself first, so the settings aren't sent while a
+    // board that reset when the port was opened is still booting
+    let hello = HostMessage::Hello(Hello {
+        version: PROTOCOL_VERSION,
+    });
+    port.write_all(gain_lib::encode_frame(&hello, &mut buf)?)?;
+    let mut reader = BufReader::new(port.try_clone()?);
+    let Some(Message::Hello(firmware)) = wait_for(&mut reader, &mut buffer, |message| {
+        matches!(message, Message::Hello(_))
+    }) else {
+        return Err(anyhow!(
+            "The device didn't answer, is it running the gain firmware?"
+        ));
+    };
+    if firmware.version < PROTOCOL_VERSION {
+        return Err(anyhow!(
+            "The firmware speaks protocol version {}, older than this host's {} and without pin settings; reflash it from this version of gain",
+            firmware.version,
+            PROTOCOL_VERSION
+        ));
+    }
+
+    port.write_all(gain_lib::encode_frame(
+        &HostMessage::PinConfig(pin),
+        &mut buf,
+    )?)?;
+    let reply = wait_for(
+        &mut reader,
+        &mut buffer,
+        |message| matches!(message, Message::PinConfig(reply) if reply.id == pin.id),
+    );
+    let Some(Message::PinConfig(reply)) = reply else {
+        return Err(anyhow!(
+            "The device has no pin for slider {}, or its firmware predates set-pin",
+            pin.id
+        ));
+    };
+
+    if reply.settings != pin.settings {
+        return Err(anyhow!(
+            "The device rejected the settings, slider {} was left {}",
+            pin.id,
+            describe(reply.settings)
+        ));
+    }
+    println!("Slider {} is now {}", pin.id, describe(reply.settings));
+    Ok(())
+}
+
+/// Reads messages until one `wanted` returns true for arrives, or [`REPLY_TIMEOUT`] passes.
+fn wait_for(
+    reader: &mut BufReader<Box<dyn serialport::SerialPort>>,
+    buffer: &mut Vec<u8>,
+    wanted: impl Fn(&Message) -> bool,
+) -> Option<Message> {
+    let deadline = Instant::now() + REPLY_TIMEOUT;
+    while Instant::now() < deadline {
+        match read_message(reader, buffer) {
+            Ok(Some(message)) if wanted(&message) => return Some(message),
+            Ok(_) => {}
+            Err(_) => return None,
+        }
+    }
+    None
+}
+
+fn describe(settings: PinSettings) -> String {
+    if settings.enabled {
+        format!("read with a threshold of {}", settings.threshold)
+    } else {
+        "off".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn pin_settings_are_parsed_and_validated() {
+        let pin = parse(&args(&["2", "12"])).unwrap();
+        assert_eq!(pin.id, 2);
+        assert_eq!(
+            pin.settings,
+            PinSettings {
+                enabled: true,
+                threshold: 12
+            }
+        );
+        assert!(!parse(&args(&["2", "off"])).unwrap().settings.enabled);
+
+        assert!(parse(&args(&["2", "-1"])).is_err());
+        assert!(parse(&args(&["2", "256"])).is_err());
+        assert!(parse(&args(&["2"])).is_err());
+    }
+}
@@ -0,0 +1,92 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// Interval between volume updates while a ramp is in progress.
+pub const RAMP_TICK: Duration = Duration::from_millis(10);
+
+/// A volume transition in progress for a single slider.
+struct Ramp {
+    from: f64,
+    to: f64,
+    started: Instant,
+    duration: Duration,
+}
+
+impl Ramp {
+    fn value_at(&self, now: Instant) -> f64 {
+        let progress = now.duration_since(self.started).as_secs_f64() / self.duration.as_secs_f64();
+        self.from + (self.to - self.from) * progress.min(1.0)
+    }
+
+    fn is_done(&self, now: Instant) -> bool {
+        now.duration_since(self.started) >= self.duration
+    }
+}
+
+/// Volume ramps in progress, keyed by slider ID.
+#[derive(Default)]
+pub struct Ramps {
+    ramps: HashMap<u8, Ramp>,
+}
+
+impl Ramps {
+    /// Starts ramping slider `id`'s target toward `to` over `duration`. If the slider is
+    /// already ramping, the new ramp picks up from where the old one currently is;
+    /// otherwise `current` is asked for the target's current level. When that level is
+    /// unknown the ramp jumps straight to `to`.
+    pub fn start<F>(&mut self, id: u8, to: f64, duration: Duration, current: F)
+    where
+        F: FnOnce() -> Option<f64>,
+    {
+        let now = Instant::now();
+        let from = match self.ramps.get(&id) {
+            Some(ramp) => ramp.value_at(now),
+            None => current().unwrap_or(to),
+        };
+
+        self.ramps.insert(
+            id,
+            Ramp {
+                from,
+                to,
+                started: now,
+                duration,
+            },
+        );
+    }
+
+    /// Stops any ramp in progress for slider `id`, leaving its target where it is.
+    pub fn cancel(&mut self, id: u8) {
+        self.ramps.remove(&id);
+    }
+
+    /// Stops every ramp in progress, leaving each target where it is.
+    pub fn cancel_all(&mut self) {
+        self.ramps.clear();
+    }
+
+    /// Returns the volume slider `id`'s target is ramping toward, if it is ramping.
+    pub fn target(&self, id: u8) -> Option<f64> {
+        self.ramps.get(&id).map(|ramp| ramp.to)
+    }
+
+    /// Returns whether any ramp is still in progress.
+    pub fn is_active(&self) -> bool {
+        !self.ramps.is_empty()
+    }
+
+    /// Returns the volume each ramping slider's target should be set to right now, and
+    /// drops the ramps that have reached their end.
+    pub fn step(&mut self) -> Vec<(u8, f64)> {
+        let now = Instant::now();
+        let levels = self
+            .ramps
+            .iter()
+            .map(|(&id, ramp)| (id, ramp.value_at(now)))
+            .collect();
+        self.ramps.retain(|_, ramp| !ramp.is_done(now));
+        levels
+    }
+}
@@ -0,0 +1,440 @@
+use crate::{
+    config::{Connection, LoadedConfig},
+    devices::{
+        self, DTR_PULSE, DeviceEvent, DeviceEvents, DeviceLink, EVENT_QUEUE_LEN, HELLO, PROBE_PING,
+        PROBE_RETRY, PROBE_TIMEOUT, Queued, Reconnects,
+    },
+    error::GainError,
+    host, resolve_port_names,
+    shutdown::Shutdown,
+    stats::Stats,
+};
+use anyhow::{Result, anyhow};
+use gain_lib::{HostMessage, MAX_FRAME_LEN, Message};
+use log::{info, warn};
+use std::{
+    io,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    runtime::{Handle, Runtime},
+    sync::mpsc::{self, Receiver, Sender, UnboundedSender, error::TrySendError},
+    task::{self, JoinHandle},
+    time,
+};
+use tokio_serial::{SerialPortBuilderExt, SerialStream};
+use tokio_util::sync::CancellationToken;
+
+/// Time between checks for a due ping or an unanswered one.
+const PING_TICK: Duration = Duration::from_millis(500);
+
+/// Runs gain on the current tokio runtime until `shutdown` is cancelled or the tray menu
+/// quits, for embedding it into a larger async app. The devices of `config` are read by a
+/// task each, and their messages are applied on a blocking task, since the volume backends
+/// (COM on Windows) are synchronous. Otherwise this is the same host as the binary's: the
+/// file at `config_path` is reloaded when it changes, and `dry_run` only logs the volume
+/// changes.
+pub async fn run(
+    config: LoadedConfig,
+    config_path: &str,
+    dry_run: bool,
+    shutdown: CancellationToken,
+) -> Result<()> {
+    let stop = Shutdown::default();
+    let cancelled = {
+        let stop = stop.clone();
+        tokio::spawn(async move {
+            shutdown.cancelled().await;
+            stop.request();
+        })
+    };
+
+    let handle = Handle::current();
+    let config_path = config_path.to_string();
+    let result = task::spawn_blocking(move || {
+        host(config, &config_path, dry_run, &stop, &|config, stats| {
+            Box::new(TaskDevices::spawn(&handle, config.connections(), stats))
+        })
+    })
+    .await;
+    cancelled.abort();
+    result?
+}
+
+/// Runs [`run`] on a runtime of its own until Ctrl-C is pressed, for the binary.
+pub fn run_until_ctrl_c(config: LoadedConfig, config_path: &str, dry_run: bool) -> Result<()> {
+    let shutdown = CancellationToken::new();
+    let handler = shutdown.clone();
+    ctrlc::set_handler(move || handler.cancel())?;
+
+    Runtime::new()?.block_on(run(config, config_path, dry_run, shutdown))
+}
+
+/// Serial devices read by a task each, like the reader threads of [`devices::Devices`], for
+/// the blocking task that applies their messages. The tasks are stopped, and their ports
+/// closed, when this is dropped.
+struct TaskDevices {
+    handle: Handle,
+    events: Receiver<DeviceEvent>,
+    stop: CancellationToken,
+    readers: Vec<JoinHandle<()>>,
+}
+
+impl TaskDevices {
+    fn spawn<'a>(
+        handle: &Handle,
+        connections: impl IntoIterator<Item = &'a Connection>,
+        stats: &Arc<Stats>,
+    ) -> Self {
+        let (sender, events) = mpsc::channel(EVENT_QUEUE_LEN);
+        let stop = CancellationToken::new();
+        let readers = connections
+            .into_iter()
+            .enumerate()
+            .map(|(index, connection)| {
+                let reader = TaskReader {
+                    index,
+                    connection: connection.clone(),
+                    events: sender.clone(),
+                    stop: stop.clone(),
+                    stats: stats.clone(),
+                };
+                handle.spawn(reader.run())
+            })
+            .collect();
+
+        TaskDevices {
+            handle: handle.clone(),
+            events,
+            stop,
+            readers,
+        }
+    }
+}
+
+impl DeviceEvents for TaskDevices {
+    fn recv(&mut self, timeout: Duration) -> Option<DeviceEvent> {
+        // Called from the blocking task, which may wait on the runtime
+        self.handle
+            .block_on(time::timeout(timeout, self.events.recv()))
+            .ok()
+            .flatten()
+    }
+}
+
+impl Drop for TaskDevices {
+    fn drop(&mut self) {
+        self.stop.cancel();
+        // Readers waiting for room in the queue give up once it is closed
+        self.events.close();
+        for reader in self.readers.drain(..) {
+            let _ = self.handle.block_on(reader);
+        }
+    }
+}
+
+/// Writes frames to a device through its reader task, which owns the port.
+struct FrameWriter(UnboundedSender<Vec<u8>>);
+
+impl io::Write for FrameWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .send(buf.to_vec())
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Connects to one device, forwards its messages and reconnects it with an exponential
+/// backoff whenever the connection is lost, like the reader threads do.
+struct TaskReader {
+    index: usize,
+    connection: Connection,
+    events: Sender<DeviceEvent>,
+    stop: CancellationToken,
+    stats: Arc<Stats>,
+}
+
+impl TaskReader {
+    async fn run(self) {
+        let mut reconnects = Reconnects::default();
+
+        while !self.stop.is_cancelled() {
+            match self.open().await {
+                Ok((reader, name)) => {
+                    reconnects.connected();
+                    let result = self.read(reader, &name).await;
+                    if self.stop.is_cancelled()
+                        || self
+                            .events
+                            .send(DeviceEvent::Disconnected(self.index))
+                            .await
+                            .is_err()
+                    {
+                        return;
+                    }
+                    if reconnects.lost(result, &name, &self.stats) {
+                        continue;
+                    }
+                }
+                Err(e) => reconnects.failed(&e),
+            }
+
+            tokio::select! {
+                _ = self.stop.cancelled() => return,
+                _ = time::sleep(reconnects.next_delay()) => {}
+            }
+        }
+    }
+
+    /// Opens the device's port, returning it with its name, like [`crate::get_port`]: when
+    /// scanning finds several candidates, the first that answers a ping is used. Only
+    /// scanning runs on a blocking task; the candidates are opened and pinged for tokio, so
+    /// the port that answered is kept rather than opened again, which would reset boards
+    /// that reset on connect.
+    async fn open(&self) -> Result<(BufReader<SerialStream>, String)> {
+        let connection = self.connection.clone();
+        let names = task::spawn_blocking(move || resolve_port_names(&connection)).await??;
+        if let [name] = names.as_slice() {
+            return Ok((BufReader::new(self.open_named(name)?), name.clone()));
+        }
+
+        let mut first = None;
+        for name in &names {
+            let port = match self.open_named(name) {
+                Ok(port) => port,
+                Err(e) => {
+                    warn!("{}, trying the next port", e);
+                    continue;
+                }
+            };
+            let mut reader = BufReader::new(port);
+            if answers_ping(&mut reader).await {
+                return Ok((reader, name.clone()));
+            }
+            info!("{} didn't answer a ping, trying the next port", name);
+            first.get_or_insert((reader, name.clone()));
+        }
+
+        // Firmware that predates pings never answers, so it is used the way it was before
+        let (reader, name) = first.ok_or_else(|| anyhow!("No scanned port could be opened"))?;
+        warn!(
+            "No port answered a ping, using {} as the first that matches",
+            name
+        );
+        Ok((reader, name))
+    }
+
+    /// Opens the serial port called `name` with the configured baud rate.
+    fn open_named(&self, name: &str) -> Result<SerialStream, GainError> {
+        info!("Connecting to {}...", name);
+        tokio_serial::new(name, self.connection.baud_rate)
+            .open_native_async()
+            .map_err(|source| GainError::SerialOpen {
+                port: name.to_string(),
+                source,
+            })
+    }
+
+    /// Reads frames from the port `reader` reads, called `name` in logs, and writes the
+    /// frames queued for it, until the connection fails, the readers are stopped or the
+    /// watchdog finds the port silent. The port is closed on return.
+    async fn read(&self, mut reader: BufReader<SerialStream>, name: &str) -> Result<()> {
+        reader
+            .get_mut()
+            .write_all(&devices::encode(&HELLO)?)
+            .await?;
+        let (writer, mut writes) = mpsc::unbounded_channel();
+        let connected = DeviceEvent::Connected(self.index, Box::new(FrameWriter(writer)));
+        if self.events.send(connected).await.is_err() {
+            return Ok(());
+        }
+
+        let mut link = DeviceLink::new(&self.connection, name, &self.stats);
+        let mut buffer = Vec::new();
+        let mut ping_tick = time::interval(PING_TICK);
+
+        loop {
+            let deadline = link.next_deadline();
+            tokio::select! {
+                _ = self.stop.cancelled() => return Ok(()),
+                frame = read_frame(&mut reader, &mut buffer) => {
+                    if let Some(message) = link.frame(frame?)
+                        && self.events.send(DeviceEvent::Message(message)).await.is_err()
+                    {
+                        return Ok(());
+                    }
+                    let sent = link.send_held(|event| match self.events.try_send(event) {
+                        Ok(()) => Queued::Sent,
+                        Err(TrySendError::Full(_)) => Queued::Full,
+                        Err(TrySendError::Closed(_)) => Queued::Closed,
+                    });
+                    if !sent {
+                        return Ok(());
+                    }
+                }
+                permit = self.events.reserve(), if link.is_holding() => {
+                    let Ok(permit) = permit else {
+                        return Ok(());
+                    };
+                    let mut permit = Some(permit);
+                    link.send_held(|event| match permit.take() {
+                        Some(permit) => {
+                            permit.send(event);
+                            Queued::Sent
+                        }
+                        None => Queued::Full,
+                    });
+                }
+                Some(frame) = writes.recv() => reader.get_mut().write_all(&frame).await?,
+                _ = ping_tick.tick() => {
+                    if let Some(ping) = link.ping_due(Instant::now()) {
+                        reader.get_mut().write_all(&devices::encode(&ping)?).await?;
+                    }
+                    if link.check(Instant::now()) {
+                        reset_port(reader.get_mut()).await;
+                        return Ok(());
+                    }
+                }
+                _ = time::sleep_until(deadline.unwrap_or_else(Instant::now).into()),
+                    if deadline.is_some() =>
+                {
+                    if link.check(Instant::now()) {
+                        reset_port(reader.get_mut()).await;
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Returns whether the device `reader` reads answers a ping within [`PROBE_TIMEOUT`], like
+/// [`devices::answers_ping`] does with blocking reads. Frames read before the pong are
+/// dropped.
+async fn answers_ping(reader: &mut BufReader<SerialStream>) -> bool {
+    let mut buffer = Vec::new();
+    let probe = async {
+        loop {
+            let ping = devices::encode(&HostMessage::Ping(PROBE_PING))?;
+            reader.get_mut().write_all(&ping).await?;
+            let retry = time::sleep(PROBE_RETRY);
+            tokio::pin!(retry);
+            loop {
+                tokio::select! {
+                    _ = &mut retry => break,
+                    frame = read_frame(reader, &mut buffer) => {
+                        if let Ok(Message::Pong(pong)) = frame?
+                            && pong == PROBE_PING
+                        {
+                            return Ok::<_, anyhow::Error>(true);
+                        }
+                    }
+                }
+            }
+        }
+    };
+    matches!(time::timeout(PROBE_TIMEOUT, probe).await, Ok(Ok(true)))
+}
+
+/// Reads the next frame from `reader` and decodes it, like [`crate::read_frame`] does with
+/// blocking reads, except that it waits for a whole frame. Cancelling it keeps the bytes
+/// read so far in `buffer`, so it can race other events.
+async fn read_frame(
+    reader: &mut (impl AsyncBufRead + Unpin),
+    buffer: &mut Vec<u8>,
+) -> Result<Result<Message, GainError>> {
+    loop {
+        let limit = MAX_FRAME_LEN.saturating_sub(buffer.len()) as u64;
+        match (&mut *reader).take(limit).read_until(0x00, buffer).await? {
+            0 => return Err(anyhow!("Serial device disconnected")),
+            _ if buffer.last() == Some(&0x00) => {
+                buffer.pop();
+                if buffer.is_empty() {
+                    continue;
+                }
+                let message = gain_lib::decode_frame(buffer).map_err(GainError::from);
+                buffer.clear();
+                return Ok(message);
+            }
+            _ if buffer.len() >= MAX_FRAME_LEN => {
+                buffer.clear();
+                skip_frame(reader).await?;
+                return Ok(Err(GainError::Decode(
+                    postcard::Error::DeserializeBadEncoding,
+                )));
+            }
+            // The stream ended mid-frame, which the next read reports
+            _ => {}
+        }
+    }
+}
+
+/// Drops the bytes up to and including the next `0x00`.
+async fn skip_frame(reader: &mut (impl AsyncBufRead + Unpin)) -> Result<()> {
+    loop {
+        let available = reader.fill_buf().await?;
+        if available.is_empty() {
+            return Ok(());
+        }
+        match available.iter().position(|&byte| byte == 0x00) {
+            Some(end) => {
+                reader.consume(end + 1);
+                return Ok(());
+            }
+            None => {
+                let len = available.len();
+                reader.consume(len);
+            }
+        }
+    }
+}
+
+/// Resets the port like the reader threads do, without blocking the runtime for the pulse.
+async fn reset_port(port: &mut SerialStream) {
+    if devices::begin_reset(port) {
+        time::sleep(DTR_PULSE).await;
+        devices::end_reset(port);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gain_lib::Slider;
+
+    fn frame(message: &Message) -> Vec<u8> {
+        let mut buf = [0; MAX_FRAME_LEN];
+        gain_lib::encode_frame(message, &mut buf).unwrap().to_vec()
+    }
+
+    #[tokio::test]
+    async fn frames_are_read_across_garbage_and_empty_frames() {
+        let mut bytes = vec![0x55; 2 * MAX_FRAME_LEN];
+        bytes.extend([0x00, 0x00, 0x00]);
+        bytes.extend(frame(&Message::Slider(Slider { id: 1, value: 512 })));
+        bytes.extend(frame(&Message::Heartbeat));
+
+        let mut reader = bytes.as_slice();
+        let mut buffer = Vec::new();
+        assert!(matches!(
+            read_frame(&mut reader, &mut buffer).await,
+            Ok(Err(GainError::Decode(_)))
+        ));
+        assert!(matches!(
+            read_frame(&mut reader, &mut buffer).await,
+            Ok(Ok(Message::Slider(Slider { id: 1, value: 512 })))
+        ));
+        assert!(matches!(
+            read_frame(&mut reader, &mut buffer).await,
+            Ok(Ok(Message::Heartbeat))
+        ));
+        assert!(read_frame(&mut reader, &mut buffer).await.is_err());
+    }
+}
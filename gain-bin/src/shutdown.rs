@@ -0,0 +1,46 @@
+use std::sync::{Arc, Condvar, Mutex};
+
+/// Shutdown request raised by Ctrl-C or the tray menu, checked by the main loop between
+/// reads and while waiting to reconnect. Also stops the device readers.
+#[derive(Clone, Default)]
+pub struct Shutdown {
+    state: Arc<(Mutex<bool>, Condvar)>,
+}
+
+impl Shutdown {
+    /// Installs the Ctrl-C handler that requests a shutdown.
+    // With the tokio feature, Ctrl-C cancels the runtime's token instead
+    #[cfg(not(feature = "tokio"))]
+    pub fn install() -> anyhow::Result<Self> {
+        let shutdown = Shutdown::default();
+
+        let handler = shutdown.clone();
+        ctrlc::set_handler(move || handler.request())?;
+
+        Ok(shutdown)
+    }
+
+    /// Requests a shutdown, waking up anything sleeping on it.
+    pub fn request(&self) {
+        let (requested, condvar) = &*self.state;
+        *requested.lock().unwrap() = true;
+        condvar.notify_all();
+    }
+
+    /// Returns whether a shutdown was requested.
+    pub fn requested(&self) -> bool {
+        *self.state.0.lock().unwrap()
+    }
+
+    /// Sleeps for `duration`, waking up early if a shutdown is requested. Returns whether a
+    /// shutdown was requested.
+    #[cfg(not(feature = "tokio"))]
+    pub fn sleep(&self, duration: std::time::Duration) -> bool {
+        let (requested, condvar) = &*self.state;
+        let guard = condvar
+            .wait_timeout_while(requested.lock().unwrap(), duration, |requested| !*requested)
+            .unwrap()
+            .0;
+        *guard
+    }
+}
@@ -0,0 +1,437 @@
+use crate::config::{General, SliderMappings};
+use std::collections::HashMap;
+
+/// Largest raw value reported by the firmware's 10-bit ADC.
+pub const MAX_RAW_VALUE: u16 = 1023;
+
+/// Step quantization with hysteresis, so a slider resting on the boundary between two
+/// steps doesn't flicker between them as its reading dithers. Once a slider settles on a
+/// step, it only moves to another one after its position moved more than half a step from
+/// where that step was chosen.
+#[derive(Default)]
+pub struct Quantizer {
+    /// Last step chosen for each slider ID, and the position it was chosen at.
+    last: HashMap<u8, (f64, f64)>,
+}
+
+impl Quantizer {
+    /// Converts a raw reading (0 to 1023) of slider `id` into a volume level (0.0 to 1.0),
+    /// shaped by the slider's curve, snapped to the configured step and inverted if
+    /// requested.
+    pub fn slider_volume(
+        &mut self,
+        id: u8,
+        value: u16,
+        general: &General,
+        mapping: &SliderMappings,
+    ) -> f64 {
+        let step = general.volume_step;
+        let position = mapping
+            .curve(general)
+            .apply(value as f64 / MAX_RAW_VALUE as f64);
+
+        let volume = match self.last.get(&id) {
+            Some(&(last, anchor)) if (position - anchor).abs() <= step / 2.0 => last,
+            _ => {
+                // Snap to nearest step (e.g., if step is 0.05, snaps to 0.00, 0.05, 0.10)
+                let quantized = ((position / step).round() * step).clamp(0.0, 1.0);
+                self.last.insert(id, (quantized, position));
+                quantized
+            }
+        };
+
+        if mapping.inverted(general) {
+            1.0 - volume
+        } else {
+            volume
+        }
+    }
+}
+
+/// Rescales a volume from 0.0 to 1.0 into the slider's `min_scalar` to `max_scalar`
+/// range, so the whole travel of the slider stays usable under a volume cap.
+pub fn scale_volume(volume: f64, mapping: &SliderMappings) -> f64 {
+    let (min, max) = mapping.volume_range();
+    min + volume * (max - min)
+}
+
+/// Returns the raw reading at which slider `mapping` sets `volume`, undoing its volume
+/// range, direction, curve, calibration and offset in turn. Motorized faders are moved there to
+/// follow volumes changed elsewhere.
+pub fn slider_position(volume: f64, general: &General, mapping: &SliderMappings) -> u16 {
+    let (min, max) = mapping.volume_range();
+    let mut level = ((volume - min) / (max - min)).clamp(0.0, 1.0);
+    if mapping.inverted(general) {
+        level = 1.0 - level;
+    }
+    let position = mapping.curve(general).invert(level);
+    let (min_raw, max_raw) = mapping.raw_range();
+    let raw = (min_raw as f64 + position * (max_raw - min_raw) as f64).round() as i32;
+    (raw - mapping.offset_raw as i32).clamp(0, MAX_RAW_VALUE as i32) as u16
+}
+
+/// Moves `current` by `ticks` steps of `step` for a relative (encoder) slider. The result
+/// is snapped to the step grid, so a volume set elsewhere lands back on it, and clamped to
+/// 0.0 to 1.0.
+pub fn step_volume(current: f64, ticks: i16, step: f64) -> f64 {
+    (((current / step).round() + ticks as f64) * step).clamp(0.0, 1.0)
+}
+
+/// Shifts a raw slider reading by the slider's `offset_raw`, then rescales it from the
+/// slider's calibrated travel (`min_raw` to `max_raw`) to the full 0 to 1023 range, so a
+/// slider that never quite reaches its ends can still mute and reach full volume. Readings
+/// outside the calibrated travel are clamped.
+pub fn calibrate(value: u16, mapping: &SliderMappings) -> u16 {
+    let value = (value as i32 + mapping.offset_raw as i32).clamp(0, MAX_RAW_VALUE as i32) as u16;
+    let (min, max) = mapping.raw_range();
+    if (min, max) == (0, MAX_RAW_VALUE) {
+        return value.min(MAX_RAW_VALUE);
+    }
+    let position = (value.clamp(min, max) - min) as f64 / (max - min) as f64;
+    (position * MAX_RAW_VALUE as f64).round() as u16
+}
+
+/// Host-side jitter filter: remembers the last raw value applied for each slider ID and
+/// rejects readings that moved less than the configured deadzone since then.
+#[derive(Default)]
+pub struct Deadzone {
+    last_applied: HashMap<u8, u16>,
+}
+
+impl Deadzone {
+    /// Returns whether `value` from slider `id` moved far enough to be applied, and if so
+    /// records it as the slider's new reference point. The first reading of a slider and
+    /// readings at either end of its travel always pass, so a slider can still reach 0 and
+    /// full volume with a large deadzone.
+    pub fn accept(&mut self, id: u8, value: u16, deadzone: u16) -> bool {
+        let accepted = match self.last_applied.get(&id) {
+            None => true,
+            Some(&last) if value == 0 || value >= MAX_RAW_VALUE => last != value,
+            Some(&last) => last.abs_diff(value) >= deadzone,
+        };
+        if accepted {
+            self.last_applied.insert(id, value);
+        }
+        accepted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ButtonMode, Curve, SliderMode, VolumeTarget};
+
+    fn general(invert_direction: bool) -> General {
+        General {
+            invert_direction,
+            ..General::default()
+        }
+    }
+
+    fn mapping(curve: Curve) -> SliderMappings {
+        SliderMappings {
+            id: 0,
+            target: VolumeTarget::Master,
+            mode: SliderMode::Absolute,
+            button: ButtonMode::Momentary,
+            curve: Some(curve),
+            midi: None,
+            osc: None,
+            min_raw: None,
+            max_raw: None,
+            offset_raw: 0,
+            min_scalar: None,
+            max_scalar: None,
+            invert: None,
+            motorized: false,
+        }
+    }
+
+    fn calibrated(min_raw: u16, max_raw: u16) -> SliderMappings {
+        SliderMappings {
+            min_raw: Some(min_raw),
+            max_raw: Some(max_raw),
+            ..mapping(Curve::Linear)
+        }
+    }
+
+    /// Converts a single reading, without any hysteresis from earlier ones.
+    fn slider_volume(value: u16, general: &General, mapping: &SliderMappings) -> f64 {
+        Quantizer::default().slider_volume(0, value, general, mapping)
+    }
+
+    fn assert_volume(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn extremes_map_directly_without_invert() {
+        assert_volume(
+            slider_volume(0, &general(false), &mapping(Curve::Linear)),
+            0.0,
+        );
+        assert_volume(
+            slider_volume(1023, &general(false), &mapping(Curve::Linear)),
+            1.0,
+        );
+    }
+
+    #[test]
+    fn extremes_are_swapped_with_invert() {
+        assert_volume(
+            slider_volume(0, &general(true), &mapping(Curve::Linear)),
+            1.0,
+        );
+        assert_volume(
+            slider_volume(1023, &general(true), &mapping(Curve::Linear)),
+            0.0,
+        );
+    }
+
+    #[test]
+    fn per_slider_invert_overrides_the_global_one() {
+        let normal = SliderMappings {
+            invert: Some(false),
+            ..mapping(Curve::Linear)
+        };
+        assert_volume(slider_volume(0, &general(true), &normal), 0.0);
+        assert_volume(slider_volume(1023, &general(true), &normal), 1.0);
+
+        let inverted = SliderMappings {
+            invert: Some(true),
+            ..mapping(Curve::Linear)
+        };
+        assert_volume(slider_volume(0, &general(false), &inverted), 1.0);
+        assert_volume(slider_volume(1023, &general(false), &inverted), 0.0);
+    }
+
+    #[test]
+    fn per_slider_curve_overrides_the_global_one() {
+        let general = General {
+            curve: Curve::Log,
+            volume_step: 0.001,
+            ..General::default()
+        };
+        let fallback = SliderMappings {
+            curve: None,
+            ..mapping(Curve::Linear)
+        };
+        assert_volume(
+            slider_volume(512, &general, &fallback),
+            (Curve::Log.apply(512.0 / 1023.0) * 1000.0).round() / 1000.0,
+        );
+        assert_volume(slider_volume(512, &general, &mapping(Curve::Linear)), 0.5);
+        assert_volume(
+            slider_volume(512, &general, &mapping(Curve::Gamma(2.0))),
+            0.25,
+        );
+    }
+
+    #[test]
+    fn positions_round_trip_through_every_curve() {
+        for curve in [Curve::Linear, Curve::Log, Curve::Exp, Curve::Gamma(2.0)] {
+            let mapping = SliderMappings {
+                invert: Some(true),
+                min_scalar: Some(0.1),
+                ..mapping(curve)
+            };
+            for value in [0, 300, 700, 1023] {
+                let volume = scale_volume(
+                    slider_volume(value, &General::default(), &mapping),
+                    &mapping,
+                );
+                let position = slider_position(volume, &General::default(), &mapping);
+                // Only the snapping to volume_step is lost
+                assert!(
+                    position.abs_diff(value) <= 30,
+                    "{curve:?}: {value} came back as {position}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn linear_curve_is_identity() {
+        assert_volume(Curve::Linear.apply(0.0), 0.0);
+        assert_volume(Curve::Linear.apply(0.5), 0.5);
+        assert_volume(Curve::Linear.apply(1.0), 1.0);
+    }
+
+    #[test]
+    fn log_curve_is_gentle_at_the_bottom() {
+        assert_volume(Curve::Log.apply(0.0), 0.0);
+        assert_volume(Curve::Log.apply(0.5), 9.0 / 99.0);
+        assert_volume(Curve::Log.apply(1.0), 1.0);
+    }
+
+    #[test]
+    fn exp_curve_is_gentle_at_the_top() {
+        assert_volume(Curve::Exp.apply(0.0), 0.0);
+        assert_volume(Curve::Exp.apply(0.5), 50.5f64.log(100.0));
+        assert_volume(Curve::Exp.apply(1.0), 1.0);
+    }
+
+    #[test]
+    fn gamma_curve_raises_to_exponent() {
+        assert_volume(Curve::Gamma(2.0).apply(0.0), 0.0);
+        assert_volume(Curve::Gamma(2.0).apply(0.5), 0.25);
+        assert_volume(Curve::Gamma(2.0).apply(1.0), 1.0);
+    }
+
+    #[test]
+    fn curve_is_applied_before_quantization() {
+        // Half travel on the log curve is ~0.0909, which snaps to the 0.1 step
+        let general = General {
+            volume_step: 0.1,
+            ..General::default()
+        };
+        assert_volume(slider_volume(512, &general, &mapping(Curve::Log)), 0.1);
+    }
+
+    #[test]
+    fn volume_range_rescales_the_full_travel() {
+        let mapping = SliderMappings {
+            min_scalar: Some(0.1),
+            max_scalar: Some(0.6),
+            ..mapping(Curve::Linear)
+        };
+        let volume =
+            |value| scale_volume(slider_volume(value, &general(false), &mapping), &mapping);
+        assert_volume(volume(1023), 0.6);
+        assert_volume(volume(0), 0.1);
+        assert_volume(volume(512), 0.35);
+    }
+
+    #[test]
+    fn default_volume_range_is_unchanged() {
+        let mapping = mapping(Curve::Linear);
+        assert_volume(scale_volume(0.0, &mapping), 0.0);
+        assert_volume(scale_volume(0.4, &mapping), 0.4);
+        assert_volume(scale_volume(1.0, &mapping), 1.0);
+    }
+
+    #[test]
+    fn dithering_on_a_step_boundary_is_stable() {
+        // The 0.49/0.50 boundary (raw 506.385) lies between these readings
+        let general = General {
+            volume_step: 0.01,
+            ..General::default()
+        };
+        let mapping = mapping(Curve::Linear);
+        let mut quantizer = Quantizer::default();
+
+        let first = quantizer.slider_volume(0, 506, &general, &mapping);
+        for value in [507, 506, 507, 507, 506, 507, 506] {
+            assert_volume(quantizer.slider_volume(0, value, &general, &mapping), first);
+        }
+        // The stateless conversion flips between the two steps on the same readings
+        assert_ne!(
+            slider_volume(506, &general, &mapping),
+            slider_volume(507, &general, &mapping)
+        );
+    }
+
+    #[test]
+    fn quantizer_follows_real_movements() {
+        let general = General {
+            volume_step: 0.01,
+            ..General::default()
+        };
+        let mapping = mapping(Curve::Linear);
+        let mut quantizer = Quantizer::default();
+
+        assert_volume(quantizer.slider_volume(0, 506, &general, &mapping), 0.49);
+        assert_volume(quantizer.slider_volume(0, 512, &general, &mapping), 0.5);
+        assert_volume(quantizer.slider_volume(0, 1023, &general, &mapping), 1.0);
+        assert_volume(quantizer.slider_volume(0, 0, &general, &mapping), 0.0);
+    }
+
+    #[test]
+    fn encoder_ticks_move_by_whole_steps() {
+        assert_volume(step_volume(0.5, 2, 0.05), 0.6);
+        assert_volume(step_volume(0.5, -3, 0.05), 0.35);
+        // Volumes set elsewhere snap back onto the step grid
+        assert_volume(step_volume(0.52, 1, 0.05), 0.55);
+    }
+
+    #[test]
+    fn encoder_ticks_stop_at_the_ends() {
+        assert_volume(step_volume(0.95, 5, 0.05), 1.0);
+        assert_volume(step_volume(0.1, i16::MIN, 0.05), 0.0);
+    }
+
+    #[test]
+    fn calibration_stretches_travel_to_the_full_range() {
+        let mapping = calibrated(30, 1000);
+        assert_eq!(calibrate(30, &mapping), 0);
+        assert_eq!(calibrate(1000, &mapping), 1023);
+        assert_eq!(calibrate(515, &mapping), 512);
+    }
+
+    #[test]
+    fn calibration_clamps_readings_outside_the_travel() {
+        let mapping = calibrated(30, 1000);
+        assert_eq!(calibrate(12, &mapping), 0);
+        assert_eq!(calibrate(1010, &mapping), 1023);
+    }
+
+    #[test]
+    fn uncalibrated_readings_are_unchanged() {
+        assert_eq!(calibrate(0, &mapping(Curve::Linear)), 0);
+        assert_eq!(calibrate(600, &mapping(Curve::Linear)), 600);
+        assert_eq!(calibrate(1023, &mapping(Curve::Linear)), 1023);
+    }
+
+    #[test]
+    fn offsets_shift_readings_up_to_the_ends() {
+        let raised = SliderMappings {
+            offset_raw: 20,
+            ..mapping(Curve::Linear)
+        };
+        assert_eq!(calibrate(500, &raised), 520);
+        assert_eq!(calibrate(1010, &raised), 1023);
+
+        let lowered = SliderMappings {
+            offset_raw: -20,
+            ..mapping(Curve::Linear)
+        };
+        assert_eq!(calibrate(500, &lowered), 480);
+        assert_eq!(calibrate(5, &lowered), 0);
+        assert_eq!(
+            slider_position(0.5, &general(false), &lowered),
+            calibrate(512, &mapping(Curve::Linear)) + 20
+        );
+    }
+
+    #[test]
+    fn deadzone_ignores_small_deltas() {
+        let mut deadzone = Deadzone::default();
+        assert!(deadzone.accept(0, 500, 8));
+        assert!(!deadzone.accept(0, 507, 8));
+        assert!(!deadzone.accept(0, 493, 8));
+        assert!(deadzone.accept(0, 508, 8));
+        // Deltas are measured from the last applied value, not the last reading
+        assert!(!deadzone.accept(0, 503, 8));
+    }
+
+    #[test]
+    fn deadzone_tracks_sliders_separately() {
+        let mut deadzone = Deadzone::default();
+        assert!(deadzone.accept(0, 500, 8));
+        assert!(deadzone.accept(1, 502, 8));
+        assert!(!deadzone.accept(1, 505, 8));
+    }
+
+    #[test]
+    fn deadzone_lets_sliders_reach_the_extremes() {
+        let mut deadzone = Deadzone::default();
+        assert!(deadzone.accept(0, 3, 8));
+        assert!(deadzone.accept(0, 0, 8));
+        assert!(!deadzone.accept(0, 0, 8));
+        assert!(deadzone.accept(0, 1020, 8));
+        assert!(deadzone.accept(0, 1023, 8));
+    }
+}
@@ -0,0 +1,89 @@
+use crate::{config::MatchMode, matcher::AppMatcher, volume::VolumeBackend};
+use anyhow::Result;
+use log::{debug, warn};
+use std::collections::HashMap;
+
+/// How far from its ducked level an app may be read back and still count as untouched,
+/// since backends round the volumes they store.
+const DUCK_TOLERANCE: f64 = 0.005;
+
+/// Apps turned down by `solo` sliders, keyed by slider ID, with the levels to put them back
+/// to once the slider is lowered.
+#[derive(Default)]
+pub struct Solos {
+    ducked: HashMap<u8, Ducked>,
+}
+
+struct Ducked {
+    duck_to: f64,
+    levels: Vec<(AppMatcher, f64)>,
+}
+
+impl Solos {
+    /// Engages or releases solo slider `id` for its new `volume`. Above 0, every app other
+    /// than `app` that is louder than `duck_to` is turned down to it, its level being kept
+    /// to restore. Apps that started since the slider last moved are ducked on its next
+    /// move. At 0, the ducked apps are restored.
+    pub fn apply(
+        &mut self,
+        id: u8,
+        app: &AppMatcher,
+        duck_to: f64,
+        volume: f64,
+        backend: &mut dyn VolumeBackend,
+    ) -> Result<()> {
+        if volume <= 0.0 {
+            self.restore(id, backend);
+            return Ok(());
+        }
+
+        let ducked = self.ducked.entry(id).or_insert_with(|| Ducked {
+            duck_to,
+            levels: Vec::new(),
+        });
+        for session in backend.sessions()? {
+            let Some(name) = session.name else {
+                continue;
+            };
+            if app.matches(&name) || ducked.levels.iter().any(|(other, _)| other.matches(&name)) {
+                continue;
+            }
+            let other = AppMatcher::new(&name, MatchMode::Exact)?.ignoring(app.ignored());
+            if let Some(level) = backend.get_app(&other)?
+                && level > duck_to
+            {
+                debug!("Solo slider {} ducks {} from {}", id, other, level);
+                backend.set_app(&other, duck_to)?;
+                ducked.levels.push((other, level));
+            }
+        }
+        Ok(())
+    }
+
+    /// Restores the apps solo slider `id` ducked. Apps whose volume changed since, such as
+    /// by another slider, keep their new level.
+    pub fn restore(&mut self, id: u8, backend: &mut dyn VolumeBackend) {
+        let Some(ducked) = self.ducked.remove(&id) else {
+            return;
+        };
+        for (app, level) in ducked.levels {
+            match backend.get_app(&app) {
+                Ok(Some(current)) if (current - ducked.duck_to).abs() <= DUCK_TOLERANCE => {
+                    if let Err(e) = backend.set_app(&app, level) {
+                        warn!("Failed to restore volume for app {}: {}", app, e);
+                    }
+                }
+                Ok(_) => debug!("{} changed while ducked, leaving it", app),
+                Err(e) => warn!("Failed to read volume for app {}: {}", app, e),
+            }
+        }
+    }
+
+    /// Restores the apps every solo slider ducked.
+    pub fn restore_all(&mut self, backend: &mut dyn VolumeBackend) {
+        let ids: Vec<u8> = self.ducked.keys().copied().collect();
+        for id in ids {
+            self.restore(id, backend);
+        }
+    }
+}
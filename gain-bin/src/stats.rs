@@ -0,0 +1,147 @@
+use log::debug;
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+/// Time between two summaries of the counters in the log.
+pub const STATS_LOG_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Lifetime counters of what happened to the data read from the devices, to tell a bad
+/// cable (decode errors, reconnects) from a noisy slider (skipped positions). Updated by
+/// the device readers and the main loop alike, and never reset.
+#[derive(Default)]
+pub struct Stats {
+    frames: AtomicU64,
+    decode_errors: AtomicU64,
+    applied: AtomicU64,
+    skipped: AtomicU64,
+    reconnects: AtomicU64,
+}
+
+/// The counters at one point in time, as logged and served by the HTTP API.
+#[derive(serde::Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Counts {
+    /// Frames read from every device, whether they decoded or not.
+    pub frames: u64,
+    /// Frames that failed to decode, such as ones with a bad checksum.
+    pub decode_errors: u64,
+    /// Slider positions applied to their target.
+    pub applied: u64,
+    /// Slider positions held back by coalescing, ignored as jitter by the deadzone or left
+    /// out because the slider hasn't picked up its target.
+    pub skipped: u64,
+    /// Times a device had to be reconnected after its connection was lost.
+    pub reconnects: u64,
+}
+
+impl Stats {
+    pub fn frame(&self, decoded: bool) {
+        self.frames.fetch_add(1, Ordering::Relaxed);
+        if !decoded {
+            self.decode_errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn applied(&self) {
+        self.applied.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn skipped(&self) {
+        self.skipped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn reconnect(&self) {
+        self.reconnects.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn counts(&self) -> Counts {
+        Counts {
+            frames: self.frames.load(Ordering::Relaxed),
+            decode_errors: self.decode_errors.load(Ordering::Relaxed),
+            applied: self.applied.load(Ordering::Relaxed),
+            skipped: self.skipped.load(Ordering::Relaxed),
+            reconnects: self.reconnects.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Counts {
+    fn since(self, earlier: Counts) -> Counts {
+        Counts {
+            frames: self.frames - earlier.frames,
+            decode_errors: self.decode_errors - earlier.decode_errors,
+            applied: self.applied - earlier.applied,
+            skipped: self.skipped - earlier.skipped,
+            reconnects: self.reconnects - earlier.reconnects,
+        }
+    }
+}
+
+/// Logs a summary of [`Stats`] at debug level every [`STATS_LOG_INTERVAL`], with what
+/// changed since the previous summary next to the lifetime totals.
+#[derive(Default)]
+pub struct StatsLog {
+    last_logged: Option<(Instant, Counts)>,
+}
+
+impl StatsLog {
+    /// Logs a summary if one is due at `now`. The first one is a whole interval after the
+    /// first call.
+    pub fn poll(&mut self, stats: &Stats, now: Instant) {
+        let Some((last, logged)) = self.last_logged else {
+            self.last_logged = Some((now, stats.counts()));
+            return;
+        };
+        if now.duration_since(last) < STATS_LOG_INTERVAL {
+            return;
+        }
+
+        let counts = stats.counts();
+        let recent = counts.since(logged);
+        debug!(
+            "Stats over the last {:?}: {} frames, {} decode errors, {} applied, {} skipped, {} reconnects (lifetime: {} frames, {} decode errors, {} applied, {} skipped, {} reconnects)",
+            now.duration_since(last),
+            recent.frames,
+            recent.decode_errors,
+            recent.applied,
+            recent.skipped,
+            recent.reconnects,
+            counts.frames,
+            counts.decode_errors,
+            counts.applied,
+            counts.skipped,
+            counts.reconnects
+        );
+        self.last_logged = Some((now, counts));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_count_their_decode_errors() {
+        let stats = Stats::default();
+        stats.frame(true);
+        stats.frame(false);
+        stats.applied();
+        stats.skipped();
+        stats.skipped();
+        stats.reconnect();
+
+        let counts = stats.counts();
+        assert_eq!(
+            counts,
+            Counts {
+                frames: 2,
+                decode_errors: 1,
+                applied: 1,
+                skipped: 2,
+                reconnects: 1,
+            }
+        );
+        assert_eq!(counts.since(counts), Counts::default());
+    }
+}
@@ -0,0 +1,107 @@
+use crate::config::VolumeTarget;
+use anyhow::Result;
+use gain_lib::Slider;
+use log::{debug, info};
+use std::{
+    io::{ErrorKind, Write},
+    net::{Ipv4Addr, TcpListener, TcpStream},
+};
+
+/// A slider update as broadcast to status clients, one JSON object per line.
+#[derive(serde::Serialize)]
+pub struct SliderStatus<'a> {
+    #[serde(flatten)]
+    pub slider: Slider,
+    /// Volume computed from the slider position, before any ramp.
+    pub volume: f64,
+    #[serde(serialize_with = "serialize_target")]
+    pub target: &'a VolumeTarget,
+}
+
+/// Writes an `unmapped` target without lists as its plain name, as it is usually
+/// configured, and every other target as usual.
+fn serialize_target<S: serde::Serializer>(
+    target: &&VolumeTarget,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    match target {
+        VolumeTarget::Unmapped {
+            exclude,
+            include: None,
+        } if exclude.is_empty() => serializer.serialize_str("unmapped"),
+        target => serde::Serialize::serialize(target, serializer),
+    }
+}
+
+/// Local TCP server that broadcasts slider updates to every connected client.
+///
+/// Everything is non-blocking: new clients are picked up on the next broadcast, and a client
+/// that can't take a whole line right away is disconnected rather than waited on, so the
+/// audio loop never stalls on a slow reader.
+pub struct StatusServer {
+    listener: TcpListener,
+    clients: Vec<TcpStream>,
+}
+
+impl StatusServer {
+    /// Starts listening on `port` on the loopback interface.
+    pub fn bind(port: u16) -> Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        listener.set_nonblocking(true)?;
+        info!("Status server listening on {}", listener.local_addr()?);
+        Ok(StatusServer {
+            listener,
+            clients: Vec::new(),
+        })
+    }
+
+    /// Sends `status` to every client.
+    pub fn broadcast(&mut self, status: &SliderStatus) {
+        self.accept_clients();
+        if self.clients.is_empty() {
+            return;
+        }
+
+        let mut line = match serde_json::to_vec(status) {
+            Ok(line) => line,
+            Err(e) => {
+                debug!("Failed to serialize slider status: {}", e);
+                return;
+            }
+        };
+        line.push(b'\n');
+
+        self.clients.retain_mut(|client| match client.write(&line) {
+            Ok(written) if written == line.len() => true,
+            // A partial line would corrupt the stream, so drop the client instead
+            Ok(_) => {
+                debug!("Status client fell behind, disconnecting it");
+                false
+            }
+            Err(e) => {
+                debug!("Status client disconnected: {}", e);
+                false
+            }
+        });
+    }
+
+    fn accept_clients(&mut self) {
+        loop {
+            match self.listener.accept() {
+                Ok((client, address)) => {
+                    if let Err(e) = client.set_nonblocking(true) {
+                        debug!("Failed to set up status client {}: {}", address, e);
+                        continue;
+                    }
+                    debug!("Status client connected from {}", address);
+                    self.clients.push(client);
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    debug!("Failed to accept status client: {}", e);
+                    break;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use log::{debug, warn};
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+/// Minimum time between two reports of the same recurring error.
+pub const ERROR_LOG_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Rate limit for a warning that can repeat in a tight loop, such as a failing read: it is
+/// logged at most once per interval, along with how many times it was suppressed since.
+/// Suppressed occurrences are still logged at debug level.
+pub struct Throttle {
+    interval: Duration,
+    last_logged: Option<Instant>,
+    suppressed: u32,
+}
+
+impl Default for Throttle {
+    fn default() -> Self {
+        Throttle::new(ERROR_LOG_INTERVAL)
+    }
+}
+
+impl Throttle {
+    pub fn new(interval: Duration) -> Self {
+        Throttle {
+            interval,
+            last_logged: None,
+            suppressed: 0,
+        }
+    }
+
+    /// Records an occurrence at `now`. Returns the number of occurrences suppressed since
+    /// the last report if this one should be reported, or `None` to suppress it.
+    pub fn check(&mut self, now: Instant) -> Option<u32> {
+        if self
+            .last_logged
+            .is_some_and(|last| now.duration_since(last) < self.interval)
+        {
+            self.suppressed += 1;
+            return None;
+        }
+        self.last_logged = Some(now);
+        Some(std::mem::take(&mut self.suppressed))
+    }
+
+    /// Logs `message` as a warning unless it was already reported within the interval.
+    pub fn warn(&mut self, message: fmt::Arguments) {
+        match self.check(Instant::now()) {
+            Some(0) => warn!("{}", message),
+            Some(suppressed) => warn!("{} ({} more since last reported)", message, suppressed),
+            None => debug!("{}", message),
+        }
+    }
+
+    /// Forgets past occurrences, so the next one is reported right away.
+    pub fn reset(&mut self) {
+        self.last_logged = None;
+        self.suppressed = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INTERVAL: Duration = Duration::from_secs(30);
+
+    #[test]
+    fn first_occurrence_is_reported() {
+        let mut throttle = Throttle::new(INTERVAL);
+        assert_eq!(throttle.check(Instant::now()), Some(0));
+    }
+
+    #[test]
+    fn repeats_within_the_interval_are_counted() {
+        let mut throttle = Throttle::new(INTERVAL);
+        let start = Instant::now();
+        throttle.check(start);
+        for i in 1..=5 {
+            assert_eq!(throttle.check(start + Duration::from_secs(i)), None);
+        }
+        assert_eq!(throttle.check(start + INTERVAL), Some(5));
+        // The count starts over after each report
+        assert_eq!(throttle.check(start + INTERVAL * 2), Some(0));
+    }
+
+    #[test]
+    fn reset_reports_the_next_occurrence() {
+        let mut throttle = Throttle::new(INTERVAL);
+        let start = Instant::now();
+        throttle.check(start);
+        throttle.check(start);
+        throttle.reset();
+        assert_eq!(throttle.check(start), Some(0));
+    }
+}
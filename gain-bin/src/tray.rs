@@ -0,0 +1,183 @@
+use crate::shutdown::Shutdown;
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+
+/// Requests made from the system tray menu, polled by the main loop.
+#[derive(Default)]
+struct TrayState {
+    paused: AtomicBool,
+    reload: AtomicBool,
+}
+
+/// System tray icon with a menu to pause slider processing, reload the configuration and
+/// quit. Without the `tray` feature, or off Windows, there is no icon and nothing is ever
+/// requested. The default has no icon either.
+#[derive(Default)]
+pub struct Tray {
+    state: Arc<TrayState>,
+    #[cfg(all(windows, feature = "tray"))]
+    _icon: Option<icon::TrayThread>,
+}
+
+impl Tray {
+    /// Shows the tray icon. Quitting from its menu requests `shutdown`.
+    pub fn start(shutdown: &Shutdown) -> Self {
+        let state = Arc::new(TrayState::default());
+
+        #[cfg(all(windows, feature = "tray"))]
+        return Tray {
+            _icon: icon::TrayThread::spawn(state.clone(), shutdown.clone())
+                .inspect_err(|e| log::warn!("Failed to show the tray icon: {}", e))
+                .ok(),
+            state,
+        };
+
+        #[cfg(all(not(windows), feature = "tray"))]
+        log::warn!("The tray icon is only supported on Windows");
+
+        #[cfg(not(all(windows, feature = "tray")))]
+        {
+            let _ = shutdown;
+            Tray { state }
+        }
+    }
+
+    /// Returns whether slider and button input is paused. Input is still read while paused,
+    /// but volumes are left alone.
+    pub fn paused(&self) -> bool {
+        self.state.paused.load(Ordering::Relaxed)
+    }
+
+    /// Returns whether a config reload was requested since the last call.
+    pub fn take_reload(&self) -> bool {
+        self.state.reload.swap(false, Ordering::Relaxed)
+    }
+}
+
+#[cfg(all(windows, feature = "tray"))]
+mod icon {
+    use super::*;
+    use anyhow::{Result, anyhow};
+    use log::info;
+    use std::{
+        sync::mpsc,
+        thread::{self, JoinHandle},
+    };
+    use tray_icon::{
+        Icon, TrayIconBuilder,
+        menu::{CheckMenuItem, Menu, MenuEvent, MenuItem, PredefinedMenuItem},
+    };
+    use windows::Win32::{
+        Foundation::{LPARAM, WPARAM},
+        System::Threading::GetCurrentThreadId,
+        UI::WindowsAndMessaging::{
+            DispatchMessageW, GetMessageW, MSG, PostThreadMessageW, TranslateMessage, WM_QUIT,
+        },
+    };
+
+    /// Side of the square tray icon, in pixels.
+    const ICON_SIZE: u32 = 16;
+
+    /// Thread that owns the tray icon and runs the message loop its menu needs. The icon is
+    /// removed when this is dropped.
+    pub struct TrayThread {
+        thread_id: u32,
+        handle: Option<JoinHandle<()>>,
+    }
+
+    impl TrayThread {
+        pub fn spawn(state: Arc<TrayState>, shutdown: Shutdown) -> Result<Self> {
+            let (started, result) = mpsc::channel();
+            let handle = thread::spawn(move || {
+                // The icon has to be created on the thread that pumps its messages
+                match show(state, shutdown) {
+                    Ok(_icon) => {
+                        let _ = started.send(Ok(unsafe { GetCurrentThreadId() }));
+                        run_message_loop();
+                    }
+                    Err(e) => {
+                        let _ = started.send(Err(e));
+                    }
+                }
+            });
+
+            let thread_id = result.recv().map_err(|_| anyhow!("Tray thread exited"))??;
+            Ok(TrayThread {
+                thread_id,
+                handle: Some(handle),
+            })
+        }
+    }
+
+    impl Drop for TrayThread {
+        fn drop(&mut self) {
+            unsafe {
+                let _ = PostThreadMessageW(self.thread_id, WM_QUIT, WPARAM(0), LPARAM(0));
+            }
+            if let Some(handle) = self.handle.take() {
+                let _ = handle.join();
+            }
+        }
+    }
+
+    fn show(state: Arc<TrayState>, shutdown: Shutdown) -> Result<tray_icon::TrayIcon> {
+        let pause = CheckMenuItem::new("Pause", true, false, None);
+        let reload = MenuItem::new("Reload config", true, None);
+        let quit = MenuItem::new("Quit", true, None);
+        let menu = Menu::new();
+        menu.append_items(&[&pause, &reload, &PredefinedMenuItem::separator(), &quit])?;
+
+        let (pause_id, reload_id, quit_id) =
+            (pause.id().clone(), reload.id().clone(), quit.id().clone());
+        MenuEvent::set_event_handler(Some(move |event: MenuEvent| {
+            if event.id == pause_id {
+                let paused = !state.paused.fetch_xor(true, Ordering::Relaxed);
+                info!(
+                    "{} from the tray menu",
+                    if paused { "Paused" } else { "Resumed" }
+                );
+            } else if event.id == reload_id {
+                state.reload.store(true, Ordering::Relaxed);
+            } else if event.id == quit_id {
+                info!("Quit from the tray menu");
+                shutdown.request();
+            }
+        }));
+
+        Ok(TrayIconBuilder::new()
+            .with_menu(Box::new(menu))
+            .with_tooltip("gain")
+            .with_icon(icon()?)
+            .build()?)
+    }
+
+    /// Draws the icon: three vertical sliders at different heights.
+    fn icon() -> Result<Icon> {
+        let knobs = [4, 9, 6];
+        let mut rgba = Vec::with_capacity((ICON_SIZE * ICON_SIZE * 4) as usize);
+        for y in 0..ICON_SIZE {
+            for x in 0..ICON_SIZE {
+                let track = (x % 5 == 2).then_some((x / 5) as usize);
+                let pixel = match track {
+                    Some(i) if (knobs[i]..knobs[i] + 3).contains(&y) => [0xFF, 0xA0, 0x20, 0xFF],
+                    Some(_) if (1..ICON_SIZE - 1).contains(&y) => [0xE0, 0xE0, 0xE0, 0xFF],
+                    _ => [0, 0, 0, 0],
+                };
+                rgba.extend_from_slice(&pixel);
+            }
+        }
+        Ok(Icon::from_rgba(rgba, ICON_SIZE, ICON_SIZE)?)
+    }
+
+    fn run_message_loop() {
+        let mut message = MSG::default();
+        unsafe {
+            while GetMessageW(&mut message, None, 0, 0).as_bool() {
+                let _ = TranslateMessage(&message);
+                DispatchMessageW(&message);
+            }
+        }
+    }
+}
@@ -1,216 +1,172 @@
-use anyhow::{Result, anyhow};
-use log::{error, trace};
-use std::{ffi::OsString, os::windows::ffi::OsStringExt};
-use windows::{
-    Win32::Foundation::{CloseHandle, MAX_PATH},
-    Win32::Media::Audio::Endpoints::IAudioEndpointVolume,
-    Win32::Media::Audio::{
-        IAudioSessionControl2, IAudioSessionManager2, IMMDeviceEnumerator, ISimpleAudioVolume,
-        MMDeviceEnumerator, eConsole, eRender,
-    },
-    Win32::System::Com::{CLSCTX_ALL, COINIT_MULTITHREADED, CoCreateInstance, CoInitializeEx},
-    Win32::System::ProcessStatus::K32GetModuleBaseNameW,
-    Win32::System::Threading::{OpenProcess, PROCESS_QUERY_INFORMATION, PROCESS_VM_READ},
-    Win32::UI::WindowsAndMessaging::{GetForegroundWindow, GetWindowThreadProcessId},
-    core::{Interface, Result as WindowsResult},
+#[cfg(all(target_os = "linux", feature = "alsa"))]
+mod alsa;
+#[cfg(target_os = "macos")]
+mod coreaudio;
+mod dry_run;
+// Only the Windows backend looks processes up by PID
+#[cfg_attr(not(windows), allow(dead_code))]
+mod processes;
+#[cfg(all(target_os = "linux", feature = "pulseaudio"))]
+mod pulseaudio;
+mod unchanged;
+mod unsupported;
+#[cfg(windows)]
+mod wasapi;
+
+use crate::{
+    config::AudioBackend,
+    matcher::{AppMatcher, UnmappedApps},
 };
-
-/// Initializes the COM library for use by the calling thread.
-pub fn windows_init() -> Result<()> {
-    unsafe {
-        if let Err(e) = CoInitializeEx(None, COINIT_MULTITHREADED).ok() {
-            error!("Failed to initialize COM: {}", e);
-            return Err(e.into());
-        }
-    }
-    Ok(())
-}
-
-/// Sets the master system volume to the specified level (0.0 to 1.0).
-pub fn set_master_volume(volume: f64) -> Result<()> {
-    unsafe {
-        let enumerator: WindowsResult<IMMDeviceEnumerator> =
-            CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL);
-
-        if let Ok(enumerator) = enumerator
-            && let Ok(device) = enumerator.GetDefaultAudioEndpoint(eRender, eConsole)
-        {
-            let endpoint_vol: WindowsResult<IAudioEndpointVolume> =
-                device.Activate(CLSCTX_ALL, None);
-
-            if let Ok(endpoint_vol) = endpoint_vol {
-                endpoint_vol.SetMute(volume <= 0.0, std::ptr::null())?;
-                endpoint_vol.SetMasterVolumeLevelScalar(volume as f32, std::ptr::null())?;
-                trace!("Set master volume to {}", volume);
-            }
-        }
-        Ok(())
-    }
+use anyhow::Result;
+
+pub use dry_run::DryRunBackend;
+pub use unchanged::SkipUnchanged;
+
+/// Platform audio system that slider and button actions are applied to.
+///
+/// Volumes are scalars from 0.0 to 1.0. Apps are matched against the name of the process
+/// that owns each audio session or stream.
+pub trait VolumeBackend {
+    /// Sets the master system volume.
+    fn set_master(&mut self, volume: f64) -> Result<()>;
+    /// Mutes or unmutes the master system volume.
+    fn set_master_mute(&mut self, mute: bool) -> Result<()>;
+    /// Sets the volume of the default input (microphone) device.
+    fn set_microphone(&mut self, volume: f64) -> Result<()>;
+    /// Mutes or unmutes the default input (microphone) device.
+    fn set_microphone_mute(&mut self, mute: bool) -> Result<()>;
+    /// Sets the volume of the currently focused application.
+    fn set_current_app(&mut self, volume: f64) -> Result<()>;
+    /// Mutes or unmutes the currently focused application.
+    fn set_current_app_mute(&mut self, mute: bool) -> Result<()>;
+    /// Sets the volume of every application matched by `app`.
+    fn set_app(&mut self, app: &AppMatcher, volume: f64) -> Result<()>;
+    /// Mutes or unmutes every application matched by `app`.
+    fn set_app_mute(&mut self, app: &AppMatcher, mute: bool) -> Result<()>;
+    /// Sets the volume of every application in the `unmapped` set.
+    fn set_unmapped(&mut self, volume: f64, unmapped: &UnmappedApps) -> Result<()>;
+    /// Mutes or unmutes every application in the `unmapped` set.
+    fn set_unmapped_mute(&mut self, mute: bool, unmapped: &UnmappedApps) -> Result<()>;
+    /// Sets the volume of the system sounds session, if there is one yet.
+    fn set_system_sounds(&mut self, volume: f64) -> Result<()>;
+    /// Mutes or unmutes the system sounds session, if there is one yet.
+    fn set_system_sounds_mute(&mut self, mute: bool) -> Result<()>;
+
+    /// Returns the master system volume.
+    fn get_master(&mut self) -> Result<f64>;
+    /// Returns the volume of the default input (microphone) device.
+    fn get_microphone(&mut self) -> Result<f64>;
+    /// Returns whether the master system volume is muted.
+    fn get_master_mute(&mut self) -> Result<bool>;
+    /// Returns whether the default input (microphone) device is muted.
+    fn get_microphone_mute(&mut self) -> Result<bool>;
+    /// Returns the volume of the currently focused application, if it has an audio session.
+    fn get_current_app(&mut self) -> Result<Option<f64>>;
+    /// Returns the volume of the first session matched by `app`, if any.
+    fn get_app(&mut self, app: &AppMatcher) -> Result<Option<f64>>;
+    /// Returns the volume of the first session in the `unmapped` set, if any.
+    fn get_unmapped(&mut self, unmapped: &UnmappedApps) -> Result<Option<f64>>;
+    /// Returns the volume of the system sounds session, if there is one yet.
+    fn get_system_sounds(&mut self) -> Result<Option<f64>>;
+
+    /// Sets the master volume of the output device named `device`.
+    fn set_device(&mut self, device: &str, volume: f64) -> Result<()>;
+    /// Mutes or unmutes the output device named `device`.
+    fn set_device_mute(&mut self, device: &str, mute: bool) -> Result<()>;
+    /// Returns the master volume of the output device named `device`.
+    fn get_device(&mut self, device: &str) -> Result<f64>;
+    /// Returns whether the output device named `device` is muted.
+    fn get_device_mute(&mut self, device: &str) -> Result<bool>;
+    /// Lists the names of the output devices, i.e. what device targets can be matched
+    /// against.
+    fn devices(&mut self) -> Result<Vec<String>>;
+
+    /// Returns the ID of the process that owns the focused window, if it can be told.
+    fn focused_pid(&mut self) -> Result<Option<u32>>;
+    /// Returns the title of the focused window, if there is one and it can be told.
+    fn focused_title(&mut self) -> Result<Option<String>>;
+
+    /// Lists the current audio sessions, i.e. what app targets can be matched against.
+    fn sessions(&mut self) -> Result<Vec<AudioSession>>;
+
+    /// Forgets which volumes were already applied, for backends that skip setting a volume
+    /// twice, so the next volume set on each target is applied even if it is the same.
+    fn forget_applied(&mut self) {}
 }
 
-/// Sets the volume of the currently focused application to the specified level (0.0 to 1.0).
-pub fn set_current_app_volume(volume: f64) -> Result<()> {
-    unsafe {
-        let hwnd = GetForegroundWindow();
-        if hwnd.0.is_null() {
-            return Ok(());
-        }
-
-        let mut pid: u32 = 0;
-        GetWindowThreadProcessId(hwnd, Some(&mut pid));
-
-        if pid == 0 {
-            return Ok(());
-        }
-
-        with_session_enumerator(|session_enum, count| {
-            for i in 0..count {
-                let process_session = || -> Result<()> {
-                    let control = session_enum.GetSession(i)?;
-                    let control2 = control.cast::<IAudioSessionControl2>()?;
-                    let session_pid = control2.GetProcessId()?;
-
-                    if session_pid == pid {
-                        let simple_vol = control.cast::<ISimpleAudioVolume>()?;
-                        set_volume(simple_vol, volume)?;
-                        trace!("Set focused app (PID {}) volume to {}", pid, volume);
-                    }
-                    Ok(())
-                };
-
-                let _ = process_session();
-            }
-            Ok(())
-        })?;
-        Ok(())
-    }
+/// An application's audio session (or playback stream on Linux).
+pub struct AudioSession {
+    /// ID of the owning process, 0 for sessions that don't belong to a single process
+    /// (such as Windows system sounds).
+    pub pid: u32,
+    /// Process name that app targets are matched against, if it could be determined.
+    pub name: Option<String>,
 }
 
-/// Sets the volume of a specific application (by name) to the specified level (0.0 to 1.0).
-pub fn set_app_volume(target_app_name: &str, volume: f64) -> Result<()> {
-    let target_lower = target_app_name.to_lowercase();
-
-    unsafe {
-        with_session_enumerator(|session_enum, count| {
-            for i in 0..count {
-                let process_session = || -> Result<()> {
-                    let control = session_enum.GetSession(i)?;
-                    let control2 = control.cast::<IAudioSessionControl2>()?;
-                    let pid = control2.GetProcessId()?;
-
-                    let name =
-                        get_process_name(pid).ok_or_else(|| anyhow!("Process name not found"))?;
-
-                    if name.to_lowercase().contains(&target_lower) {
-                        let simple_vol = control.cast::<ISimpleAudioVolume>()?;
-                        set_volume(simple_vol, volume)?;
-                        trace!("Set {} volume to {}", name, volume);
-                    }
-                    Ok(())
-                };
-
-                let _ = process_session();
-            }
-            Ok(())
-        })?;
-        Ok(())
-    }
+/// Returns whether a device called `name` is the one a device target asks for.
+#[cfg_attr(
+    not(any(
+        windows,
+        target_os = "macos",
+        all(target_os = "linux", any(feature = "pulseaudio", feature = "alsa"))
+    )),
+    allow(dead_code)
+)]
+fn is_device(name: &str, wanted: &str) -> bool {
+    name.eq_ignore_ascii_case(wanted)
 }
 
-/// Sets the volume for all applications not in the mapped_apps list to the specified level (0.0 to 1.0).
-pub fn set_unmapped_volume(volume: f64, mapped_apps: &[String]) -> Result<()> {
-    let excluded_lower: Vec<String> = mapped_apps.iter().map(|s| s.to_lowercase()).collect();
-
-    unsafe {
-        with_session_enumerator(|session_enum, count| {
-            for i in 0..count {
-                let process_session = || -> Result<()> {
-                    let control = session_enum.GetSession(i)?;
-                    let control2 = control.cast::<IAudioSessionControl2>()?;
-                    let pid = control2.GetProcessId()?;
-
-                    let name =
-                        get_process_name(pid).ok_or_else(|| anyhow!("Process name not found"))?;
-
-                    let name_lower = name.to_lowercase();
-
-                    let is_excluded = excluded_lower.iter().any(|ex| name_lower.contains(ex));
-
-                    if !is_excluded {
-                        let simple_vol = control.cast::<ISimpleAudioVolume>()?;
-                        set_volume(simple_vol, volume)?;
-                        trace!("Set unmapped app {} volume to {}", name, volume);
-                    }
-                    Ok(())
-                };
-
-                let _ = process_session();
-            }
-            Ok(())
-        })?;
-        Ok(())
+/// Creates the volume backend for the current platform, or one that ignores every change
+/// if there is none. `choice` picks the audio system on Linux and is ignored elsewhere.
+pub fn new_backend(choice: AudioBackend) -> Result<Box<dyn VolumeBackend>> {
+    #[cfg(not(target_os = "linux"))]
+    if choice != AudioBackend::Auto {
+        log::warn!("general.backend only applies on Linux; ignoring it");
     }
-}
-
-unsafe fn set_volume(sav: ISimpleAudioVolume, volume: f64) -> Result<()> {
-    let volume = volume.clamp(0.0, 1.0);
-    unsafe { sav.SetMute(volume <= 0.0, std::ptr::null())? }
-    unsafe { sav.SetMasterVolume(volume as f32, std::ptr::null())? }
-    Ok(())
-}
-
-unsafe fn with_session_enumerator<F>(mut callback: F) -> Result<()>
-where
-    F: FnMut(&windows::Win32::Media::Audio::IAudioSessionEnumerator, i32) -> Result<()>,
-{
-    unsafe {
-        let enumerator: IMMDeviceEnumerator =
-            CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)?;
-
-        let device = enumerator.GetDefaultAudioEndpoint(eRender, eConsole)?;
-
-        let manager: IAudioSessionManager2 = device.Activate(CLSCTX_ALL, None)?;
 
-        let session_enum = manager.GetSessionEnumerator()?;
+    #[cfg(windows)]
+    return Ok(Box::new(wasapi::WasapiBackend::new()?));
 
-        let count = session_enum.GetCount()?;
+    #[cfg(target_os = "linux")]
+    return linux_backend(choice);
 
-        callback(&session_enum, count)?;
+    #[cfg(target_os = "macos")]
+    return Ok(Box::new(coreaudio::CoreAudioBackend::new()?));
 
-        Ok(())
-    }
+    #[allow(unreachable_code)]
+    Ok(Box::new(unsupported::UnsupportedBackend::default()))
 }
 
-unsafe fn get_process_name(process_id: u32) -> Option<String> {
-    if process_id == 0 {
-        return None;
-    }
-
-    unsafe {
-        let handle = OpenProcess(
-            PROCESS_QUERY_INFORMATION | PROCESS_VM_READ,
-            false,
-            process_id,
-        )
-        .ok()?;
-
-        if handle.is_invalid() {
-            return None;
+/// Creates the backend `choice` asks for, erroring if this build doesn't include it. `auto`
+/// uses PulseAudio when a server is running, and falls back to ALSA when it isn't.
+#[cfg(target_os = "linux")]
+#[allow(unreachable_code)]
+fn linux_backend(choice: AudioBackend) -> Result<Box<dyn VolumeBackend>> {
+    match choice {
+        AudioBackend::PulseAudio => {
+            #[cfg(feature = "pulseaudio")]
+            return Ok(Box::new(pulseaudio::PulseAudioBackend::new()?));
+            Err(anyhow::anyhow!(
+                "gain was built without the `pulseaudio` feature"
+            ))
         }
-
-        let mut buffer = [0u16; MAX_PATH as usize];
-        let result = K32GetModuleBaseNameW(handle, None, &mut buffer);
-        let _ = CloseHandle(handle);
-
-        if result == 0 {
-            return None;
+        AudioBackend::Alsa => {
+            #[cfg(feature = "alsa")]
+            return Ok(Box::new(alsa::AlsaBackend::new()?));
+            Err(anyhow::anyhow!("gain was built without the `alsa` feature"))
+        }
+        AudioBackend::Auto => {
+            #[cfg(feature = "pulseaudio")]
+            match pulseaudio::PulseAudioBackend::new() {
+                Ok(backend) => return Ok(Box::new(backend)),
+                #[cfg(feature = "alsa")]
+                Err(e) => log::info!("{}, falling back to ALSA", e),
+                #[cfg(not(feature = "alsa"))]
+                Err(e) => return Err(e),
+            }
+            #[cfg(feature = "alsa")]
+            return Ok(Box::new(alsa::AlsaBackend::new()?));
+            Ok(Box::new(unsupported::UnsupportedBackend::default()))
         }
-
-        let len = result as usize;
-        let name = OsString::from_wide(&buffer[0..len])
-            .to_string_lossy()
-            .into_owned();
-
-        Some(name)
     }
 }
@@ -0,0 +1,308 @@
+use super::{AudioSession, VolumeBackend, is_device};
+use crate::matcher::{AppMatcher, UnmappedApps};
+use alsa::{
+    card,
+    mixer::{Mixer, Selem, SelemChannelId, SelemId},
+};
+use anyhow::{Context, Result, anyhow};
+use log::{trace, warn};
+
+/// Mixer device that follows the default card.
+const DEFAULT_MIXER: &str = "default";
+/// Simple mixer element holding a card's playback volume.
+const PLAYBACK_ELEMENT: &str = "Master";
+/// Simple mixer element holding a card's capture (microphone) volume.
+const CAPTURE_ELEMENT: &str = "Capture";
+
+/// Volume backend for Linux systems running bare ALSA, without a PulseAudio or PipeWire
+/// server.
+///
+/// ALSA mixes every app into the card itself, so only `master`, `microphone` and device
+/// targets are supported, through the `Master` and `Capture` mixer elements. Device targets
+/// are matched against card names, as listed by `list-devices`. App, `current`,
+/// `foreground`, `window_title`, `unmapped` and `system_sounds` targets log a warning once
+/// and are otherwise ignored.
+pub struct AlsaBackend {
+    mixer: Mixer,
+    warned_apps: bool,
+}
+
+/// Which side of a mixer element an operation applies to.
+#[derive(Clone, Copy)]
+enum Direction {
+    Playback,
+    Capture,
+}
+
+impl AlsaBackend {
+    pub fn new() -> Result<Self> {
+        let mixer = Mixer::new(DEFAULT_MIXER, false).context("Failed to open the ALSA mixer")?;
+        // Fail early if the default card has nothing to control
+        element(&mixer, PLAYBACK_ELEMENT)?;
+        Ok(AlsaBackend {
+            mixer,
+            warned_apps: false,
+        })
+    }
+
+    fn warn_apps_unsupported(&mut self) {
+        if !self.warned_apps {
+            warn!(
+                "ALSA has no per-application volume; ignoring app, `current`, `foreground`, `window_title`, `unmapped` and `system_sounds` targets"
+            );
+            self.warned_apps = true;
+        }
+    }
+}
+
+/// Picks up changes made by other programs, then looks up the element called `name`.
+fn element<'a>(mixer: &'a Mixer, name: &str) -> Result<Selem<'a>> {
+    mixer.handle_events()?;
+    mixer
+        .find_selem(&SelemId::new(name, 0))
+        .ok_or_else(|| anyhow!("The ALSA mixer has no {} element", name))
+}
+
+/// Opens the mixer of the card called `device`.
+fn card_mixer(device: &str) -> Result<Mixer> {
+    for card in card::Iter::new() {
+        let card = card?;
+        if is_device(&card.get_name()?, device) {
+            return Ok(Mixer::new(&format!("hw:{}", card.get_index()), false)?);
+        }
+    }
+    Err(anyhow!("No sound card called {}", device))
+}
+
+fn volume(selem: &Selem, direction: Direction) -> Result<f64> {
+    let ((min, max), value) = match direction {
+        Direction::Playback => (
+            selem.get_playback_volume_range(),
+            selem.get_playback_volume(SelemChannelId::mono())?,
+        ),
+        Direction::Capture => (
+            selem.get_capture_volume_range(),
+            selem.get_capture_volume(SelemChannelId::mono())?,
+        ),
+    };
+    if max <= min {
+        return Ok(0.0);
+    }
+    Ok((value - min) as f64 / (max - min) as f64)
+}
+
+fn set_volume(selem: &Selem, direction: Direction, volume: f64) -> Result<()> {
+    let (min, max) = match direction {
+        Direction::Playback => selem.get_playback_volume_range(),
+        Direction::Capture => selem.get_capture_volume_range(),
+    };
+    let value = min + (volume.clamp(0.0, 1.0) * (max - min) as f64).round() as i64;
+    match direction {
+        Direction::Playback => selem.set_playback_volume_all(value)?,
+        Direction::Capture => selem.set_capture_volume_all(value)?,
+    }
+    Ok(())
+}
+
+/// Returns whether the element is muted, which elements without a switch never are.
+fn muted(selem: &Selem, direction: Direction) -> Result<bool> {
+    let switch = match direction {
+        Direction::Playback if selem.has_playback_switch() => {
+            selem.get_playback_switch(SelemChannelId::mono())?
+        }
+        Direction::Capture if selem.has_capture_switch() => {
+            selem.get_capture_switch(SelemChannelId::mono())?
+        }
+        _ => return Ok(false),
+    };
+    Ok(switch == 0)
+}
+
+fn set_mute(selem: &Selem, direction: Direction, mute: bool) -> Result<()> {
+    // The switch is on while the element is audible
+    let switch = i32::from(!mute);
+    match direction {
+        Direction::Playback if selem.has_playback_switch() => {
+            selem.set_playback_switch_all(switch)?
+        }
+        Direction::Capture if selem.has_capture_switch() => selem.set_capture_switch_all(switch)?,
+        _ => return Err(anyhow!("The ALSA mixer element can't be muted")),
+    }
+    Ok(())
+}
+
+impl VolumeBackend for AlsaBackend {
+    fn set_master(&mut self, volume: f64) -> Result<()> {
+        set_volume(
+            &element(&self.mixer, PLAYBACK_ELEMENT)?,
+            Direction::Playback,
+            volume,
+        )?;
+        trace!("Set master volume to {}", volume);
+        Ok(())
+    }
+
+    fn set_master_mute(&mut self, mute: bool) -> Result<()> {
+        set_mute(
+            &element(&self.mixer, PLAYBACK_ELEMENT)?,
+            Direction::Playback,
+            mute,
+        )?;
+        trace!("Set master mute to {}", mute);
+        Ok(())
+    }
+
+    fn set_microphone(&mut self, volume: f64) -> Result<()> {
+        set_volume(
+            &element(&self.mixer, CAPTURE_ELEMENT)?,
+            Direction::Capture,
+            volume,
+        )?;
+        trace!("Set microphone volume to {}", volume);
+        Ok(())
+    }
+
+    fn set_microphone_mute(&mut self, mute: bool) -> Result<()> {
+        set_mute(
+            &element(&self.mixer, CAPTURE_ELEMENT)?,
+            Direction::Capture,
+            mute,
+        )?;
+        trace!("Set microphone mute to {}", mute);
+        Ok(())
+    }
+
+    fn set_current_app(&mut self, _volume: f64) -> Result<()> {
+        self.warn_apps_unsupported();
+        Ok(())
+    }
+
+    fn set_current_app_mute(&mut self, _mute: bool) -> Result<()> {
+        self.warn_apps_unsupported();
+        Ok(())
+    }
+
+    fn set_app(&mut self, _app: &AppMatcher, _volume: f64) -> Result<()> {
+        self.warn_apps_unsupported();
+        Ok(())
+    }
+
+    fn set_app_mute(&mut self, _app: &AppMatcher, _mute: bool) -> Result<()> {
+        self.warn_apps_unsupported();
+        Ok(())
+    }
+
+    fn set_unmapped(&mut self, _volume: f64, _unmapped: &UnmappedApps) -> Result<()> {
+        self.warn_apps_unsupported();
+        Ok(())
+    }
+
+    fn set_unmapped_mute(&mut self, _mute: bool, _unmapped: &UnmappedApps) -> Result<()> {
+        self.warn_apps_unsupported();
+        Ok(())
+    }
+
+    fn set_system_sounds(&mut self, _volume: f64) -> Result<()> {
+        self.warn_apps_unsupported();
+        Ok(())
+    }
+
+    fn set_system_sounds_mute(&mut self, _mute: bool) -> Result<()> {
+        self.warn_apps_unsupported();
+        Ok(())
+    }
+
+    fn set_device(&mut self, device: &str, volume: f64) -> Result<()> {
+        let mixer = card_mixer(device)?;
+        set_volume(
+            &element(&mixer, PLAYBACK_ELEMENT)?,
+            Direction::Playback,
+            volume,
+        )?;
+        trace!("Set device {} volume to {}", device, volume);
+        Ok(())
+    }
+
+    fn set_device_mute(&mut self, device: &str, mute: bool) -> Result<()> {
+        let mixer = card_mixer(device)?;
+        set_mute(
+            &element(&mixer, PLAYBACK_ELEMENT)?,
+            Direction::Playback,
+            mute,
+        )?;
+        trace!("Set device {} mute to {}", device, mute);
+        Ok(())
+    }
+
+    fn get_master(&mut self) -> Result<f64> {
+        volume(
+            &element(&self.mixer, PLAYBACK_ELEMENT)?,
+            Direction::Playback,
+        )
+    }
+
+    fn get_microphone(&mut self) -> Result<f64> {
+        volume(&element(&self.mixer, CAPTURE_ELEMENT)?, Direction::Capture)
+    }
+
+    fn get_master_mute(&mut self) -> Result<bool> {
+        muted(
+            &element(&self.mixer, PLAYBACK_ELEMENT)?,
+            Direction::Playback,
+        )
+    }
+
+    fn get_microphone_mute(&mut self) -> Result<bool> {
+        muted(&element(&self.mixer, CAPTURE_ELEMENT)?, Direction::Capture)
+    }
+
+    fn get_current_app(&mut self) -> Result<Option<f64>> {
+        self.warn_apps_unsupported();
+        Ok(None)
+    }
+
+    fn get_app(&mut self, _app: &AppMatcher) -> Result<Option<f64>> {
+        self.warn_apps_unsupported();
+        Ok(None)
+    }
+
+    fn get_unmapped(&mut self, _unmapped: &UnmappedApps) -> Result<Option<f64>> {
+        self.warn_apps_unsupported();
+        Ok(None)
+    }
+
+    fn get_system_sounds(&mut self) -> Result<Option<f64>> {
+        self.warn_apps_unsupported();
+        Ok(None)
+    }
+
+    fn get_device(&mut self, device: &str) -> Result<f64> {
+        let mixer = card_mixer(device)?;
+        volume(&element(&mixer, PLAYBACK_ELEMENT)?, Direction::Playback)
+    }
+
+    fn get_device_mute(&mut self, device: &str) -> Result<bool> {
+        let mixer = card_mixer(device)?;
+        muted(&element(&mixer, PLAYBACK_ELEMENT)?, Direction::Playback)
+    }
+
+    fn devices(&mut self) -> Result<Vec<String>> {
+        card::Iter::new()
+            .map(|card| Ok(card?.get_name()?))
+            .collect()
+    }
+
+    fn focused_pid(&mut self) -> Result<Option<u32>> {
+        Ok(None)
+    }
+
+    fn focused_title(&mut self) -> Result<Option<String>> {
+        self.warn_apps_unsupported();
+        Ok(None)
+    }
+
+    fn sessions(&mut self) -> Result<Vec<AudioSession>> {
+        self.warn_apps_unsupported();
+        Ok(Vec::new())
+    }
+}
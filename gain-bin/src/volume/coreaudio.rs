@@ -0,0 +1,457 @@
+use super::{AudioSession, VolumeBackend, is_device};
+use crate::matcher::{AppMatcher, UnmappedApps};
+use anyhow::{Result, anyhow};
+use log::{trace, warn};
+use objc2_core_audio::{
+    AudioObjectGetPropertyData, AudioObjectGetPropertyDataSize, AudioObjectHasProperty,
+    AudioObjectID, AudioObjectPropertyAddress, AudioObjectPropertyScope,
+    AudioObjectPropertySelector, AudioObjectSetPropertyData, kAudioDevicePropertyMute,
+    kAudioDevicePropertyStreams, kAudioDevicePropertyVolumeScalar,
+    kAudioHardwarePropertyDefaultInputDevice, kAudioHardwarePropertyDefaultOutputDevice,
+    kAudioHardwarePropertyDevices, kAudioObjectPropertyElementMain, kAudioObjectPropertyName,
+    kAudioObjectPropertyScopeGlobal, kAudioObjectPropertyScopeInput,
+    kAudioObjectPropertyScopeOutput, kAudioObjectSystemObject,
+};
+use objc2_core_foundation::{CFRetained, CFString};
+use std::{ffi::c_void, ptr::NonNull};
+
+/// Channels whose volume is used when a device has no main volume control, as is the
+/// case for most built-in speakers and microphones.
+const STEREO_CHANNELS: [u32; 2] = [1, 2];
+
+/// Volume backend for macOS, built on the CoreAudio properties of the default devices.
+///
+/// macOS has no public API for per-application volume, so only `master` and `microphone`
+/// targets are supported. App, `current`, `foreground` and `unmapped` targets log a warning
+/// once and are otherwise ignored.
+pub struct CoreAudioBackend {
+    warned_apps: bool,
+}
+
+/// Which device an operation applies to.
+#[derive(Clone, Copy)]
+enum Device {
+    /// The default output device.
+    Output,
+    /// The default input device.
+    Input,
+    /// A specific output device.
+    Named(AudioObjectID),
+}
+
+impl Device {
+    /// Looks up the output device called `name`.
+    fn named(name: &str) -> Result<Self> {
+        output_devices()?
+            .into_iter()
+            .find(|(device_name, _)| is_device(device_name, name))
+            .map(|(_, id)| Device::Named(id))
+            .ok_or_else(|| anyhow!("No output device named {}", name))
+    }
+
+    fn scope(self) -> AudioObjectPropertyScope {
+        match self {
+            Device::Output | Device::Named(_) => kAudioObjectPropertyScopeOutput,
+            Device::Input => kAudioObjectPropertyScopeInput,
+        }
+    }
+
+    /// Looks up the current default device, which can change at any time.
+    fn id(self) -> Result<AudioObjectID> {
+        let selector = match self {
+            Device::Output => kAudioHardwarePropertyDefaultOutputDevice,
+            Device::Input => kAudioHardwarePropertyDefaultInputDevice,
+            Device::Named(id) => return Ok(id),
+        };
+        let address = address(
+            selector,
+            kAudioObjectPropertyScopeGlobal,
+            kAudioObjectPropertyElementMain,
+        );
+        let id: AudioObjectID = get_property(kAudioObjectSystemObject as AudioObjectID, address)?;
+        if id == 0 {
+            return Err(anyhow!("No default audio device"));
+        }
+        Ok(id)
+    }
+
+    /// Returns the elements carrying `selector` on the device: the main element if it has
+    /// one, otherwise the stereo channels that do.
+    fn elements(self, device: AudioObjectID, selector: AudioObjectPropertySelector) -> Vec<u32> {
+        let main = address(selector, self.scope(), kAudioObjectPropertyElementMain);
+        if has_property(device, main) {
+            return vec![kAudioObjectPropertyElementMain];
+        }
+        STEREO_CHANNELS
+            .into_iter()
+            .filter(|&channel| has_property(device, address(selector, self.scope(), channel)))
+            .collect()
+    }
+
+    fn volume(self) -> Result<f64> {
+        let device = self.id()?;
+        let elements = self.elements(device, kAudioDevicePropertyVolumeScalar);
+        if elements.is_empty() {
+            return Err(anyhow!("The device has no volume control"));
+        }
+
+        let mut total = 0.0;
+        for &element in &elements {
+            let address = address(kAudioDevicePropertyVolumeScalar, self.scope(), element);
+            total += get_property::<f32>(device, address)? as f64;
+        }
+        Ok(total / elements.len() as f64)
+    }
+
+    fn set_volume(self, volume: f64) -> Result<()> {
+        let device = self.id()?;
+        let volume = volume.clamp(0.0, 1.0) as f32;
+        for element in self.elements(device, kAudioDevicePropertyVolumeScalar) {
+            let address = address(kAudioDevicePropertyVolumeScalar, self.scope(), element);
+            set_property(device, address, &volume)?;
+        }
+        self.set_mute(volume <= 0.0)
+    }
+
+    /// Returns whether the device is muted, which devices without a mute control never are.
+    fn muted(self) -> Result<bool> {
+        let device = self.id()?;
+        match self.elements(device, kAudioDevicePropertyMute).first() {
+            Some(&element) => {
+                let address = address(kAudioDevicePropertyMute, self.scope(), element);
+                Ok(get_property::<u32>(device, address)? != 0)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn set_mute(self, mute: bool) -> Result<()> {
+        let device = self.id()?;
+        let mute = mute as u32;
+        for element in self.elements(device, kAudioDevicePropertyMute) {
+            set_property(
+                device,
+                address(kAudioDevicePropertyMute, self.scope(), element),
+                &mute,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl CoreAudioBackend {
+    pub fn new() -> Result<Self> {
+        // Fail early if there is no audio system to talk to
+        Device::Output.id()?;
+        Ok(CoreAudioBackend { warned_apps: false })
+    }
+
+    fn warn_apps_unsupported(&mut self) {
+        if !self.warned_apps {
+            warn!(
+                "macOS has no per-application volume; ignoring app, `current`, `foreground`, `window_title`, `unmapped` and `system_sounds` targets"
+            );
+            self.warned_apps = true;
+        }
+    }
+}
+
+impl VolumeBackend for CoreAudioBackend {
+    fn set_master(&mut self, volume: f64) -> Result<()> {
+        Device::Output.set_volume(volume)?;
+        trace!("Set master volume to {}", volume);
+        Ok(())
+    }
+
+    fn set_master_mute(&mut self, mute: bool) -> Result<()> {
+        Device::Output.set_mute(mute)?;
+        trace!("Set master mute to {}", mute);
+        Ok(())
+    }
+
+    fn set_microphone(&mut self, volume: f64) -> Result<()> {
+        Device::Input.set_volume(volume)?;
+        trace!("Set microphone volume to {}", volume);
+        Ok(())
+    }
+
+    fn set_microphone_mute(&mut self, mute: bool) -> Result<()> {
+        Device::Input.set_mute(mute)?;
+        trace!("Set microphone mute to {}", mute);
+        Ok(())
+    }
+
+    fn set_current_app(&mut self, _volume: f64) -> Result<()> {
+        self.warn_apps_unsupported();
+        Ok(())
+    }
+
+    fn set_current_app_mute(&mut self, _mute: bool) -> Result<()> {
+        self.warn_apps_unsupported();
+        Ok(())
+    }
+
+    fn set_app(&mut self, _app: &AppMatcher, _volume: f64) -> Result<()> {
+        self.warn_apps_unsupported();
+        Ok(())
+    }
+
+    fn set_app_mute(&mut self, _app: &AppMatcher, _mute: bool) -> Result<()> {
+        self.warn_apps_unsupported();
+        Ok(())
+    }
+
+    fn set_unmapped(&mut self, _volume: f64, _unmapped: &UnmappedApps) -> Result<()> {
+        self.warn_apps_unsupported();
+        Ok(())
+    }
+
+    fn set_unmapped_mute(&mut self, _mute: bool, _unmapped: &UnmappedApps) -> Result<()> {
+        self.warn_apps_unsupported();
+        Ok(())
+    }
+
+    fn set_system_sounds(&mut self, _volume: f64) -> Result<()> {
+        self.warn_apps_unsupported();
+        Ok(())
+    }
+
+    fn set_system_sounds_mute(&mut self, _mute: bool) -> Result<()> {
+        self.warn_apps_unsupported();
+        Ok(())
+    }
+
+    fn set_device(&mut self, device: &str, volume: f64) -> Result<()> {
+        Device::named(device)?.set_volume(volume)?;
+        trace!("Set device {} volume to {}", device, volume);
+        Ok(())
+    }
+
+    fn set_device_mute(&mut self, device: &str, mute: bool) -> Result<()> {
+        Device::named(device)?.set_mute(mute)?;
+        trace!("Set device {} mute to {}", device, mute);
+        Ok(())
+    }
+
+    fn get_master(&mut self) -> Result<f64> {
+        Device::Output.volume()
+    }
+
+    fn get_microphone(&mut self) -> Result<f64> {
+        Device::Input.volume()
+    }
+
+    fn get_master_mute(&mut self) -> Result<bool> {
+        Device::Output.muted()
+    }
+
+    fn get_microphone_mute(&mut self) -> Result<bool> {
+        Device::Input.muted()
+    }
+
+    fn get_current_app(&mut self) -> Result<Option<f64>> {
+        self.warn_apps_unsupported();
+        Ok(None)
+    }
+
+    fn get_app(&mut self, _app: &AppMatcher) -> Result<Option<f64>> {
+        self.warn_apps_unsupported();
+        Ok(None)
+    }
+
+    fn get_unmapped(&mut self, _unmapped: &UnmappedApps) -> Result<Option<f64>> {
+        self.warn_apps_unsupported();
+        Ok(None)
+    }
+
+    fn get_system_sounds(&mut self) -> Result<Option<f64>> {
+        self.warn_apps_unsupported();
+        Ok(None)
+    }
+
+    fn get_device(&mut self, device: &str) -> Result<f64> {
+        Device::named(device)?.volume()
+    }
+
+    fn get_device_mute(&mut self, device: &str) -> Result<bool> {
+        Device::named(device)?.muted()
+    }
+
+    fn devices(&mut self) -> Result<Vec<String>> {
+        Ok(output_devices()?
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect())
+    }
+
+    fn focused_pid(&mut self) -> Result<Option<u32>> {
+        Ok(None)
+    }
+
+    fn focused_title(&mut self) -> Result<Option<String>> {
+        self.warn_apps_unsupported();
+        Ok(None)
+    }
+
+    fn sessions(&mut self) -> Result<Vec<AudioSession>> {
+        self.warn_apps_unsupported();
+        Ok(Vec::new())
+    }
+}
+
+/// Lists every device with at least one output stream, with its name.
+fn output_devices() -> Result<Vec<(String, AudioObjectID)>> {
+    let devices: Vec<AudioObjectID> = get_property_list(
+        kAudioObjectSystemObject as AudioObjectID,
+        address(
+            kAudioHardwarePropertyDevices,
+            kAudioObjectPropertyScopeGlobal,
+            kAudioObjectPropertyElementMain,
+        ),
+    )?;
+
+    let mut outputs = Vec::new();
+    for device in devices {
+        let streams = address(
+            kAudioDevicePropertyStreams,
+            kAudioObjectPropertyScopeOutput,
+            kAudioObjectPropertyElementMain,
+        );
+        if property_size(device, streams)? == 0 {
+            continue;
+        }
+
+        let name = address(
+            kAudioObjectPropertyName,
+            kAudioObjectPropertyScopeGlobal,
+            kAudioObjectPropertyElementMain,
+        );
+        let Some(name) = get_property::<Option<NonNull<CFString>>>(device, name)? else {
+            continue;
+        };
+        // The caller owns the returned string
+        let name = unsafe { CFRetained::from_raw(name) };
+        outputs.push((name.to_string(), device));
+    }
+    Ok(outputs)
+}
+
+fn address(
+    selector: AudioObjectPropertySelector,
+    scope: AudioObjectPropertyScope,
+    element: u32,
+) -> AudioObjectPropertyAddress {
+    AudioObjectPropertyAddress {
+        mSelector: selector,
+        mScope: scope,
+        mElement: element,
+    }
+}
+
+fn has_property(object: AudioObjectID, address: AudioObjectPropertyAddress) -> bool {
+    unsafe { AudioObjectHasProperty(object, NonNull::from(&address)) }
+}
+
+/// Reads a fixed-size property value.
+fn get_property<T: Copy + Default>(
+    object: AudioObjectID,
+    address: AudioObjectPropertyAddress,
+) -> Result<T> {
+    let mut value = T::default();
+    let mut size = size_of::<T>() as u32;
+    let status = unsafe {
+        AudioObjectGetPropertyData(
+            object,
+            NonNull::from(&address),
+            0,
+            std::ptr::null(),
+            NonNull::from(&mut size),
+            NonNull::from(&mut value).cast::<c_void>(),
+        )
+    };
+    match status {
+        0 => Ok(value),
+        status => Err(anyhow!(
+            "CoreAudio property read failed (OSStatus {})",
+            status
+        )),
+    }
+}
+
+/// Returns the size in bytes of a property value.
+fn property_size(object: AudioObjectID, address: AudioObjectPropertyAddress) -> Result<u32> {
+    let mut size = 0;
+    let status = unsafe {
+        AudioObjectGetPropertyDataSize(
+            object,
+            NonNull::from(&address),
+            0,
+            std::ptr::null(),
+            NonNull::from(&mut size),
+        )
+    };
+    match status {
+        0 => Ok(size),
+        status => Err(anyhow!(
+            "CoreAudio property size read failed (OSStatus {})",
+            status
+        )),
+    }
+}
+
+/// Reads a property value made of a variable number of fixed-size items.
+fn get_property_list<T: Copy + Default>(
+    object: AudioObjectID,
+    address: AudioObjectPropertyAddress,
+) -> Result<Vec<T>> {
+    let mut size = property_size(object, address)?;
+    let mut values = vec![T::default(); size as usize / size_of::<T>()];
+    if values.is_empty() {
+        return Ok(values);
+    }
+    let status = unsafe {
+        AudioObjectGetPropertyData(
+            object,
+            NonNull::from(&address),
+            0,
+            std::ptr::null(),
+            NonNull::from(&mut size),
+            NonNull::from(values.as_mut_slice()).cast::<c_void>(),
+        )
+    };
+    match status {
+        0 => {
+            values.truncate(size as usize / size_of::<T>());
+            Ok(values)
+        }
+        status => Err(anyhow!(
+            "CoreAudio property read failed (OSStatus {})",
+            status
+        )),
+    }
+}
+
+/// Writes a fixed-size property value.
+fn set_property<T>(
+    object: AudioObjectID,
+    address: AudioObjectPropertyAddress,
+    value: &T,
+) -> Result<()> {
+    let status = unsafe {
+        AudioObjectSetPropertyData(
+            object,
+            NonNull::from(&address),
+            0,
+            std::ptr::null(),
+            size_of::<T>() as u32,
+            NonNull::from(value).cast::<c_void>(),
+        )
+    };
+    match status {
+        0 => Ok(()),
+        status => Err(anyhow!(
+            "CoreAudio property write failed (OSStatus {})",
+            status
+        )),
+    }
+}
@@ -0,0 +1,156 @@
+use super::{AudioSession, VolumeBackend};
+use crate::matcher::{AppMatcher, UnmappedApps};
+use anyhow::Result;
+use log::info;
+
+/// Backend wrapper that logs every change instead of applying it.
+///
+/// Reads are still forwarded to the wrapped backend, so features that depend on current
+/// levels (such as ramps) behave as they would for real.
+pub struct DryRunBackend {
+    inner: Box<dyn VolumeBackend>,
+}
+
+impl DryRunBackend {
+    pub fn new(inner: Box<dyn VolumeBackend>) -> Self {
+        DryRunBackend { inner }
+    }
+}
+
+impl VolumeBackend for DryRunBackend {
+    fn set_master(&mut self, volume: f64) -> Result<()> {
+        info!("[dry run] Set master volume to {}", volume);
+        Ok(())
+    }
+
+    fn set_master_mute(&mut self, mute: bool) -> Result<()> {
+        info!("[dry run] Set master mute to {}", mute);
+        Ok(())
+    }
+
+    fn set_microphone(&mut self, volume: f64) -> Result<()> {
+        info!("[dry run] Set microphone volume to {}", volume);
+        Ok(())
+    }
+
+    fn set_microphone_mute(&mut self, mute: bool) -> Result<()> {
+        info!("[dry run] Set microphone mute to {}", mute);
+        Ok(())
+    }
+
+    fn set_current_app(&mut self, volume: f64) -> Result<()> {
+        info!("[dry run] Set focused app volume to {}", volume);
+        Ok(())
+    }
+
+    fn set_current_app_mute(&mut self, mute: bool) -> Result<()> {
+        info!("[dry run] Set focused app mute to {}", mute);
+        Ok(())
+    }
+
+    fn set_app(&mut self, app: &AppMatcher, volume: f64) -> Result<()> {
+        info!("[dry run] Set {} volume to {}", app, volume);
+        Ok(())
+    }
+
+    fn set_app_mute(&mut self, app: &AppMatcher, mute: bool) -> Result<()> {
+        info!("[dry run] Set {} mute to {}", app, mute);
+        Ok(())
+    }
+
+    fn set_unmapped(&mut self, volume: f64, unmapped: &UnmappedApps) -> Result<()> {
+        info!(
+            "[dry run] Set unmapped apps ({:?}) volume to {}",
+            unmapped, volume
+        );
+        Ok(())
+    }
+
+    fn set_unmapped_mute(&mut self, mute: bool, unmapped: &UnmappedApps) -> Result<()> {
+        info!(
+            "[dry run] Set unmapped apps ({:?}) mute to {}",
+            unmapped, mute
+        );
+        Ok(())
+    }
+
+    fn set_system_sounds(&mut self, volume: f64) -> Result<()> {
+        info!("[dry run] Set system sounds volume to {}", volume);
+        Ok(())
+    }
+
+    fn set_system_sounds_mute(&mut self, mute: bool) -> Result<()> {
+        info!("[dry run] Set system sounds mute to {}", mute);
+        Ok(())
+    }
+
+    fn set_device(&mut self, device: &str, volume: f64) -> Result<()> {
+        info!("[dry run] Set device {} volume to {}", device, volume);
+        Ok(())
+    }
+
+    fn set_device_mute(&mut self, device: &str, mute: bool) -> Result<()> {
+        info!("[dry run] Set device {} mute to {}", device, mute);
+        Ok(())
+    }
+
+    fn get_master(&mut self) -> Result<f64> {
+        self.inner.get_master()
+    }
+
+    fn get_microphone(&mut self) -> Result<f64> {
+        self.inner.get_microphone()
+    }
+
+    fn get_master_mute(&mut self) -> Result<bool> {
+        self.inner.get_master_mute()
+    }
+
+    fn get_microphone_mute(&mut self) -> Result<bool> {
+        self.inner.get_microphone_mute()
+    }
+
+    fn get_current_app(&mut self) -> Result<Option<f64>> {
+        self.inner.get_current_app()
+    }
+
+    fn get_app(&mut self, app: &AppMatcher) -> Result<Option<f64>> {
+        self.inner.get_app(app)
+    }
+
+    fn get_unmapped(&mut self, unmapped: &UnmappedApps) -> Result<Option<f64>> {
+        self.inner.get_unmapped(unmapped)
+    }
+
+    fn get_system_sounds(&mut self) -> Result<Option<f64>> {
+        self.inner.get_system_sounds()
+    }
+
+    fn get_device(&mut self, device: &str) -> Result<f64> {
+        self.inner.get_device(device)
+    }
+
+    fn get_device_mute(&mut self, device: &str) -> Result<bool> {
+        self.inner.get_device_mute(device)
+    }
+
+    fn devices(&mut self) -> Result<Vec<String>> {
+        self.inner.devices()
+    }
+
+    fn focused_pid(&mut self) -> Result<Option<u32>> {
+        self.inner.focused_pid()
+    }
+
+    fn focused_title(&mut self) -> Result<Option<String>> {
+        self.inner.focused_title()
+    }
+
+    fn sessions(&mut self) -> Result<Vec<AudioSession>> {
+        self.inner.sessions()
+    }
+
+    fn forget_applied(&mut self) {
+        self.inner.forget_applied();
+    }
+}
@@ -0,0 +1,114 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// How long looked-up process names and paths are reused before being looked up again.
+pub const PROCESS_CACHE_TTL: Duration = Duration::from_secs(1);
+
+/// Names and executable paths of processes by PID, so that every slider tick doesn't open
+/// every process that owns an audio session again.
+///
+/// The whole cache is dropped once it is older than its TTL, rather than each entry, so a
+/// process that exits and has its PID reused is reported under the old name for at most
+/// one TTL. Failed lookups are cached too, as they fail again until the process is gone.
+pub struct ProcessCache {
+    ttl: Duration,
+    filled_since: Option<Instant>,
+    names: HashMap<u32, Option<String>>,
+    paths: HashMap<u32, Option<String>>,
+}
+
+impl Default for ProcessCache {
+    fn default() -> Self {
+        ProcessCache::new(PROCESS_CACHE_TTL)
+    }
+}
+
+impl ProcessCache {
+    pub fn new(ttl: Duration) -> Self {
+        ProcessCache {
+            ttl,
+            filled_since: None,
+            names: HashMap::new(),
+            paths: HashMap::new(),
+        }
+    }
+
+    /// Returns the name of process `pid` at `now`, calling `lookup` unless it was looked
+    /// up within the TTL.
+    pub fn name(
+        &mut self,
+        pid: u32,
+        now: Instant,
+        lookup: impl FnOnce(u32) -> Option<String>,
+    ) -> Option<String> {
+        self.expire(now);
+        self.names.entry(pid).or_insert_with(|| lookup(pid)).clone()
+    }
+
+    /// Returns the executable path of process `pid` at `now`, calling `lookup` unless it
+    /// was looked up within the TTL.
+    pub fn path(
+        &mut self,
+        pid: u32,
+        now: Instant,
+        lookup: impl FnOnce(u32) -> Option<String>,
+    ) -> Option<String> {
+        self.expire(now);
+        self.paths.entry(pid).or_insert_with(|| lookup(pid)).clone()
+    }
+
+    /// Empties the cache if it was filled more than a TTL before `now`.
+    fn expire(&mut self, now: Instant) {
+        if self
+            .filled_since
+            .is_none_or(|since| now.duration_since(since) >= self.ttl)
+        {
+            self.names.clear();
+            self.paths.clear();
+            self.filled_since = Some(now);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TTL: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn names_are_looked_up_once_per_ttl() {
+        let mut cache = ProcessCache::new(TTL);
+        let start = Instant::now();
+        let lookups = std::cell::Cell::new(0);
+        let lookup = |_| {
+            lookups.set(lookups.get() + 1);
+            Some("game.exe".to_string())
+        };
+
+        for ms in [0, 300, 999] {
+            let now = start + Duration::from_millis(ms);
+            assert_eq!(cache.name(42, now, lookup).as_deref(), Some("game.exe"));
+        }
+        assert_eq!(lookups.get(), 1);
+
+        cache.name(42, start + TTL, lookup);
+        assert_eq!(lookups.get(), 2);
+    }
+
+    #[test]
+    fn reused_pids_are_looked_up_again_after_the_ttl() {
+        let mut cache = ProcessCache::new(TTL);
+        let start = Instant::now();
+        cache.name(42, start, |_| Some("old.exe".to_string()));
+        cache.path(42, start, |_| Some(r"C:\old.exe".to_string()));
+
+        let later = start + TTL;
+        let name = cache.name(42, later, |_| Some("new.exe".to_string()));
+        let path = cache.path(42, later, |_| Some(r"C:\new.exe".to_string()));
+        assert_eq!(name.as_deref(), Some("new.exe"));
+        assert_eq!(path.as_deref(), Some(r"C:\new.exe"));
+    }
+}
@@ -0,0 +1,474 @@
+use super::{AudioSession, VolumeBackend, is_device};
+use crate::matcher::{AppMatcher, UnmappedApps};
+use anyhow::{Result, anyhow};
+use libpulse_binding::{
+    callbacks::ListResult,
+    context::{Context, FlagSet, State},
+    mainloop::standard::{IterateResult, Mainloop},
+    operation::{Operation, State as OperationState},
+    volume::{ChannelVolumes, Volume},
+};
+use log::{trace, warn};
+use std::{cell::RefCell, rc::Rc};
+
+/// Special sink name that the server resolves to the current default output.
+const DEFAULT_SINK: &str = "@DEFAULT_SINK@";
+/// Special source name that the server resolves to the current default input.
+const DEFAULT_SOURCE: &str = "@DEFAULT_SOURCE@";
+/// Stream property holding the base name of the executable that owns the stream.
+const PROCESS_BINARY: &str = "application.process.binary";
+/// Stream property holding the ID of the process that owns the stream.
+const PROCESS_ID: &str = "application.process.id";
+
+/// Volume backend for Linux, built on a PulseAudio (or PipeWire-Pulse) server.
+///
+/// Apps are matched by the `application.process.binary` property of their playback
+/// streams, which holds the same executable name Windows reports for a session.
+pub struct PulseAudioBackend {
+    // Declared before `mainloop` so the context is dropped first
+    context: Context,
+    mainloop: Mainloop,
+    warned_current_app: bool,
+    warned_system_sounds: bool,
+}
+
+/// A playback stream (sink input) reported by the server.
+struct SinkInput {
+    index: u32,
+    binary: String,
+    channels: u8,
+    volume: f64,
+}
+
+/// An output (sink) or input (source) device reported by the server.
+struct Device {
+    index: u32,
+    channels: u8,
+    volume: f64,
+    muted: bool,
+}
+
+impl PulseAudioBackend {
+    /// Connects to the PulseAudio server and waits until it is ready.
+    pub fn new() -> Result<Self> {
+        let mainloop =
+            Mainloop::new().ok_or_else(|| anyhow!("Failed to create PulseAudio mainloop"))?;
+        let mut context = Context::new(&mainloop, "gain")
+            .ok_or_else(|| anyhow!("Failed to create PulseAudio context"))?;
+        context.connect(None, FlagSet::NOFLAGS, None)?;
+
+        let mut backend = PulseAudioBackend {
+            context,
+            mainloop,
+            warned_current_app: false,
+            warned_system_sounds: false,
+        };
+
+        loop {
+            match backend.context.get_state() {
+                State::Ready => break,
+                State::Failed | State::Terminated => {
+                    return Err(anyhow!("Failed to connect to the PulseAudio server"));
+                }
+                _ => backend.iterate()?,
+            }
+        }
+
+        Ok(backend)
+    }
+
+    fn iterate(&mut self) -> Result<()> {
+        match self.mainloop.iterate(true) {
+            IterateResult::Success(_) => Ok(()),
+            IterateResult::Quit(_) => Err(anyhow!("PulseAudio mainloop quit")),
+            IterateResult::Err(e) => Err(anyhow!("PulseAudio mainloop failed: {}", e)),
+        }
+    }
+
+    /// Runs the mainloop until `operation` has completed.
+    fn wait<T: ?Sized>(&mut self, operation: Operation<T>) -> Result<()> {
+        while operation.get_state() == OperationState::Running {
+            self.iterate()?;
+        }
+        Ok(())
+    }
+
+    fn sink_inputs(&mut self) -> Result<Vec<SinkInput>> {
+        let inputs = Rc::new(RefCell::new(Vec::new()));
+        let collected = inputs.clone();
+
+        let operation = self
+            .context
+            .introspect()
+            .get_sink_input_info_list(move |result| {
+                if let ListResult::Item(info) = result
+                    && let Some(binary) = info.proplist.get_str(PROCESS_BINARY)
+                {
+                    collected.borrow_mut().push(SinkInput {
+                        index: info.index,
+                        binary,
+                        channels: info.volume.len(),
+                        volume: scalar(&info.volume),
+                    });
+                }
+            });
+        self.wait(operation)?;
+
+        Ok(inputs.take())
+    }
+
+    /// Lists every playback stream, including those without a process binary property,
+    /// which apps can't be matched against.
+    fn all_sink_inputs(&mut self) -> Result<Vec<AudioSession>> {
+        let sessions = Rc::new(RefCell::new(Vec::new()));
+        let collected = sessions.clone();
+
+        let operation = self
+            .context
+            .introspect()
+            .get_sink_input_info_list(move |result| {
+                if let ListResult::Item(info) = result {
+                    collected.borrow_mut().push(AudioSession {
+                        pid: info
+                            .proplist
+                            .get_str(PROCESS_ID)
+                            .and_then(|pid| pid.parse().ok())
+                            .unwrap_or(0),
+                        name: info.proplist.get_str(PROCESS_BINARY),
+                    });
+                }
+            });
+        self.wait(operation)?;
+
+        Ok(sessions.take())
+    }
+
+    fn default_sink(&mut self) -> Result<Device> {
+        let sink = Rc::new(RefCell::new(None));
+        let found = sink.clone();
+
+        let operation =
+            self.context
+                .introspect()
+                .get_sink_info_by_name(DEFAULT_SINK, move |result| {
+                    if let ListResult::Item(info) = result {
+                        *found.borrow_mut() = Some(Device {
+                            index: info.index,
+                            channels: info.volume.len(),
+                            volume: scalar(&info.volume),
+                            muted: info.mute,
+                        });
+                    }
+                });
+        self.wait(operation)?;
+
+        sink.take()
+            .ok_or_else(|| anyhow!("No default PulseAudio sink"))
+    }
+
+    fn default_source(&mut self) -> Result<Device> {
+        let source = Rc::new(RefCell::new(None));
+        let found = source.clone();
+
+        let operation =
+            self.context
+                .introspect()
+                .get_source_info_by_name(DEFAULT_SOURCE, move |result| {
+                    if let ListResult::Item(info) = result {
+                        *found.borrow_mut() = Some(Device {
+                            index: info.index,
+                            channels: info.volume.len(),
+                            volume: scalar(&info.volume),
+                            muted: info.mute,
+                        });
+                    }
+                });
+        self.wait(operation)?;
+
+        source
+            .take()
+            .ok_or_else(|| anyhow!("No default PulseAudio source"))
+    }
+
+    /// Lists every sink with its description, the human-readable name device targets are
+    /// matched against.
+    fn sinks(&mut self) -> Result<Vec<(String, Device)>> {
+        let sinks = Rc::new(RefCell::new(Vec::new()));
+        let collected = sinks.clone();
+
+        let operation = self.context.introspect().get_sink_info_list(move |result| {
+            if let ListResult::Item(info) = result
+                && let Some(description) = &info.description
+            {
+                collected.borrow_mut().push((
+                    description.to_string(),
+                    Device {
+                        index: info.index,
+                        channels: info.volume.len(),
+                        volume: scalar(&info.volume),
+                        muted: info.mute,
+                    },
+                ));
+            }
+        });
+        self.wait(operation)?;
+
+        Ok(sinks.take())
+    }
+
+    fn sink_named(&mut self, name: &str) -> Result<Device> {
+        self.sinks()?
+            .into_iter()
+            .find(|(description, _)| is_device(description, name))
+            .map(|(_, sink)| sink)
+            .ok_or_else(|| anyhow!("No output device named {}", name))
+    }
+
+    fn get_sink_input<F>(&mut self, mut filter: F) -> Result<Option<f64>>
+    where
+        F: FnMut(&str) -> bool,
+    {
+        Ok(self
+            .sink_inputs()?
+            .into_iter()
+            .find(|input| filter(&input.binary))
+            .map(|input| input.volume))
+    }
+
+    fn set_sink_inputs<F>(&mut self, volume: f64, mut filter: F) -> Result<()>
+    where
+        F: FnMut(&str) -> bool,
+    {
+        for input in self.sink_inputs()? {
+            if filter(&input.binary) {
+                let volumes = channel_volumes(input.channels, volume);
+                let operation =
+                    self.context
+                        .introspect()
+                        .set_sink_input_volume(input.index, &volumes, None);
+                self.wait(operation)?;
+                let operation =
+                    self.context
+                        .introspect()
+                        .set_sink_input_mute(input.index, volume <= 0.0, None);
+                self.wait(operation)?;
+                trace!("Set {} volume to {}", input.binary, volume);
+            }
+        }
+        Ok(())
+    }
+
+    fn set_sink_inputs_mute<F>(&mut self, mute: bool, mut filter: F) -> Result<()>
+    where
+        F: FnMut(&str) -> bool,
+    {
+        for input in self.sink_inputs()? {
+            if filter(&input.binary) {
+                let operation =
+                    self.context
+                        .introspect()
+                        .set_sink_input_mute(input.index, mute, None);
+                self.wait(operation)?;
+                trace!("Set {} mute to {}", input.binary, mute);
+            }
+        }
+        Ok(())
+    }
+
+    fn warn_current_app_unsupported(&mut self) {
+        if !self.warned_current_app {
+            warn!(
+                "The PulseAudio backend can't tell which application is focused; ignoring `current`, `foreground` and `window_title` targets"
+            );
+            self.warned_current_app = true;
+        }
+    }
+
+    fn warn_system_sounds_unsupported(&mut self) {
+        if !self.warned_system_sounds {
+            warn!("System sounds sessions only exist on Windows; ignoring `system_sounds` targets");
+            self.warned_system_sounds = true;
+        }
+    }
+}
+
+impl VolumeBackend for PulseAudioBackend {
+    fn set_master(&mut self, volume: f64) -> Result<()> {
+        let volumes = channel_volumes(self.default_sink()?.channels, volume);
+        let operation =
+            self.context
+                .introspect()
+                .set_sink_volume_by_name(DEFAULT_SINK, &volumes, None);
+        self.wait(operation)?;
+        self.set_master_mute(volume <= 0.0)?;
+        trace!("Set master volume to {}", volume);
+        Ok(())
+    }
+
+    fn set_master_mute(&mut self, mute: bool) -> Result<()> {
+        let operation = self
+            .context
+            .introspect()
+            .set_sink_mute_by_name(DEFAULT_SINK, mute, None);
+        self.wait(operation)?;
+        trace!("Set master mute to {}", mute);
+        Ok(())
+    }
+
+    fn set_microphone(&mut self, volume: f64) -> Result<()> {
+        let volumes = channel_volumes(self.default_source()?.channels, volume);
+        let operation =
+            self.context
+                .introspect()
+                .set_source_volume_by_name(DEFAULT_SOURCE, &volumes, None);
+        self.wait(operation)?;
+        self.set_microphone_mute(volume <= 0.0)?;
+        trace!("Set microphone volume to {}", volume);
+        Ok(())
+    }
+
+    fn set_microphone_mute(&mut self, mute: bool) -> Result<()> {
+        let operation =
+            self.context
+                .introspect()
+                .set_source_mute_by_name(DEFAULT_SOURCE, mute, None);
+        self.wait(operation)?;
+        trace!("Set microphone mute to {}", mute);
+        Ok(())
+    }
+
+    fn set_current_app(&mut self, _volume: f64) -> Result<()> {
+        self.warn_current_app_unsupported();
+        Ok(())
+    }
+
+    fn set_current_app_mute(&mut self, _mute: bool) -> Result<()> {
+        self.warn_current_app_unsupported();
+        Ok(())
+    }
+
+    fn set_app(&mut self, app: &AppMatcher, volume: f64) -> Result<()> {
+        self.set_sink_inputs(volume, |name| app.matches(name))
+    }
+
+    fn set_app_mute(&mut self, app: &AppMatcher, mute: bool) -> Result<()> {
+        self.set_sink_inputs_mute(mute, |name| app.matches(name))
+    }
+
+    fn set_unmapped(&mut self, volume: f64, unmapped: &UnmappedApps) -> Result<()> {
+        self.set_sink_inputs(volume, |name| unmapped.matches(name))
+    }
+
+    fn set_unmapped_mute(&mut self, mute: bool, unmapped: &UnmappedApps) -> Result<()> {
+        self.set_sink_inputs_mute(mute, |name| unmapped.matches(name))
+    }
+
+    fn set_system_sounds(&mut self, _volume: f64) -> Result<()> {
+        self.warn_system_sounds_unsupported();
+        Ok(())
+    }
+
+    fn set_system_sounds_mute(&mut self, _mute: bool) -> Result<()> {
+        self.warn_system_sounds_unsupported();
+        Ok(())
+    }
+
+    fn set_device(&mut self, device: &str, volume: f64) -> Result<()> {
+        let sink = self.sink_named(device)?;
+        let volumes = channel_volumes(sink.channels, volume);
+        let operation = self
+            .context
+            .introspect()
+            .set_sink_volume_by_index(sink.index, &volumes, None);
+        self.wait(operation)?;
+        self.set_device_mute(device, volume <= 0.0)?;
+        trace!("Set device {} volume to {}", device, volume);
+        Ok(())
+    }
+
+    fn set_device_mute(&mut self, device: &str, mute: bool) -> Result<()> {
+        let sink = self.sink_named(device)?;
+        let operation = self
+            .context
+            .introspect()
+            .set_sink_mute_by_index(sink.index, mute, None);
+        self.wait(operation)?;
+        trace!("Set device {} mute to {}", device, mute);
+        Ok(())
+    }
+
+    fn get_master(&mut self) -> Result<f64> {
+        Ok(self.default_sink()?.volume)
+    }
+
+    fn get_microphone(&mut self) -> Result<f64> {
+        Ok(self.default_source()?.volume)
+    }
+
+    fn get_master_mute(&mut self) -> Result<bool> {
+        Ok(self.default_sink()?.muted)
+    }
+
+    fn get_microphone_mute(&mut self) -> Result<bool> {
+        Ok(self.default_source()?.muted)
+    }
+
+    fn get_current_app(&mut self) -> Result<Option<f64>> {
+        self.warn_current_app_unsupported();
+        Ok(None)
+    }
+
+    fn get_app(&mut self, app: &AppMatcher) -> Result<Option<f64>> {
+        self.get_sink_input(|name| app.matches(name))
+    }
+
+    fn get_unmapped(&mut self, unmapped: &UnmappedApps) -> Result<Option<f64>> {
+        self.get_sink_input(|name| unmapped.matches(name))
+    }
+
+    fn get_system_sounds(&mut self) -> Result<Option<f64>> {
+        self.warn_system_sounds_unsupported();
+        Ok(None)
+    }
+
+    fn get_device(&mut self, device: &str) -> Result<f64> {
+        Ok(self.sink_named(device)?.volume)
+    }
+
+    fn get_device_mute(&mut self, device: &str) -> Result<bool> {
+        Ok(self.sink_named(device)?.muted)
+    }
+
+    fn devices(&mut self) -> Result<Vec<String>> {
+        Ok(self.sinks()?.into_iter().map(|(name, _)| name).collect())
+    }
+
+    fn focused_pid(&mut self) -> Result<Option<u32>> {
+        self.warn_current_app_unsupported();
+        Ok(None)
+    }
+
+    fn focused_title(&mut self) -> Result<Option<String>> {
+        self.warn_current_app_unsupported();
+        Ok(None)
+    }
+
+    fn sessions(&mut self) -> Result<Vec<AudioSession>> {
+        self.all_sink_inputs()
+    }
+}
+
+/// Builds a per-channel volume with every channel set to `volume` (0.0 to 1.0), where 1.0
+/// is the server's nominal 100%.
+fn channel_volumes(channels: u8, volume: f64) -> ChannelVolumes {
+    let volume = volume.clamp(0.0, 1.0);
+    let mut volumes = ChannelVolumes::default();
+    volumes.set(channels, Volume((Volume::NORMAL.0 as f64 * volume) as u32));
+    volumes
+}
+
+/// Converts a per-channel volume into a single scalar (0.0 to 1.0+), where 1.0 is the
+/// server's nominal 100%.
+fn scalar(volumes: &ChannelVolumes) -> f64 {
+    volumes.avg().0 as f64 / Volume::NORMAL.0 as f64
+}
@@ -0,0 +1,240 @@
+use super::{AudioSession, VolumeBackend};
+use crate::matcher::{AppKey, AppMatcher, UnmappedApps, UnmappedKey};
+use anyhow::Result;
+use log::trace;
+use std::{
+    collections::{HashMap, HashSet},
+    ops::DerefMut,
+};
+
+/// Difference below which a volume counts as the one already applied.
+const UNCHANGED_EPSILON: f64 = 1e-4;
+
+/// What a volume was applied to, so changing the target (such as focusing another app)
+/// still applies the volume.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Applied {
+    Master,
+    Microphone,
+    SystemSounds,
+    /// The focused app, by process ID.
+    CurrentApp(u32),
+    /// Apps matched by a matcher, by its pattern and mode.
+    App(AppKey),
+    /// An `unmapped` set, by its lists.
+    Unmapped(UnmappedKey),
+    Device(String),
+}
+
+/// Backend wrapper that skips setting a volume that equals the last one set on the same
+/// target, such as when the firmware re-sends a position or a focus change re-applies the
+/// same level, so the audio system isn't called for nothing.
+///
+/// Mute calls are always forwarded and forget the target's last volume, since setting the
+/// volume is what unmutes it. App, `unmapped` and focused app volumes are forgotten
+/// whenever the audio sessions listed by [`VolumeBackend::sessions`] change, so sessions
+/// that appeared since are still set.
+pub struct SkipUnchanged<B> {
+    inner: B,
+    applied: HashMap<Applied, f64>,
+    sessions: Option<HashSet<(u32, Option<String>)>>,
+}
+
+impl<B: DerefMut<Target: VolumeBackend>> SkipUnchanged<B> {
+    pub fn new(inner: B) -> Self {
+        SkipUnchanged {
+            inner,
+            applied: HashMap::new(),
+            sessions: None,
+        }
+    }
+
+    /// Calls `set` unless `volume` is the last volume set on `target`, and remembers it
+    /// if the call succeeds.
+    fn set(
+        &mut self,
+        target: Applied,
+        volume: f64,
+        set: impl FnOnce(&mut B::Target) -> Result<()>,
+    ) -> Result<()> {
+        if self
+            .applied
+            .get(&target)
+            .is_some_and(|&last| (last - volume).abs() < UNCHANGED_EPSILON)
+        {
+            trace!("{:?} is already at volume {}, skipping", target, volume);
+            return Ok(());
+        }
+        // Forgotten first, so a failed call is retried next time
+        self.applied.remove(&target);
+        set(&mut self.inner)?;
+        self.applied.insert(target, volume);
+        Ok(())
+    }
+
+    /// Forwards a mute call, after forgetting the last volume of `target`.
+    fn mute(
+        &mut self,
+        target: Applied,
+        mute: impl FnOnce(&mut B::Target) -> Result<()>,
+    ) -> Result<()> {
+        self.applied.remove(&target);
+        mute(&mut self.inner)
+    }
+}
+
+impl<B: DerefMut<Target: VolumeBackend>> VolumeBackend for SkipUnchanged<B> {
+    fn set_master(&mut self, volume: f64) -> Result<()> {
+        self.set(Applied::Master, volume, |inner| inner.set_master(volume))
+    }
+
+    fn set_master_mute(&mut self, mute: bool) -> Result<()> {
+        self.mute(Applied::Master, |inner| inner.set_master_mute(mute))
+    }
+
+    fn set_microphone(&mut self, volume: f64) -> Result<()> {
+        self.set(Applied::Microphone, volume, |inner| {
+            inner.set_microphone(volume)
+        })
+    }
+
+    fn set_microphone_mute(&mut self, mute: bool) -> Result<()> {
+        self.mute(Applied::Microphone, |inner| inner.set_microphone_mute(mute))
+    }
+
+    fn set_current_app(&mut self, volume: f64) -> Result<()> {
+        match self.inner.focused_pid()? {
+            Some(pid) => self.set(Applied::CurrentApp(pid), volume, |inner| {
+                inner.set_current_app(volume)
+            }),
+            None => self.inner.set_current_app(volume),
+        }
+    }
+
+    fn set_current_app_mute(&mut self, mute: bool) -> Result<()> {
+        self.applied
+            .retain(|target, _| !matches!(target, Applied::CurrentApp(_)));
+        self.inner.set_current_app_mute(mute)
+    }
+
+    fn set_app(&mut self, app: &AppMatcher, volume: f64) -> Result<()> {
+        self.set(Applied::App(app.key()), volume, |inner| {
+            inner.set_app(app, volume)
+        })
+    }
+
+    fn set_app_mute(&mut self, app: &AppMatcher, mute: bool) -> Result<()> {
+        self.mute(Applied::App(app.key()), |inner| {
+            inner.set_app_mute(app, mute)
+        })
+    }
+
+    fn set_unmapped(&mut self, volume: f64, unmapped: &UnmappedApps) -> Result<()> {
+        self.set(Applied::Unmapped(unmapped.key()), volume, |inner| {
+            inner.set_unmapped(volume, unmapped)
+        })
+    }
+
+    fn set_unmapped_mute(&mut self, mute: bool, unmapped: &UnmappedApps) -> Result<()> {
+        self.mute(Applied::Unmapped(unmapped.key()), |inner| {
+            inner.set_unmapped_mute(mute, unmapped)
+        })
+    }
+
+    fn set_system_sounds(&mut self, volume: f64) -> Result<()> {
+        self.set(Applied::SystemSounds, volume, |inner| {
+            inner.set_system_sounds(volume)
+        })
+    }
+
+    fn set_system_sounds_mute(&mut self, mute: bool) -> Result<()> {
+        self.mute(Applied::SystemSounds, |inner| {
+            inner.set_system_sounds_mute(mute)
+        })
+    }
+
+    fn set_device(&mut self, device: &str, volume: f64) -> Result<()> {
+        let target = Applied::Device(device.to_lowercase());
+        self.set(target, volume, |inner| inner.set_device(device, volume))
+    }
+
+    fn set_device_mute(&mut self, device: &str, mute: bool) -> Result<()> {
+        let target = Applied::Device(device.to_lowercase());
+        self.mute(target, |inner| inner.set_device_mute(device, mute))
+    }
+
+    fn get_master(&mut self) -> Result<f64> {
+        self.inner.get_master()
+    }
+
+    fn get_microphone(&mut self) -> Result<f64> {
+        self.inner.get_microphone()
+    }
+
+    fn get_master_mute(&mut self) -> Result<bool> {
+        self.inner.get_master_mute()
+    }
+
+    fn get_microphone_mute(&mut self) -> Result<bool> {
+        self.inner.get_microphone_mute()
+    }
+
+    fn get_current_app(&mut self) -> Result<Option<f64>> {
+        self.inner.get_current_app()
+    }
+
+    fn get_app(&mut self, app: &AppMatcher) -> Result<Option<f64>> {
+        self.inner.get_app(app)
+    }
+
+    fn get_unmapped(&mut self, unmapped: &UnmappedApps) -> Result<Option<f64>> {
+        self.inner.get_unmapped(unmapped)
+    }
+
+    fn get_system_sounds(&mut self) -> Result<Option<f64>> {
+        self.inner.get_system_sounds()
+    }
+
+    fn get_device(&mut self, device: &str) -> Result<f64> {
+        self.inner.get_device(device)
+    }
+
+    fn get_device_mute(&mut self, device: &str) -> Result<bool> {
+        self.inner.get_device_mute(device)
+    }
+
+    fn devices(&mut self) -> Result<Vec<String>> {
+        self.inner.devices()
+    }
+
+    fn focused_pid(&mut self) -> Result<Option<u32>> {
+        self.inner.focused_pid()
+    }
+
+    fn focused_title(&mut self) -> Result<Option<String>> {
+        self.inner.focused_title()
+    }
+
+    fn sessions(&mut self) -> Result<Vec<AudioSession>> {
+        let sessions = self.inner.sessions()?;
+        let current = sessions
+            .iter()
+            .map(|session| (session.pid, session.name.clone()))
+            .collect();
+        if self.sessions.as_ref() != Some(&current) {
+            self.applied.retain(|target, _| {
+                !matches!(
+                    target,
+                    Applied::App(_) | Applied::Unmapped(_) | Applied::CurrentApp(_)
+                )
+            });
+            self.sessions = Some(current);
+        }
+        Ok(sessions)
+    }
+
+    fn forget_applied(&mut self) {
+        self.applied.clear();
+        self.inner.forget_applied();
+    }
+}
@@ -0,0 +1,160 @@
+use super::{AudioSession, VolumeBackend};
+use crate::matcher::{AppMatcher, UnmappedApps};
+use anyhow::{Result, anyhow};
+use log::warn;
+
+/// Stand-in backend for platforms (or builds) without a volume backend. It warns once and
+/// ignores every change, so the serial and config layers can still be run and tested.
+#[derive(Default)]
+pub struct UnsupportedBackend {
+    warned: bool,
+}
+
+impl UnsupportedBackend {
+    fn warn(&mut self) {
+        if !self.warned {
+            warn!("gain has no volume backend for this platform or build; ignoring volume changes");
+            self.warned = true;
+        }
+    }
+
+    fn ignore(&mut self) -> Result<()> {
+        self.warn();
+        Ok(())
+    }
+
+    fn unknown(&mut self) -> Result<Option<f64>> {
+        self.warn();
+        Ok(None)
+    }
+}
+
+impl VolumeBackend for UnsupportedBackend {
+    fn set_master(&mut self, _volume: f64) -> Result<()> {
+        self.ignore()
+    }
+
+    fn set_master_mute(&mut self, _mute: bool) -> Result<()> {
+        self.ignore()
+    }
+
+    fn set_microphone(&mut self, _volume: f64) -> Result<()> {
+        self.ignore()
+    }
+
+    fn set_microphone_mute(&mut self, _mute: bool) -> Result<()> {
+        self.ignore()
+    }
+
+    fn set_current_app(&mut self, _volume: f64) -> Result<()> {
+        self.ignore()
+    }
+
+    fn set_current_app_mute(&mut self, _mute: bool) -> Result<()> {
+        self.ignore()
+    }
+
+    fn set_app(&mut self, _app: &AppMatcher, _volume: f64) -> Result<()> {
+        self.ignore()
+    }
+
+    fn set_app_mute(&mut self, _app: &AppMatcher, _mute: bool) -> Result<()> {
+        self.ignore()
+    }
+
+    fn set_unmapped(&mut self, _volume: f64, _unmapped: &UnmappedApps) -> Result<()> {
+        self.ignore()
+    }
+
+    fn set_unmapped_mute(&mut self, _mute: bool, _unmapped: &UnmappedApps) -> Result<()> {
+        self.ignore()
+    }
+
+    fn set_system_sounds(&mut self, _volume: f64) -> Result<()> {
+        self.ignore()
+    }
+
+    fn set_system_sounds_mute(&mut self, _mute: bool) -> Result<()> {
+        self.ignore()
+    }
+
+    fn set_device(&mut self, _device: &str, _volume: f64) -> Result<()> {
+        self.ignore()
+    }
+
+    fn set_device_mute(&mut self, _device: &str, _mute: bool) -> Result<()> {
+        self.ignore()
+    }
+
+    fn get_master(&mut self) -> Result<f64> {
+        self.warn();
+        Err(anyhow!("Master volume is not available on this platform"))
+    }
+
+    fn get_microphone(&mut self) -> Result<f64> {
+        self.warn();
+        Err(anyhow!(
+            "Microphone volume is not available on this platform"
+        ))
+    }
+
+    fn get_master_mute(&mut self) -> Result<bool> {
+        self.warn();
+        Err(anyhow!("Master mute is not available on this platform"))
+    }
+
+    fn get_microphone_mute(&mut self) -> Result<bool> {
+        self.warn();
+        Err(anyhow!("Microphone mute is not available on this platform"))
+    }
+
+    fn get_current_app(&mut self) -> Result<Option<f64>> {
+        self.unknown()
+    }
+
+    fn get_app(&mut self, _app: &AppMatcher) -> Result<Option<f64>> {
+        self.unknown()
+    }
+
+    fn get_unmapped(&mut self, _unmapped: &UnmappedApps) -> Result<Option<f64>> {
+        self.unknown()
+    }
+
+    fn get_system_sounds(&mut self) -> Result<Option<f64>> {
+        self.unknown()
+    }
+
+    fn get_device(&mut self, device: &str) -> Result<f64> {
+        self.warn();
+        Err(anyhow!(
+            "Device {} is not available on this platform",
+            device
+        ))
+    }
+
+    fn get_device_mute(&mut self, device: &str) -> Result<bool> {
+        self.warn();
+        Err(anyhow!(
+            "Device {} is not available on this platform",
+            device
+        ))
+    }
+
+    fn devices(&mut self) -> Result<Vec<String>> {
+        self.warn();
+        Ok(Vec::new())
+    }
+
+    fn focused_pid(&mut self) -> Result<Option<u32>> {
+        Ok(None)
+    }
+
+    fn focused_title(&mut self) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    fn sessions(&mut self) -> Result<Vec<AudioSession>> {
+        self.warn();
+        Ok(Vec::new())
+    }
+}
@@ -0,0 +1,986 @@
+use super::{AudioSession, VolumeBackend, is_device, processes::ProcessCache};
+use crate::matcher::{AppMatcher, UnmappedApps};
+use anyhow::{Context, Result, anyhow};
+use log::{debug, error, trace, warn};
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    os::windows::ffi::OsStringExt,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Instant,
+};
+use windows::{
+    Win32::Devices::FunctionDiscovery::PKEY_Device_FriendlyName,
+    Win32::Foundation::{CloseHandle, MAX_PATH, RPC_E_CHANGED_MODE, S_OK},
+    Win32::Media::Audio::Endpoints::IAudioEndpointVolume,
+    Win32::Media::Audio::{
+        DEVICE_STATE, DEVICE_STATE_ACTIVE, EDataFlow, ERole, IAudioSessionControl2,
+        IAudioSessionEnumerator, IAudioSessionManager2, IMMDevice, IMMDeviceEnumerator,
+        IMMNotificationClient, IMMNotificationClient_Impl, ISimpleAudioVolume, MMDeviceEnumerator,
+        eCapture, eConsole, eRender,
+    },
+    Win32::System::Com::{
+        CLSCTX_ALL, COINIT_MULTITHREADED, CoCreateInstance, CoInitializeEx, CoUninitialize,
+        STGM_READ,
+    },
+    Win32::System::ProcessStatus::{K32GetModuleBaseNameW, K32GetModuleFileNameExW},
+    Win32::System::Threading::{OpenProcess, PROCESS_QUERY_INFORMATION, PROCESS_VM_READ},
+    Win32::UI::Shell::PropertiesSystem::PROPERTYKEY,
+    Win32::UI::WindowsAndMessaging::{
+        GetForegroundWindow, GetWindowTextLengthW, GetWindowTextW, GetWindowThreadProcessId,
+    },
+    core::{Interface, PCWSTR, Result as WindowsResult, implement},
+};
+
+/// Volume backend for Windows, built on the WASAPI session and endpoint interfaces.
+pub struct WasapiBackend {
+    // Declared before `_com` so the cached interfaces are released before COM is shut down
+    state: AudioState,
+    _com: ComGuard,
+}
+
+/// Uninitializes COM on the calling thread when dropped, if this backend initialized it.
+struct ComGuard {
+    initialized: bool,
+}
+
+impl Drop for ComGuard {
+    fn drop(&mut self) {
+        if self.initialized {
+            unsafe { CoUninitialize() };
+        }
+    }
+}
+
+impl WasapiBackend {
+    /// Initializes the COM library for use by the calling thread and creates the device
+    /// enumerator. COM is uninitialized again when the backend is dropped.
+    ///
+    /// If the thread already joined a single-threaded apartment (e.g. when gain is embedded
+    /// or started by some launchers), that apartment is used as is, since the audio
+    /// interfaces work in either kind.
+    pub fn new() -> Result<Self> {
+        unsafe {
+            let result = CoInitializeEx(None, COINIT_MULTITHREADED);
+            // S_FALSE means COM was already initialized the same way, and still has to be
+            // balanced by CoUninitialize
+            let com = if result == RPC_E_CHANGED_MODE {
+                debug!("COM is already initialized in another apartment, using it");
+                ComGuard { initialized: false }
+            } else if let Err(e) = result.ok() {
+                error!("Failed to initialize COM: {}", e);
+                return Err(e.into());
+            } else {
+                ComGuard { initialized: true }
+            };
+            Ok(WasapiBackend {
+                state: AudioState::new()?,
+                _com: com,
+            })
+        }
+    }
+}
+
+impl VolumeBackend for WasapiBackend {
+    fn set_master(&mut self, volume: f64) -> Result<()> {
+        unsafe {
+            self.state.with_endpoint(eRender, |endpoint_vol| {
+                endpoint_vol.SetMute(volume <= 0.0, std::ptr::null())?;
+                endpoint_vol.SetMasterVolumeLevelScalar(volume as f32, std::ptr::null())?;
+                trace!("Set master volume to {}", volume);
+                Ok(())
+            })
+        }
+    }
+
+    fn set_master_mute(&mut self, mute: bool) -> Result<()> {
+        unsafe {
+            self.state.with_endpoint(eRender, |endpoint_vol| {
+                endpoint_vol.SetMute(mute, std::ptr::null())?;
+                trace!("Set master mute to {}", mute);
+                Ok(())
+            })
+        }
+    }
+
+    fn set_microphone(&mut self, volume: f64) -> Result<()> {
+        unsafe {
+            self.state.with_endpoint(eCapture, |endpoint_vol| {
+                endpoint_vol.SetMute(volume <= 0.0, std::ptr::null())?;
+                endpoint_vol.SetMasterVolumeLevelScalar(volume as f32, std::ptr::null())?;
+                trace!("Set microphone volume to {}", volume);
+                Ok(())
+            })
+        }
+    }
+
+    fn set_microphone_mute(&mut self, mute: bool) -> Result<()> {
+        unsafe {
+            self.state.with_endpoint(eCapture, |endpoint_vol| {
+                endpoint_vol.SetMute(mute, std::ptr::null())?;
+                trace!("Set microphone mute to {}", mute);
+                Ok(())
+            })
+        }
+    }
+
+    fn set_current_app(&mut self, volume: f64) -> Result<()> {
+        unsafe {
+            self.state.for_each_current_app_session(|pid, simple_vol| {
+                set_volume(simple_vol, volume)?;
+                trace!("Set focused app (PID {}) volume to {}", pid, volume);
+                Ok(())
+            })
+        }
+    }
+
+    fn set_current_app_mute(&mut self, mute: bool) -> Result<()> {
+        unsafe {
+            self.state.for_each_current_app_session(|pid, simple_vol| {
+                set_mute(simple_vol, mute)?;
+                trace!("Set focused app (PID {}) mute to {}", pid, mute);
+                Ok(())
+            })
+        }
+    }
+
+    fn set_app(&mut self, app: &AppMatcher, volume: f64) -> Result<()> {
+        unsafe {
+            self.state.for_each_app_session(app, |name, simple_vol| {
+                set_volume(simple_vol, volume)?;
+                trace!("Set {} volume to {}", name, volume);
+                Ok(())
+            })
+        }
+    }
+
+    fn set_app_mute(&mut self, app: &AppMatcher, mute: bool) -> Result<()> {
+        unsafe {
+            self.state.for_each_app_session(app, |name, simple_vol| {
+                set_mute(simple_vol, mute)?;
+                trace!("Set {} mute to {}", name, mute);
+                Ok(())
+            })
+        }
+    }
+
+    fn set_unmapped(&mut self, volume: f64, unmapped: &UnmappedApps) -> Result<()> {
+        unsafe {
+            self.state
+                .for_each_unmapped_session(unmapped, |name, simple_vol| {
+                    set_volume(simple_vol, volume)?;
+                    trace!("Set unmapped app {} volume to {}", name, volume);
+                    Ok(())
+                })
+        }
+    }
+
+    fn set_unmapped_mute(&mut self, mute: bool, unmapped: &UnmappedApps) -> Result<()> {
+        unsafe {
+            self.state
+                .for_each_unmapped_session(unmapped, |name, simple_vol| {
+                    set_mute(simple_vol, mute)?;
+                    trace!("Set unmapped app {} mute to {}", name, mute);
+                    Ok(())
+                })
+        }
+    }
+
+    fn set_system_sounds(&mut self, volume: f64) -> Result<()> {
+        unsafe {
+            self.state.with_system_sounds_session(|simple_vol| {
+                set_volume(simple_vol, volume)?;
+                trace!("Set system sounds volume to {}", volume);
+                Ok(())
+            })
+        }
+    }
+
+    fn set_system_sounds_mute(&mut self, mute: bool) -> Result<()> {
+        unsafe {
+            self.state.with_system_sounds_session(|simple_vol| {
+                set_mute(simple_vol, mute)?;
+                trace!("Set system sounds mute to {}", mute);
+                Ok(())
+            })
+        }
+    }
+
+    fn set_device(&mut self, device: &str, volume: f64) -> Result<()> {
+        unsafe {
+            self.state.with_device(device, |endpoint_vol| {
+                endpoint_vol.SetMute(volume <= 0.0, std::ptr::null())?;
+                endpoint_vol.SetMasterVolumeLevelScalar(volume as f32, std::ptr::null())?;
+                trace!("Set device {} volume to {}", device, volume);
+                Ok(())
+            })
+        }
+    }
+
+    fn set_device_mute(&mut self, device: &str, mute: bool) -> Result<()> {
+        unsafe {
+            self.state.with_device(device, |endpoint_vol| {
+                endpoint_vol.SetMute(mute, std::ptr::null())?;
+                trace!("Set device {} mute to {}", device, mute);
+                Ok(())
+            })
+        }
+    }
+
+    fn get_master(&mut self) -> Result<f64> {
+        unsafe {
+            self.state.with_endpoint(eRender, |endpoint_vol| {
+                Ok(endpoint_vol.GetMasterVolumeLevelScalar()? as f64)
+            })
+        }
+    }
+
+    fn get_microphone(&mut self) -> Result<f64> {
+        unsafe {
+            self.state.with_endpoint(eCapture, |endpoint_vol| {
+                Ok(endpoint_vol.GetMasterVolumeLevelScalar()? as f64)
+            })
+        }
+    }
+
+    fn get_master_mute(&mut self) -> Result<bool> {
+        unsafe {
+            self.state.with_endpoint(eRender, |endpoint_vol| {
+                Ok(endpoint_vol.GetMute()?.as_bool())
+            })
+        }
+    }
+
+    fn get_microphone_mute(&mut self) -> Result<bool> {
+        unsafe {
+            self.state.with_endpoint(eCapture, |endpoint_vol| {
+                Ok(endpoint_vol.GetMute()?.as_bool())
+            })
+        }
+    }
+
+    fn get_current_app(&mut self) -> Result<Option<f64>> {
+        let mut level = None;
+        unsafe {
+            self.state.for_each_current_app_session(|_, simple_vol| {
+                if level.is_none() {
+                    level = Some(simple_vol.GetMasterVolume()? as f64);
+                }
+                Ok(())
+            })?;
+        }
+        Ok(level)
+    }
+
+    fn get_app(&mut self, app: &AppMatcher) -> Result<Option<f64>> {
+        let mut level = None;
+        unsafe {
+            self.state.for_each_app_session(app, |_, simple_vol| {
+                if level.is_none() {
+                    level = Some(simple_vol.GetMasterVolume()? as f64);
+                }
+                Ok(())
+            })?;
+        }
+        Ok(level)
+    }
+
+    fn get_unmapped(&mut self, unmapped: &UnmappedApps) -> Result<Option<f64>> {
+        let mut level = None;
+        unsafe {
+            self.state
+                .for_each_unmapped_session(unmapped, |_, simple_vol| {
+                    if level.is_none() {
+                        level = Some(simple_vol.GetMasterVolume()? as f64);
+                    }
+                    Ok(())
+                })?;
+        }
+        Ok(level)
+    }
+
+    fn get_system_sounds(&mut self) -> Result<Option<f64>> {
+        let mut level = None;
+        unsafe {
+            self.state.with_system_sounds_session(|simple_vol| {
+                level = Some(simple_vol.GetMasterVolume()? as f64);
+                Ok(())
+            })?;
+        }
+        Ok(level)
+    }
+
+    fn get_device(&mut self, device: &str) -> Result<f64> {
+        unsafe {
+            self.state.with_device(device, |endpoint_vol| {
+                Ok(endpoint_vol.GetMasterVolumeLevelScalar()? as f64)
+            })
+        }
+    }
+
+    fn get_device_mute(&mut self, device: &str) -> Result<bool> {
+        unsafe {
+            self.state
+                .with_device(device, |endpoint_vol| Ok(endpoint_vol.GetMute()?.as_bool()))
+        }
+    }
+
+    fn devices(&mut self) -> Result<Vec<String>> {
+        let devices = unsafe { self.state.render_devices()? };
+        Ok(devices.into_iter().map(|(name, _)| name).collect())
+    }
+
+    fn focused_pid(&mut self) -> Result<Option<u32>> {
+        Ok(unsafe { foreground_pid() })
+    }
+
+    fn focused_title(&mut self) -> Result<Option<String>> {
+        Ok(unsafe { foreground_title() })
+    }
+
+    fn sessions(&mut self) -> Result<Vec<AudioSession>> {
+        let mut sessions = Vec::new();
+        unsafe {
+            self.state
+                .with_session_enumerator(|session_enum, count, processes| {
+                    for i in 0..count {
+                        let Ok(control) = session_enum.GetSession(i) else {
+                            continue;
+                        };
+                        // The system sounds session isn't owned by a single process
+                        let pid = control
+                            .cast::<IAudioSessionControl2>()
+                            .and_then(|control2| control2.GetProcessId())
+                            .unwrap_or(0);
+                        sessions.push(AudioSession {
+                            pid,
+                            name: processes.name(pid, Instant::now(), |pid| get_process_name(pid)),
+                        });
+                    }
+                    Ok(())
+                })?;
+        }
+        Ok(sessions)
+    }
+}
+
+unsafe fn set_volume(sav: ISimpleAudioVolume, volume: f64) -> Result<()> {
+    let volume = volume.clamp(0.0, 1.0);
+    unsafe { sav.SetMute(volume <= 0.0, std::ptr::null())? }
+    unsafe { sav.SetMasterVolume(volume as f32, std::ptr::null())? }
+    Ok(())
+}
+
+unsafe fn set_mute(sav: ISimpleAudioVolume, mute: bool) -> Result<()> {
+    unsafe { sav.SetMute(mute, std::ptr::null())? }
+    Ok(())
+}
+
+/// COM interfaces kept between volume changes, so a slider event doesn't have to recreate
+/// the device enumerator and re-activate the default endpoints every time.
+///
+/// Endpoint interfaces are activated lazily and dropped when Windows reports a new default
+/// device, or when a call on them fails, which is what happens once their device goes away
+/// (e.g. headphones are unplugged). The next call then activates them again on the new
+/// default device.
+struct AudioState {
+    enumerator: IMMDeviceEnumerator,
+    watcher: IMMNotificationClient,
+    changes: Arc<DefaultDeviceChanges>,
+    render: Option<RenderEndpoint>,
+    capture: Option<IAudioEndpointVolume>,
+    /// Endpoint volumes of the output devices used by device targets, keyed by the name in
+    /// the configuration.
+    devices: HashMap<String, IAudioEndpointVolume>,
+    /// Names and paths of the processes that own audio sessions.
+    processes: ProcessCache,
+}
+
+/// Interfaces activated on the default render (output) device.
+#[derive(Clone)]
+struct RenderEndpoint {
+    volume: IAudioEndpointVolume,
+    sessions: IAudioSessionManager2,
+}
+
+impl AudioState {
+    unsafe fn new() -> Result<Self> {
+        unsafe {
+            let enumerator: IMMDeviceEnumerator =
+                CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)?;
+
+            let changes = Arc::new(DefaultDeviceChanges::default());
+            let watcher: IMMNotificationClient = DeviceWatcher {
+                changes: changes.clone(),
+            }
+            .into();
+            // Failed calls still rebuild the endpoints, so this is not fatal
+            if let Err(e) = enumerator.RegisterEndpointNotificationCallback(&watcher) {
+                warn!("Failed to watch for default audio device changes: {}", e);
+            }
+
+            Ok(AudioState {
+                enumerator,
+                watcher,
+                changes,
+                render: None,
+                capture: None,
+                devices: HashMap::new(),
+                processes: ProcessCache::default(),
+            })
+        }
+    }
+
+    /// Returns the cached render endpoint, activating it on the current default output
+    /// device if needed.
+    unsafe fn render(&mut self) -> Result<RenderEndpoint> {
+        if self.changes.take(eRender) {
+            debug!("Default output device changed, reactivating its endpoint");
+            self.render = None;
+        }
+
+        if let Some(render) = &self.render {
+            return Ok(render.clone());
+        }
+
+        unsafe {
+            let device = self
+                .enumerator
+                .GetDefaultAudioEndpoint(eRender, eConsole)
+                .context("No default audio endpoint")?;
+            let render = RenderEndpoint {
+                volume: device.Activate(CLSCTX_ALL, None)?,
+                sessions: device.Activate(CLSCTX_ALL, None)?,
+            };
+            self.render = Some(render.clone());
+            Ok(render)
+        }
+    }
+
+    /// Returns the cached capture endpoint volume, activating it on the current default
+    /// input device if needed.
+    unsafe fn capture(&mut self) -> Result<IAudioEndpointVolume> {
+        if self.changes.take(eCapture) {
+            debug!("Default input device changed, reactivating its endpoint");
+            self.capture = None;
+        }
+
+        if let Some(capture) = &self.capture {
+            return Ok(capture.clone());
+        }
+
+        unsafe {
+            let device = self
+                .enumerator
+                .GetDefaultAudioEndpoint(eCapture, eConsole)
+                .context("No default capture endpoint")?;
+            let capture: IAudioEndpointVolume = device.Activate(CLSCTX_ALL, None)?;
+            self.capture = Some(capture.clone());
+            Ok(capture)
+        }
+    }
+
+    /// Lists every active output device with its friendly name.
+    unsafe fn render_devices(&self) -> Result<Vec<(String, IMMDevice)>> {
+        unsafe {
+            let collection = self
+                .enumerator
+                .EnumAudioEndpoints(eRender, DEVICE_STATE_ACTIVE)?;
+            let mut devices = Vec::new();
+            for i in 0..collection.GetCount()? {
+                let device = collection.Item(i)?;
+                let name = device
+                    .OpenPropertyStore(STGM_READ)?
+                    .GetValue(&PKEY_Device_FriendlyName)?
+                    .to_string();
+                devices.push((name, device));
+            }
+            Ok(devices)
+        }
+    }
+
+    /// Returns the cached endpoint volume of the output device named `name`, activating it
+    /// if needed.
+    unsafe fn device(&mut self, name: &str) -> Result<IAudioEndpointVolume> {
+        if let Some(volume) = self.devices.get(name) {
+            return Ok(volume.clone());
+        }
+
+        unsafe {
+            let device = self
+                .render_devices()?
+                .into_iter()
+                .find(|(device_name, _)| is_device(device_name, name))
+                .map(|(_, device)| device)
+                .ok_or_else(|| anyhow!("No output device named {}", name))?;
+            let volume: IAudioEndpointVolume = device.Activate(CLSCTX_ALL, None)?;
+            self.devices.insert(name.to_string(), volume.clone());
+            Ok(volume)
+        }
+    }
+
+    /// Calls `callback` with the volume control of the output device named `name`. If it
+    /// fails, the device's cached interface is dropped and the call is made once more, so
+    /// a device that was unplugged and plugged back in is picked up again.
+    unsafe fn with_device<T, F>(&mut self, name: &str, mut callback: F) -> Result<T>
+    where
+        F: FnMut(&IAudioEndpointVolume) -> Result<T>,
+    {
+        let mut attempt = |state: &mut Self| unsafe { callback(&state.device(name)?) };
+        attempt(self).or_else(|e| {
+            debug!(
+                "Audio call on device {} failed, reactivating it: {}",
+                name, e
+            );
+            self.devices.remove(name);
+            attempt(self)
+        })
+    }
+
+    /// Runs `operation`, and if it fails, drops the cached interfaces for `flow` and runs it
+    /// once more against freshly activated ones.
+    fn retrying<T, F>(&mut self, flow: EDataFlow, mut operation: F) -> Result<T>
+    where
+        F: FnMut(&mut Self) -> Result<T>,
+    {
+        operation(self).or_else(|e| {
+            debug!("Audio call failed, rebuilding cached endpoint: {}", e);
+            if flow == eRender {
+                self.render = None;
+            } else {
+                self.capture = None;
+            }
+            operation(self)
+        })
+    }
+
+    /// Calls `callback` with the volume control of the default render (output) or capture
+    /// (input) endpoint.
+    unsafe fn with_endpoint<T, F>(&mut self, flow: EDataFlow, mut callback: F) -> Result<T>
+    where
+        F: FnMut(&IAudioEndpointVolume) -> Result<T>,
+    {
+        self.retrying(flow, |state| unsafe {
+            let endpoint_vol = if flow == eRender {
+                state.render()?.volume
+            } else {
+                state.capture()?
+            };
+            callback(&endpoint_vol)
+        })
+    }
+
+    /// Calls `callback` with a fresh enumerator over the default output device's audio
+    /// sessions, since sessions come and go as applications start and stop playing, and the
+    /// cache to look up the processes that own them in.
+    unsafe fn with_session_enumerator<F>(&mut self, mut callback: F) -> Result<()>
+    where
+        F: FnMut(&IAudioSessionEnumerator, i32, &mut ProcessCache) -> Result<()>,
+    {
+        unsafe {
+            let session_enum = self.retrying(eRender, |state| {
+                Ok(state.render()?.sessions.GetSessionEnumerator()?)
+            })?;
+            let count = session_enum.GetCount()?;
+            callback(&session_enum, count, &mut self.processes)
+        }
+    }
+
+    /// Calls `action` with the PID and volume control of every audio session that belongs to
+    /// the foreground window's process.
+    unsafe fn for_each_current_app_session<F>(&mut self, mut action: F) -> Result<()>
+    where
+        F: FnMut(u32, ISimpleAudioVolume) -> Result<()>,
+    {
+        unsafe {
+            let Some(pid) = foreground_pid() else {
+                debug!("No foreground window to set the volume of");
+                return Ok(());
+            };
+
+            let mut found = false;
+            self.with_session_enumerator(|session_enum, count, _| {
+                for i in 0..count {
+                    let mut process_session = || -> Result<()> {
+                        let control = session_enum.GetSession(i)?;
+                        let control2 = control.cast::<IAudioSessionControl2>()?;
+                        let session_pid = control2.GetProcessId()?;
+
+                        if session_pid == pid {
+                            found = true;
+                            action(pid, control.cast::<ISimpleAudioVolume>()?)?;
+                        }
+                        Ok(())
+                    };
+
+                    if let Err(e) = process_session() {
+                        log_skipped_session(i, &e);
+                    }
+                }
+                Ok(())
+            })?;
+            if !found {
+                debug!("Focused app (PID {}) has no audio session", pid);
+            }
+            Ok(())
+        }
+    }
+
+    /// Calls `action` with the volume control of the system sounds session. Windows only
+    /// creates that session once a sound has been played on the device, so until then
+    /// nothing is done.
+    unsafe fn with_system_sounds_session<F>(&mut self, mut action: F) -> Result<()>
+    where
+        F: FnMut(ISimpleAudioVolume) -> Result<()>,
+    {
+        unsafe {
+            let mut found = false;
+            self.with_session_enumerator(|session_enum, count, _| {
+                for i in 0..count {
+                    let mut process_session = || -> Result<()> {
+                        let control = session_enum.GetSession(i)?;
+                        let control2 = control.cast::<IAudioSessionControl2>()?;
+                        // S_FALSE for every other session
+                        if !found && control2.IsSystemSoundsSession() == S_OK {
+                            found = true;
+                            action(control.cast::<ISimpleAudioVolume>()?)?;
+                        }
+                        Ok(())
+                    };
+
+                    if let Err(e) = process_session() {
+                        log_skipped_session(i, &e);
+                    }
+                }
+                Ok(())
+            })?;
+            if !found {
+                debug!("No system sounds session yet");
+            }
+            Ok(())
+        }
+    }
+
+    /// Calls `action` with the process name and volume control of every audio session whose
+    /// process name (or executable path, for path patterns) is matched by `app`.
+    unsafe fn for_each_app_session<F>(&mut self, app: &AppMatcher, mut action: F) -> Result<()>
+    where
+        F: FnMut(&str, ISimpleAudioVolume) -> Result<()>,
+    {
+        unsafe {
+            self.with_session_enumerator(|session_enum, count, processes| {
+                for i in 0..count {
+                    let mut process_session = || -> Result<()> {
+                        let control = session_enum.GetSession(i)?;
+                        let control2 = control.cast::<IAudioSessionControl2>()?;
+                        let pid = control2.GetProcessId()?;
+
+                        let now = Instant::now();
+                        let name = processes
+                            .name(pid, now, |pid| get_process_name(pid))
+                            .ok_or_else(|| anyhow!("Process name not found"))?;
+                        let path = app
+                            .uses_path()
+                            .then(|| processes.path(pid, now, |pid| get_process_path(pid)))
+                            .flatten();
+
+                        if app.matches_process(&name, path.as_deref()) {
+                            action(&name, control.cast::<ISimpleAudioVolume>()?)?;
+                        }
+                        Ok(())
+                    };
+
+                    if let Err(e) = process_session() {
+                        log_skipped_session(i, &e);
+                    }
+                }
+                Ok(())
+            })
+        }
+    }
+
+    /// Calls `action` with the process name and volume control of every audio session whose
+    /// process name is in the `unmapped` set.
+    unsafe fn for_each_unmapped_session<F>(
+        &mut self,
+        unmapped: &UnmappedApps,
+        mut action: F,
+    ) -> Result<()>
+    where
+        F: FnMut(&str, ISimpleAudioVolume) -> Result<()>,
+    {
+        unsafe {
+            self.with_session_enumerator(|session_enum, count, processes| {
+                for i in 0..count {
+                    let mut process_session = || -> Result<()> {
+                        let control = session_enum.GetSession(i)?;
+                        let control2 = control.cast::<IAudioSessionControl2>()?;
+                        let pid = control2.GetProcessId()?;
+
+                        let now = Instant::now();
+                        let name = processes
+                            .name(pid, now, |pid| get_process_name(pid))
+                            .ok_or_else(|| anyhow!("Process name not found"))?;
+                        let path = unmapped
+                            .uses_path()
+                            .then(|| processes.path(pid, now, |pid| get_process_path(pid)))
+                            .flatten();
+
+                        if unmapped.matches_process(&name, path.as_deref()) {
+                            action(&name, control.cast::<ISimpleAudioVolume>()?)?;
+                        }
+                        Ok(())
+                    };
+
+                    if let Err(e) = process_session() {
+                        log_skipped_session(i, &e);
+                    }
+                }
+                Ok(())
+            })
+        }
+    }
+}
+
+/// Logs why audio session `index` was skipped. Audio client errors, such as a session
+/// that expired between being enumerated and being set, are logged at debug level; the
+/// others, mostly system sound sessions that have no process name, only at trace level.
+fn log_skipped_session(index: i32, e: &anyhow::Error) {
+    // Facility 0x889 with the error bit set, i.e. AUDCLNT_E_*
+    let audio_client = e
+        .downcast_ref::<windows::core::Error>()
+        .is_some_and(|e| e.code().0 as u32 & 0xFFFF_0000 == 0x8889_0000);
+    if audio_client {
+        debug!("Skipped audio session {}, audio client error: {}", index, e);
+    } else {
+        trace!("Skipped audio session {}: {}", index, e);
+    }
+}
+
+impl Drop for AudioState {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = self
+                .enumerator
+                .UnregisterEndpointNotificationCallback(&self.watcher);
+        }
+    }
+}
+
+/// Default endpoints that changed since [`AudioState`] last looked. Set from the thread
+/// Windows delivers notifications on, so the flags are atomic.
+#[derive(Default)]
+struct DefaultDeviceChanges {
+    render: AtomicBool,
+    capture: AtomicBool,
+}
+
+impl DefaultDeviceChanges {
+    fn flag(&self, flow: EDataFlow) -> Option<&AtomicBool> {
+        if flow == eRender {
+            Some(&self.render)
+        } else if flow == eCapture {
+            Some(&self.capture)
+        } else {
+            None
+        }
+    }
+
+    /// Returns whether the default device for `flow` changed, clearing the flag.
+    fn take(&self, flow: EDataFlow) -> bool {
+        self.flag(flow)
+            .is_some_and(|flag| flag.swap(false, Ordering::AcqRel))
+    }
+}
+
+/// Endpoint notification callback that flags default device changes for [`AudioState`].
+#[implement(IMMNotificationClient)]
+struct DeviceWatcher {
+    changes: Arc<DefaultDeviceChanges>,
+}
+
+impl IMMNotificationClient_Impl for DeviceWatcher_Impl {
+    fn OnDeviceStateChanged(&self, _device_id: &PCWSTR, _state: DEVICE_STATE) -> WindowsResult<()> {
+        Ok(())
+    }
+
+    fn OnDeviceAdded(&self, _device_id: &PCWSTR) -> WindowsResult<()> {
+        Ok(())
+    }
+
+    fn OnDeviceRemoved(&self, _device_id: &PCWSTR) -> WindowsResult<()> {
+        Ok(())
+    }
+
+    fn OnDefaultDeviceChanged(
+        &self,
+        flow: EDataFlow,
+        role: ERole,
+        _device_id: &PCWSTR,
+    ) -> WindowsResult<()> {
+        // Only the console role is ever used by the backend
+        if role == eConsole
+            && let Some(flag) = self.changes.flag(flow)
+        {
+            flag.store(true, Ordering::Release);
+        }
+        Ok(())
+    }
+
+    fn OnPropertyValueChanged(&self, _device_id: &PCWSTR, _key: &PROPERTYKEY) -> WindowsResult<()> {
+        Ok(())
+    }
+}
+
+/// Returns the ID of the process that owns the foreground window, if there is one.
+unsafe fn foreground_pid() -> Option<u32> {
+    unsafe {
+        let hwnd = GetForegroundWindow();
+        if hwnd.0.is_null() {
+            return None;
+        }
+
+        let mut pid: u32 = 0;
+        GetWindowThreadProcessId(hwnd, Some(&mut pid));
+        (pid != 0).then_some(pid)
+    }
+}
+
+/// Returns the title of the foreground window, if there is one and it has a title.
+unsafe fn foreground_title() -> Option<String> {
+    unsafe {
+        let hwnd = GetForegroundWindow();
+        if hwnd.0.is_null() {
+            return None;
+        }
+
+        let len = GetWindowTextLengthW(hwnd);
+        if len <= 0 {
+            return None;
+        }
+        let mut buffer = vec![0u16; len as usize + 1];
+        let copied = GetWindowTextW(hwnd, &mut buffer);
+        if copied <= 0 {
+            return None;
+        }
+
+        Some(String::from_utf16_lossy(&buffer[..copied as usize]))
+    }
+}
+
+unsafe fn get_process_name(process_id: u32) -> Option<String> {
+    if process_id == 0 {
+        return None;
+    }
+
+    unsafe {
+        let handle = OpenProcess(
+            PROCESS_QUERY_INFORMATION | PROCESS_VM_READ,
+            false,
+            process_id,
+        )
+        .ok()?;
+
+        if handle.is_invalid() {
+            return None;
+        }
+
+        let mut buffer = [0u16; MAX_PATH as usize];
+        let result = K32GetModuleBaseNameW(handle, None, &mut buffer);
+        let _ = CloseHandle(handle);
+
+        if result == 0 {
+            return None;
+        }
+
+        let len = result as usize;
+        let name = OsString::from_wide(&buffer[0..len])
+            .to_string_lossy()
+            .into_owned();
+
+        Some(name)
+    }
+}
+
+/// Returns the full path of the executable of the process `process_id`, for app patterns
+/// that name a path.
+unsafe fn get_process_path(process_id: u32) -> Option<String> {
+    if process_id == 0 {
+        return None;
+    }
+
+    unsafe {
+        let handle = OpenProcess(
+            PROCESS_QUERY_INFORMATION | PROCESS_VM_READ,
+            false,
+            process_id,
+        )
+        .ok()?;
+
+        if handle.is_invalid() {
+            return None;
+        }
+
+        let mut buffer = [0u16; MAX_PATH as usize];
+        let result = K32GetModuleFileNameExW(handle, None, &mut buffer);
+        let _ = CloseHandle(handle);
+
+        if result == 0 {
+            return None;
+        }
+
+        let len = result as usize;
+        let path = OsString::from_wide(&buffer[0..len])
+            .to_string_lossy()
+            .into_owned();
+
+        Some(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use windows::Win32::Media::Audio::eMultimedia;
+
+    fn watcher() -> (IMMNotificationClient, Arc<DefaultDeviceChanges>) {
+        let changes = Arc::new(DefaultDeviceChanges::default());
+        let watcher = DeviceWatcher {
+            changes: changes.clone(),
+        }
+        .into();
+        (watcher, changes)
+    }
+
+    #[test]
+    fn default_device_change_invalidates_endpoint_once() {
+        let (watcher, changes) = watcher();
+
+        unsafe {
+            watcher
+                .OnDefaultDeviceChanged(eRender, eConsole, PCWSTR::null())
+                .unwrap();
+        }
+
+        assert!(!changes.take(eCapture));
+        assert!(changes.take(eRender));
+        // The next event reuses the endpoint activated on the new default
+        assert!(!changes.take(eRender));
+    }
+
+    #[test]
+    fn other_roles_are_ignored() {
+        let (watcher, changes) = watcher();
+
+        unsafe {
+            watcher
+                .OnDefaultDeviceChanged(eCapture, eMultimedia, PCWSTR::null())
+                .unwrap();
+        }
+
+        assert!(!changes.take(eCapture));
+    }
+}
@@ -1,9 +1,347 @@
+//! Wire protocol shared by the gain firmware and desktop application.
+//!
+//! Every frame carries one [`Message`] (firmware to host) or [`HostMessage`] (host to
+//! firmware), serialized with postcard, followed by a CRC-8 and COBS-encoded with a `0x00`
+//! terminator. [`encode_frame`] and [`decode_frame`] are the only place this format is
+//! spelled out, so any host or firmware that uses them stays compatible with both.
+
 #![no_std]
 
+mod potentiometer;
+
+use crc::{CRC_8_SMBUS, Crc};
+use postcard::{
+    Error,
+    ser_flavors::{Cobs, Slice, crc::CrcModifier},
+};
 use serde::{Deserialize, Serialize};
 
+pub use potentiometer::Potentiometer;
+
+/// Version of the wire protocol, exchanged in [`Hello`] frames. It is bumped whenever a
+/// change makes frames from one side undecodable by older builds of the other, such as a
+/// new message variant. Version 2 added pin settings, motorized faders, pings and levels.
+pub const PROTOCOL_VERSION: u8 = 2;
+
+/// Largest serialized size of a [`Message`] or [`HostMessage`] before COBS encoding: one
+/// byte for the variant tag plus the largest payload, a [`RawReading`] (one byte of id and
+/// up to three bytes for each of its two varint-encoded values), followed by one byte of
+/// checksum.
+const MAX_MESSAGE_LEN: usize = 1 + 1 + 3 + 3 + 1;
+
+/// Size of a buffer that can always hold a COBS-encoded [`Message`] or [`HostMessage`],
+/// including the trailing `0x00` frame delimiter.
+pub const MAX_FRAME_LEN: usize = MAX_MESSAGE_LEN + MAX_MESSAGE_LEN / 254 + 2;
+
+/// Checksum appended to every serialized message, so corrupted frames can be told apart
+/// from valid ones.
+static FRAME_CRC: Crc<u8> = Crc::<u8>::new(&CRC_8_SMBUS);
+
+/// Serializes `message` into `buf` as a frame: the postcard encoding of the message and
+/// its CRC-8, COBS-encoded and terminated by `0x00`. Returns the used part of `buf`.
+///
+/// Both directions use the same framing: the firmware sends [`Message`]s and the host sends
+/// [`HostMessage`]s.
+pub fn encode_frame<'a, T>(message: &T, buf: &'a mut [u8]) -> postcard::Result<&'a mut [u8]>
+where
+    T: Serialize,
+{
+    let flavor = CrcModifier::new(Cobs::try_new(Slice::new(buf))?, FRAME_CRC.digest());
+    postcard::serialize_with_flavor(message, flavor)
+}
+
+/// Decodes a frame produced by [`encode_frame`], without its `0x00` terminator. `frame` is
+/// decoded in place. Fails with [`Error::DeserializeBadCrc`] if the frame was corrupted.
+pub fn decode_frame<'a, T>(frame: &'a mut [u8]) -> postcard::Result<T>
+where
+    T: Deserialize<'a>,
+{
+    let len = cobs::decode_in_place(frame).map_err(|_| Error::DeserializeBadEncoding)?;
+    postcard::de_flavors::crc::from_bytes_u8(&frame[..len], FRAME_CRC.digest())
+}
+
+/// A single frame sent from the firmware to the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Message {
+    /// A slider moved to a new position.
+    Slider(Slider),
+    /// A button was pressed or released.
+    Button(Button),
+    /// Sent periodically so the host can tell an idle device from a dead one.
+    Heartbeat,
+    /// A rotary encoder in place of a slider was turned.
+    Encoder(Encoder),
+    /// A slider's unfiltered reading, sent on every sample by firmware built with
+    /// `DEBUG_RAW`.
+    Raw(RawReading),
+    /// The firmware's protocol version, sent on boot and in reply to the host's
+    /// [`HostMessage::Hello`].
+    Hello(Hello),
+    /// The settings a pin was left with, sent in reply to the host's
+    /// [`HostMessage::PinConfig`].
+    PinConfig(PinConfig),
+    /// Reply to the host's [`HostMessage::Ping`], with its token.
+    Pong(Ping),
+}
+
+/// A single frame sent from the host to the firmware.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HostMessage {
+    /// Turns the LED of a slider on or off.
+    Led(LedState),
+    /// The host's protocol version, sent on connect so firmware that didn't reset when the
+    /// port was opened still announces its own.
+    Hello(Hello),
+    /// Moves a motorized fader to a position set on the host, such as from the system mixer.
+    Fader(FaderPosition),
+    /// Changes the settings of a slider's pin. The firmware stores them in EEPROM, so they
+    /// outlive a power cycle.
+    PinConfig(PinConfig),
+    /// Asks the firmware to answer with a [`Message::Pong`], so the host can tell that the
+    /// device on a port runs the gain firmware and is alive.
+    Ping(Ping),
+    /// The volume of a slider's target, for firmware that shows it on an LED strip.
+    Level(LevelState),
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Slider {
     pub id: u8,
     pub value: u16,
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Button {
+    pub id: u8,
+    pub pressed: bool,
+}
+
+/// Ticks a rotary encoder was turned by since its last message, positive clockwise. The
+/// host moves the volume of the slider with the same id by one `volume_step` per tick.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Encoder {
+    pub id: u8,
+    pub delta: i16,
+}
+
+/// A slider's raw ADC reading next to the filter's smoothed value for it, before
+/// hysteresis and edge clamping.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RawReading {
+    pub id: u8,
+    pub raw: u16,
+    pub smoothed: u16,
+}
+
+/// Protocol version announced by either side when a connection starts. A side that receives
+/// a version other than its own [`PROTOCOL_VERSION`] can't rely on decoding the other's
+/// frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hello {
+    pub version: u8,
+}
+
+/// Token of a ping, echoed in its pong so each pong can be matched to its ping.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Ping {
+    pub token: u8,
+}
+
+/// LED state of the slider with the same id. The host lights it while the slider's target
+/// is muted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LedState {
+    pub id: u8,
+    pub on: bool,
+}
+
+/// Volume level of the slider with the same id's target, from 0 (silent or muted) to 255
+/// (full volume).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LevelState {
+    pub id: u8,
+    pub level: u8,
+}
+
+/// Position (0 to 1023, like [`Slider::value`]) that the motorized fader of the slider with
+/// the same id should be moved to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FaderPosition {
+    pub id: u8,
+    pub value: u16,
+}
+
+/// Largest hysteresis a pin can be given, in raw ADC steps. Anything more would swallow too
+/// much of a slider's travel to be useful.
+pub const MAX_THRESHOLD: i16 = 255;
+
+/// Bytes one pin's [`PinSettings`] take in the firmware's EEPROM. Pin `i`'s settings are
+/// stored at offset `i * PIN_SETTINGS_LEN`: a byte that is 1 if the pin is read and 0 if
+/// not, then the threshold as a little-endian `i16`.
+pub const PIN_SETTINGS_LEN: usize = 3;
+
+/// How the firmware reads one potentiometer pin, set from the host with
+/// [`HostMessage::PinConfig`] in place of the defaults the firmware is built with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PinSettings {
+    /// Whether the pin is read at all. Disabled pins send nothing.
+    pub enabled: bool,
+    /// Hysteresis of the pin's filter, see [`Potentiometer`].
+    pub threshold: i16,
+}
+
+impl PinSettings {
+    /// Returns whether the firmware can use these settings, i.e. the threshold is between 0
+    /// and [`MAX_THRESHOLD`].
+    pub fn is_valid(&self) -> bool {
+        (0..=MAX_THRESHOLD).contains(&self.threshold)
+    }
+
+    /// Returns the settings as they are stored in EEPROM.
+    pub fn to_bytes(self) -> [u8; PIN_SETTINGS_LEN] {
+        let [low, high] = self.threshold.to_le_bytes();
+        [self.enabled as u8, low, high]
+    }
+
+    /// Reads settings stored by [`PinSettings::to_bytes`]. Returns `None` for bytes that
+    /// aren't valid settings, such as erased EEPROM (which reads as `0xFF`), so pins that
+    /// were never configured keep the firmware's defaults.
+    pub fn from_bytes([enabled, low, high]: [u8; PIN_SETTINGS_LEN]) -> Option<Self> {
+        let settings = PinSettings {
+            enabled: match enabled {
+                0 => false,
+                1 => true,
+                _ => return None,
+            },
+            threshold: i16::from_le_bytes([low, high]),
+        };
+        settings.is_valid().then_some(settings)
+    }
+}
+
+/// Settings of the pin of the slider with the same id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PinConfig {
+    pub id: u8,
+    pub settings: PinSettings,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Encodes `message` and strips the frame terminator, like the host's reader does.
+    fn frame<T: Serialize>(message: &T, buf: &mut [u8; MAX_FRAME_LEN]) -> usize {
+        let frame = encode_frame(message, buf).unwrap();
+        assert_eq!(frame.last(), Some(&0x00));
+        frame.len() - 1
+    }
+
+    #[test]
+    fn frames_round_trip() {
+        let message = Message::Slider(Slider { id: 3, value: 1023 });
+        let mut buf = [0; MAX_FRAME_LEN];
+        let len = frame(&message, &mut buf);
+        assert_eq!(decode_frame(&mut buf[..len]), Ok(message));
+    }
+
+    #[test]
+    fn encoder_frames_round_trip() {
+        let message = Message::Encoder(Encoder {
+            id: 1,
+            delta: i16::MIN,
+        });
+        let mut buf = [0; MAX_FRAME_LEN];
+        let len = frame(&message, &mut buf);
+        assert_eq!(decode_frame(&mut buf[..len]), Ok(message));
+    }
+
+    #[test]
+    fn hello_frames_round_trip() {
+        let message = Message::Hello(Hello {
+            version: PROTOCOL_VERSION,
+        });
+        let mut buf = [0; MAX_FRAME_LEN];
+        let len = frame(&message, &mut buf);
+        assert_eq!(decode_frame(&mut buf[..len]), Ok(message));
+    }
+
+    #[test]
+    fn largest_frames_fit() {
+        let message = Message::Raw(RawReading {
+            id: u8::MAX,
+            raw: u16::MAX,
+            smoothed: u16::MAX,
+        });
+        let mut buf = [0; MAX_FRAME_LEN];
+        let len = frame(&message, &mut buf);
+        assert_eq!(decode_frame(&mut buf[..len]), Ok(message));
+    }
+
+    #[test]
+    fn host_frames_round_trip() {
+        let message = HostMessage::Led(LedState { id: 5, on: true });
+        let mut buf = [0; MAX_FRAME_LEN];
+        let len = frame(&message, &mut buf);
+        assert_eq!(decode_frame(&mut buf[..len]), Ok(message));
+    }
+
+    #[test]
+    fn level_frames_round_trip() {
+        let message = HostMessage::Level(LevelState {
+            id: u8::MAX,
+            level: u8::MAX,
+        });
+        let mut buf = [0; MAX_FRAME_LEN];
+        let len = frame(&message, &mut buf);
+        assert_eq!(decode_frame(&mut buf[..len]), Ok(message));
+    }
+
+    #[test]
+    fn pin_config_frames_round_trip() {
+        let message = HostMessage::PinConfig(PinConfig {
+            id: u8::MAX,
+            settings: PinSettings {
+                enabled: true,
+                threshold: i16::MIN,
+            },
+        });
+        let mut buf = [0; MAX_FRAME_LEN];
+        let len = frame(&message, &mut buf);
+        assert_eq!(decode_frame(&mut buf[..len]), Ok(message));
+    }
+
+    #[test]
+    fn pin_settings_round_trip_through_eeprom() {
+        let settings = PinSettings {
+            enabled: false,
+            threshold: MAX_THRESHOLD,
+        };
+        assert_eq!(PinSettings::from_bytes(settings.to_bytes()), Some(settings));
+    }
+
+    #[test]
+    fn invalid_eeprom_settings_are_ignored() {
+        // Erased EEPROM
+        assert_eq!(PinSettings::from_bytes([0xFF; PIN_SETTINGS_LEN]), None);
+        // Negative and oversized thresholds
+        assert_eq!(PinSettings::from_bytes([1, 0xFF, 0xFF]), None);
+        assert_eq!(PinSettings::from_bytes([1, 0x00, 0x01]), None);
+    }
+
+    #[test]
+    fn corrupted_frames_are_rejected() {
+        let message = Message::Button(Button {
+            id: 2,
+            pressed: true,
+        });
+        let mut buf = [0; MAX_FRAME_LEN];
+        let len = frame(&message, &mut buf);
+        // Flip a bit of the button id, which still decodes to a valid message
+        buf[2] ^= 0x01;
+        assert_eq!(
+            decode_frame::<Message>(&mut buf[..len]),
+            Err(Error::DeserializeBadCrc)
+        );
+    }
+}
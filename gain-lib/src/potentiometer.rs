@@ -0,0 +1,188 @@
+/// Readings below this are reported as 0, so a slider pushed all the way down reliably
+/// reaches the bottom despite ADC noise.
+const LOW_EDGE: u16 = 5;
+/// Readings above this are reported as 1023, so a slider pushed all the way up reliably
+/// reaches the top.
+const HIGH_EDGE: u16 = 1018;
+
+/// Noise filter for one potentiometer, run by the firmware on every raw ADC reading
+/// (0 to 1023).
+///
+/// Readings are smoothed by an exponential moving average that halves the weight of older
+/// samples on every update. The reported value only follows the smoothed one once it moves
+/// more than `threshold` away (hysteresis), and is snapped to the ends of travel near them.
+///
+/// With an idle reset, see [`Potentiometer::set_idle_reset`], the first movement after the
+/// slider rested for long enough seeds the average again instead of being averaged with the
+/// resting position.
+#[derive(Debug, Clone, Copy)]
+pub struct Potentiometer {
+    /// Twice the smoothed reading, kept doubled so the halving doesn't lose precision.
+    accumulator: u32,
+    /// Whether a reading has been seen yet. The first one seeds the average directly.
+    initialized: bool,
+    last_stable_val: u16,
+    threshold: i16,
+    /// Readings in a row within `threshold` of the average after which the slider counts as
+    /// idle, or 0 to never reset the average.
+    idle_reset: u16,
+    idle_samples: u16,
+}
+
+impl Potentiometer {
+    pub fn new(threshold: i16) -> Self {
+        Self {
+            accumulator: 0,
+            initialized: false,
+            last_stable_val: 0,
+            threshold,
+            idle_reset: 0,
+            idle_samples: 0,
+        }
+    }
+
+    /// Makes the first reading that moves past the threshold after `samples` readings in a
+    /// row that didn't seed the average, as the very first reading does. 0 turns this off.
+    pub fn set_idle_reset(&mut self, samples: u16) {
+        self.idle_reset = samples;
+        self.idle_samples = 0;
+    }
+
+    /// Changes the hysteresis, keeping the readings seen so far.
+    pub fn set_threshold(&mut self, threshold: i16) {
+        self.threshold = threshold;
+    }
+
+    /// Returns the moving average of the readings so far, before hysteresis and clamping.
+    pub fn smoothed(&self) -> u16 {
+        (self.accumulator >> 1) as u16
+    }
+
+    /// Feeds a raw reading through the filter and returns the value to report.
+    pub fn update(&mut self, raw_input: u16) -> u16 {
+        // An idle slider that starts moving seeds the filter again
+        let moved = (raw_input as i16 - self.smoothed() as i16).abs() > self.threshold;
+        let idle = self.idle_reset > 0 && self.idle_samples >= self.idle_reset;
+        self.idle_samples = if moved {
+            0
+        } else {
+            self.idle_samples.saturating_add(1)
+        };
+
+        // EMA Filter
+        if !self.initialized || (idle && moved) {
+            self.accumulator = (raw_input as u32) << 1;
+            self.initialized = true;
+        } else {
+            self.accumulator = self.accumulator - (self.accumulator >> 1) + raw_input as u32;
+        }
+
+        let smoothed_raw = self.smoothed();
+
+        // Hysteresis
+        let diff = (smoothed_raw as i16 - self.last_stable_val as i16).abs();
+
+        if diff > self.threshold {
+            self.last_stable_val = smoothed_raw;
+        }
+
+        // Edge Clamping
+        if self.last_stable_val > HIGH_EDGE {
+            1023
+        } else if self.last_stable_val < LOW_EDGE {
+            0
+        } else {
+            self.last_stable_val
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The threshold the firmware ships with.
+    const THRESHOLD: i16 = 4;
+
+    /// Feeds `readings` through `pot`, returning the last reported value.
+    fn feed(pot: &mut Potentiometer, readings: impl IntoIterator<Item = u16>) -> u16 {
+        readings
+            .into_iter()
+            .fold(0, |_, reading| pot.update(reading))
+    }
+
+    #[test]
+    fn constant_input_converges_to_it() {
+        let mut pot = Potentiometer::new(THRESHOLD);
+        assert_eq!(feed(&mut pot, [500; 20]), 500);
+    }
+
+    #[test]
+    fn step_input_settles_within_ten_samples() {
+        let mut pot = Potentiometer::new(THRESHOLD);
+        feed(&mut pot, [500; 20]);
+        let settled = feed(&mut pot, [800; 10]);
+        assert!(
+            settled.abs_diff(800) <= THRESHOLD as u16,
+            "settled at {settled}"
+        );
+        // And stays put once there
+        assert_eq!(feed(&mut pot, [800; 10]), settled);
+    }
+
+    #[test]
+    fn jitter_under_the_threshold_is_ignored() {
+        let mut pot = Potentiometer::new(THRESHOLD);
+        feed(&mut pot, [500; 20]);
+        for reading in [503, 497, 502, 498, 504, 496].into_iter().cycle().take(60) {
+            assert_eq!(pot.update(reading), 500);
+        }
+    }
+
+    #[test]
+    fn rise_from_a_zero_start_is_smoothed() {
+        let mut pot = Potentiometer::new(THRESHOLD);
+        assert_eq!(feed(&mut pot, [0; 5]), 0);
+
+        // Zeros are real readings, so the first rise is averaged with them rather than
+        // reseeding the filter
+        let mut last = 0;
+        for _ in 0..10 {
+            let value = pot.update(800);
+            assert!(value >= last && value < 800, "jumped to {value}");
+            last = value;
+        }
+        assert!(last.abs_diff(800) <= THRESHOLD as u16, "settled at {last}");
+    }
+
+    #[test]
+    fn first_movement_after_idle_reseeds_the_average() {
+        let mut pot = Potentiometer::new(THRESHOLD);
+        pot.set_idle_reset(10);
+        feed(&mut pot, [500; 20]);
+        assert_eq!(pot.update(800), 800);
+        // Moving on keeps smoothing, since the slider isn't idle anymore
+        let value = pot.update(600);
+        assert!(value > 600 && value < 800, "jumped to {value}");
+
+        // Too short a rest is still averaged with the new reading
+        let mut pot = Potentiometer::new(THRESHOLD);
+        pot.set_idle_reset(10);
+        feed(&mut pot, [500; 5]);
+        assert!(pot.update(800) < 800);
+
+        // And so is every movement without an idle reset
+        let mut pot = Potentiometer::new(THRESHOLD);
+        feed(&mut pot, [500; 20]);
+        assert!(pot.update(800) < 800);
+    }
+
+    #[test]
+    fn readings_near_the_ends_are_clamped() {
+        let mut pot = Potentiometer::new(THRESHOLD);
+        assert_eq!(feed(&mut pot, [3; 20]), 0);
+
+        let mut pot = Potentiometer::new(THRESHOLD);
+        assert_eq!(feed(&mut pot, [1020; 20]), 1023);
+    }
+}